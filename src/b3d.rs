@@ -0,0 +1,14 @@
+//! Blitz3D (`.b3d`) support.
+//!
+//! B3D is minetest's most battle-tested animated model format.
+//!
+//! **Note**: Data is read and written as is. If the model should be in minetest space,
+//! load it with a [CoordinateConversion](crate::coordinates::CoordinateConversion) first.
+
+/// Reads B3D chunk trees into models.
+mod reader;
+/// Writes models into B3D chunk trees.
+mod writer;
+
+pub use reader::*;
+pub use writer::*;
@@ -0,0 +1,519 @@
+use std::error::Error;
+
+use ahash::AHashMap;
+use glam::{Mat4, Quat, Vec2, Vec3};
+
+use crate::{
+  animation::{finalize_bone_animations, BoneAnimationChannel},
+  minetest_gltf::MinetestGLTF,
+  primitive::{Mode, Vertex},
+  skeleton::{Skeleton, SkeletonNode},
+  Model, Primitive,
+};
+
+///
+/// Reads little endian values out of a B3D chunk.
+///
+struct Cursor<'a> {
+  bytes: &'a [u8],
+  position: usize,
+}
+
+impl<'a> Cursor<'a> {
+  fn new(bytes: &'a [u8]) -> Self {
+    Cursor { bytes, position: 0 }
+  }
+
+  fn is_empty(&self) -> bool {
+    self.position >= self.bytes.len()
+  }
+
+  fn take(&mut self, amount: usize) -> Result<&'a [u8], String> {
+    if self.position + amount > self.bytes.len() {
+      return Err(format!(
+        "B3D: Unexpected end of chunk. Wanted {} bytes at {} of {}.",
+        amount,
+        self.position,
+        self.bytes.len()
+      ));
+    }
+    let taken = &self.bytes[self.position..self.position + amount];
+    self.position += amount;
+    Ok(taken)
+  }
+
+  fn int(&mut self) -> Result<i32, String> {
+    let mut raw = [0; 4];
+    raw.copy_from_slice(self.take(4)?);
+    Ok(i32::from_le_bytes(raw))
+  }
+
+  fn float(&mut self) -> Result<f32, String> {
+    let mut raw = [0; 4];
+    raw.copy_from_slice(self.take(4)?);
+    Ok(f32::from_le_bytes(raw))
+  }
+
+  fn vec3(&mut self) -> Result<Vec3, String> {
+    Ok(Vec3::new(self.float()?, self.float()?, self.float()?))
+  }
+
+  fn quat(&mut self) -> Result<Quat, String> {
+    // B3D stores w first.
+    let w = self.float()?;
+    let x = self.float()?;
+    let y = self.float()?;
+    let z = self.float()?;
+    Ok(Quat::from_xyzw(x, y, z, w))
+  }
+
+  fn string(&mut self) -> Result<String, String> {
+    let remaining = &self.bytes[self.position.min(self.bytes.len())..];
+    let end = match remaining.iter().position(|byte| *byte == 0) {
+      Some(end) => end,
+      None => return Err("B3D: Unterminated string.".into()),
+    };
+    let string = String::from_utf8_lossy(&remaining[..end]).into_owned();
+    self.position += end + 1;
+    Ok(string)
+  }
+
+  ///
+  /// Read the next chunk header. Returns the tag and a cursor over the chunk body.
+  ///
+  fn chunk(&mut self) -> Result<([u8; 4], Cursor<'a>), String> {
+    let mut tag = [0; 4];
+    tag.copy_from_slice(self.take(4)?);
+    let length = self.int()?;
+    if length < 0 {
+      return Err("B3D: Negative chunk length.".into());
+    }
+    Ok((tag, Cursor::new(self.take(length as usize)?)))
+  }
+}
+
+///
+/// Everything collected while walking the B3D chunk tree.
+///
+#[derive(Default)]
+struct B3DData {
+  nodes: Vec<SkeletonNode>,
+  node_names: Vec<Option<String>>,
+  globals: Vec<Mat4>,
+  primitives: Vec<Primitive>,
+  // Global vertex id of each vertex of each primitive.
+  primitive_vertex_ids: Vec<Vec<usize>>,
+  // The vertex data in VRTS order.
+  positions: Vec<Vec3>,
+  normals: Vec<Vec3>,
+  tex_coords: Vec<Vec2>,
+  colors: Vec<[f32; 4]>,
+  has_normals: bool,
+  has_tex_coords: bool,
+  has_colors: bool,
+  // Where the vertices of the current MESH start. BONE vertex ids are relative to this.
+  vertex_base: usize,
+  // (node id, global vertex id, weight)
+  bone_weights: Vec<(i32, usize, f32)>,
+  // Raw keys. Timestamps are frame numbers until the end.
+  channels: AHashMap<i32, BoneAnimationChannel>,
+  fps: f32,
+}
+
+///
+/// Read B3D bytes into the same output as a glTF load.
+///
+/// Each `TRIS` chunk becomes its own primitive, like the mesh buffers of minetest.
+/// `BONE` and `KEYS` chunks become the skeleton and bone animations.
+///
+pub fn read_b3d(bytes: &[u8], path: &str) -> Result<MinetestGLTF, Box<dyn Error + Send + Sync>> {
+  let mut cursor = Cursor::new(bytes);
+  let (tag, mut body) = cursor.chunk()?;
+  if &tag != b"BB3D" {
+    return Err(format!("B3D: Missing BB3D header. {}", path).into());
+  }
+
+  let version = body.int()?;
+  if version / 100 > 0 {
+    return Err(format!("B3D: Unsupported version {}. {}", version, path).into());
+  }
+
+  let mut data = B3DData {
+    fps: 60.0,
+    ..Default::default()
+  };
+
+  while !body.is_empty() {
+    let (tag, chunk) = body.chunk()?;
+    // TEXS and BRUS don't matter, primitives are in TRIS order.
+    if &tag == b"NODE" {
+      read_node(&mut data, chunk, None)?;
+    }
+  }
+
+  if data.primitives.is_empty() {
+    return Err(format!("B3D: Model has no primitives! {}", path).into());
+  }
+
+  let mut minetest_gltf = MinetestGLTF::new(vec![], path);
+  finalize_skin(&mut data);
+
+  #[cfg(feature = "names")]
+  {
+    minetest_gltf.set_node_names(data.node_names.clone());
+  }
+
+  let is_skinned = !data.bone_weights.is_empty();
+  if is_skinned {
+    let skeleton = build_skeleton(&data);
+
+    if !data.channels.is_empty() {
+      let bone_animations = finish_channels(&data, &skeleton);
+      finalize_bone_animations(&mut minetest_gltf, bone_animations)?;
+    }
+
+    minetest_gltf.skeleton = Some(skeleton);
+  }
+
+  minetest_gltf.model = Some(Model::from_primitives(data.primitives));
+
+  Ok(minetest_gltf)
+}
+
+///
+/// Read a NODE chunk and everything underneath it.
+///
+fn read_node(data: &mut B3DData, mut chunk: Cursor, parent: Option<i32>) -> Result<(), String> {
+  let name = chunk.string()?;
+  let translation = chunk.vec3()?;
+  let scale = chunk.vec3()?;
+  let rotation = chunk.quat()?.normalize();
+
+  let id = data.nodes.len() as i32;
+  data.nodes.push(SkeletonNode {
+    #[cfg(feature = "names")]
+    name: Some(name.clone()),
+    id,
+    parent,
+    children: vec![],
+    translation,
+    rotation,
+    scale,
+  });
+  data.node_names.push(Some(name));
+
+  let local = Mat4::from_scale_rotation_translation(scale, rotation, translation);
+  let global = match parent {
+    Some(parent) => {
+      data.nodes[parent as usize].children.push(id);
+      data.globals[parent as usize] * local
+    }
+    None => local,
+  };
+  data.globals.push(global);
+
+  while !chunk.is_empty() {
+    let (tag, sub_chunk) = chunk.chunk()?;
+    match &tag {
+      b"MESH" => read_mesh(data, sub_chunk, global)?,
+      b"BONE" => read_bone(data, sub_chunk, id)?,
+      b"KEYS" => read_keys(data, sub_chunk, id)?,
+      b"ANIM" => read_anim(data, sub_chunk)?,
+      b"NODE" => read_node(data, sub_chunk, Some(id))?,
+      _ => {}
+    }
+  }
+
+  Ok(())
+}
+
+///
+/// Read a MESH chunk. Vertices are baked into the space of the model, like glTF primitives.
+///
+fn read_mesh(data: &mut B3DData, mut chunk: Cursor, transform: Mat4) -> Result<(), String> {
+  // Brush id. Not needed.
+  chunk.int()?;
+
+  data.vertex_base = data.positions.len();
+
+  while !chunk.is_empty() {
+    let (tag, sub_chunk) = chunk.chunk()?;
+    match &tag {
+      b"VRTS" => read_vertices(data, sub_chunk, transform)?,
+      b"TRIS" => read_triangles(data, sub_chunk)?,
+      _ => {}
+    }
+  }
+
+  Ok(())
+}
+
+fn read_vertices(data: &mut B3DData, mut chunk: Cursor, transform: Mat4) -> Result<(), String> {
+  let flags = chunk.int()?;
+  let tex_coord_sets = chunk.int()?.max(0) as usize;
+  let tex_coord_set_size = chunk.int()?.max(0) as usize;
+
+  let has_normals = flags & 1 != 0;
+  let has_colors = flags & 2 != 0;
+  data.has_normals |= has_normals;
+  data.has_colors |= has_colors;
+  data.has_tex_coords |= tex_coord_sets > 0 && tex_coord_set_size >= 2;
+
+  while !chunk.is_empty() {
+    let position = chunk.vec3()?;
+    data.positions.push(transform.transform_point3(position));
+
+    let normal = if has_normals {
+      chunk.vec3()?
+    } else {
+      Vec3::ZERO
+    };
+    data
+      .normals
+      .push(transform.transform_vector3(normal).normalize_or_zero());
+
+    let mut color = [1.0; 4];
+    if has_colors {
+      for channel in &mut color {
+        *channel = chunk.float()?;
+      }
+    }
+    data.colors.push(color);
+
+    let mut tex_coords = Vec2::ZERO;
+    for set in 0..tex_coord_sets {
+      for component in 0..tex_coord_set_size {
+        let value = chunk.float()?;
+        if set == 0 && component < 2 {
+          tex_coords[component] = value;
+        }
+      }
+    }
+    data.tex_coords.push(tex_coords);
+  }
+
+  Ok(())
+}
+
+fn read_triangles(data: &mut B3DData, mut chunk: Cursor) -> Result<(), String> {
+  // Every TRIS is its own primitive anyways. -1 means no brush.
+  let brush = chunk.int()?;
+
+  let mut vertex_ids = vec![];
+  let mut remap: AHashMap<usize, u32> = AHashMap::new();
+  let mut indices = vec![];
+
+  while !chunk.is_empty() {
+    let vertex_id = data.vertex_base + chunk.int()?.max(0) as usize;
+    if vertex_id >= data.positions.len() {
+      return Err(format!(
+        "B3D: Triangle vertex {} is out of range.",
+        vertex_id
+      ));
+    }
+    let index = *remap.entry(vertex_id).or_insert_with(|| {
+      vertex_ids.push(vertex_id);
+      (vertex_ids.len() - 1) as u32
+    });
+    indices.push(index);
+  }
+
+  let vertices = vertex_ids
+    .iter()
+    .map(|id| Vertex {
+      position: data.positions[*id],
+      normal: data.normals[*id],
+      tex_coords: data.tex_coords[*id],
+      ..Default::default()
+    })
+    .collect();
+  let colors = if data.has_colors {
+    vertex_ids.iter().map(|id| data.colors[*id]).collect()
+  } else {
+    vec![]
+  };
+
+  data.primitives.push(Primitive {
+    primitive_index: data.primitives.len(),
+    vertices,
+    indices: Some(indices),
+    mode: Mode::Triangles,
+    has_normals: data.has_normals,
+    has_tex_coords: data.has_tex_coords,
+    has_colors: data.has_colors,
+    colors,
+    material: usize::try_from(brush).ok(),
+    ..Default::default()
+  });
+  data.primitive_vertex_ids.push(vertex_ids);
+
+  Ok(())
+}
+
+fn read_bone(data: &mut B3DData, mut chunk: Cursor, id: i32) -> Result<(), String> {
+  while !chunk.is_empty() {
+    let vertex = data.vertex_base + chunk.int()?.max(0) as usize;
+    let weight = chunk.float()?;
+    if weight > 0.0 {
+      data.bone_weights.push((id, vertex, weight));
+    }
+  }
+  Ok(())
+}
+
+fn read_keys(data: &mut B3DData, mut chunk: Cursor, id: i32) -> Result<(), String> {
+  let flags = chunk.int()?;
+  let channel = data.channels.entry(id).or_default();
+
+  while !chunk.is_empty() {
+    let frame = chunk.int()? as f32;
+    if flags & 1 != 0 {
+      channel.translation_timestamps.push(frame);
+      channel.translations.push(chunk.vec3()?);
+    }
+    if flags & 2 != 0 {
+      channel.scale_timestamps.push(frame);
+      channel.scales.push(chunk.vec3()?);
+    }
+    if flags & 4 != 0 {
+      channel.rotation_timestamps.push(frame);
+      channel.rotations.push(chunk.quat()?.normalize());
+    }
+  }
+  Ok(())
+}
+
+fn read_anim(data: &mut B3DData, mut chunk: Cursor) -> Result<(), String> {
+  // Flags and frame count. The keys have what we need.
+  chunk.int()?;
+  chunk.int()?;
+  let fps = chunk.float()?;
+  if fps > 0.0 {
+    data.fps = fps;
+  }
+  Ok(())
+}
+
+///
+/// Give every primitive vertex its 4 strongest joints and weights.
+///
+fn finalize_skin(data: &mut B3DData) {
+  if data.bone_weights.is_empty() {
+    return;
+  }
+
+  let joints = joint_ids(data);
+  let joint_index: AHashMap<i32, u16> = joints
+    .iter()
+    .enumerate()
+    .map(|(index, id)| (*id, index as u16))
+    .collect();
+
+  let mut influences: Vec<Vec<(u16, f32)>> = vec![vec![]; data.positions.len()];
+  for (id, vertex, weight) in &data.bone_weights {
+    if let (Some(influence), Some(joint)) = (influences.get_mut(*vertex), joint_index.get(id)) {
+      influence.push((*joint, *weight));
+    }
+  }
+
+  for influence in &mut influences {
+    influence.sort_by(|a, b| b.1.total_cmp(&a.1));
+    influence.truncate(4);
+  }
+
+  for (primitive, vertex_ids) in data.primitives.iter_mut().zip(&data.primitive_vertex_ids) {
+    primitive.joints.clear();
+    primitive.weights.clear();
+    for vertex_id in vertex_ids {
+      let mut joints = [0; 4];
+      let mut weights = [0.0; 4];
+      for (slot, (joint, weight)) in influences[*vertex_id].iter().enumerate() {
+        joints[slot] = *joint;
+        weights[slot] = *weight;
+      }
+      // Weights have to add up to 1.
+      let total: f32 = weights.iter().sum();
+      if total > 0.0 {
+        for weight in &mut weights {
+          *weight /= total;
+        }
+      }
+      primitive.joints.push(joints);
+      primitive.weights.push(weights);
+    }
+    primitive.has_joints = true;
+    primitive.has_weights = true;
+  }
+}
+
+///
+/// The node ids that have BONE weights, in the order they showed up.
+///
+fn joint_ids(data: &B3DData) -> Vec<i32> {
+  let mut joints: Vec<i32> = vec![];
+  for (id, _, _) in &data.bone_weights {
+    if !joints.contains(id) {
+      joints.push(*id);
+    }
+  }
+  joints
+}
+
+fn build_skeleton(data: &B3DData) -> Skeleton {
+  let joints = joint_ids(data);
+  let inverse_bind_matrices = joints
+    .iter()
+    .map(|id| data.globals[*id as usize].inverse())
+    .collect();
+  Skeleton {
+    nodes: data.nodes.clone(),
+    joints,
+    inverse_bind_matrices,
+  }
+}
+
+///
+/// Turn the frame numbers into seconds and fill in the tracks that have no keys with the rest pose.
+///
+fn finish_channels(data: &B3DData, skeleton: &Skeleton) -> AHashMap<i32, BoneAnimationChannel> {
+  // B3D frames start at 1.
+  let to_time = |frame: &f32| (frame - 1.0).max(0.0) / data.fps;
+
+  data
+    .channels
+    .iter()
+    .map(|(id, raw)| {
+      let mut channel = BoneAnimationChannel {
+        translation_timestamps: raw.translation_timestamps.iter().map(to_time).collect(),
+        translations: raw.translations.clone(),
+        rotation_timestamps: raw.rotation_timestamps.iter().map(to_time).collect(),
+        rotations: raw.rotations.clone(),
+        scale_timestamps: raw.scale_timestamps.iter().map(to_time).collect(),
+        scales: raw.scales.clone(),
+        ..Default::default()
+      };
+
+      #[cfg(feature = "names")]
+      {
+        channel.name = data.node_names[*id as usize].clone();
+      }
+
+      if let Some(node) = skeleton.node(*id) {
+        if channel.translations.is_empty() {
+          channel.translation_timestamps.push(0.0);
+          channel.translations.push(node.translation);
+        }
+        if channel.rotations.is_empty() {
+          channel.rotation_timestamps.push(0.0);
+          channel.rotations.push(node.rotation);
+        }
+        if channel.scales.is_empty() {
+          channel.scale_timestamps.push(0.0);
+          channel.scales.push(node.scale);
+        }
+      }
+
+      (*id, channel)
+    })
+    .collect()
+}
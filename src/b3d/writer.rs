@@ -0,0 +1,512 @@
+use std::{
+  error::Error,
+  fs::File,
+  io::{BufWriter, Write},
+};
+
+use ahash::AHashMap;
+use glam::{Mat4, Quat, Vec3};
+
+use crate::{
+  animation::{BoneAnimationChannel, BoneTransform, Pose},
+  minetest_gltf::MinetestGLTF,
+  skeleton::Skeleton,
+};
+
+///
+/// Builds up nested B3D chunks. Chunk lengths are patched in when the chunk ends.
+///
+#[derive(Default)]
+struct ChunkWriter {
+  bytes: Vec<u8>,
+  open_chunks: Vec<usize>,
+}
+
+impl ChunkWriter {
+  fn begin(&mut self, tag: &[u8; 4]) {
+    self.bytes.extend_from_slice(tag);
+    self.open_chunks.push(self.bytes.len());
+    // Placeholder length.
+    self.int(0);
+  }
+
+  fn end(&mut self) {
+    if let Some(length_position) = self.open_chunks.pop() {
+      let length = (self.bytes.len() - length_position - 4) as i32;
+      self.bytes[length_position..length_position + 4].copy_from_slice(&length.to_le_bytes());
+    }
+  }
+
+  fn int(&mut self, value: i32) {
+    self.bytes.extend_from_slice(&value.to_le_bytes());
+  }
+
+  fn float(&mut self, value: f32) {
+    self.bytes.extend_from_slice(&value.to_le_bytes());
+  }
+
+  fn vec3(&mut self, value: Vec3) {
+    self.float(value.x);
+    self.float(value.y);
+    self.float(value.z);
+  }
+
+  fn quat(&mut self, value: Quat) {
+    // B3D stores w first.
+    self.float(value.w);
+    self.float(value.x);
+    self.float(value.y);
+    self.float(value.z);
+  }
+
+  fn string(&mut self, value: &str) {
+    self.bytes.extend_from_slice(value.as_bytes());
+    self.bytes.push(0);
+  }
+}
+
+///
+/// Write a loaded model into B3D bytes.
+///
+/// Every primitive becomes its own `TRIS` chunk, so minetest creates one mesh buffer per
+/// primitive. The chunks are sorted by texture slot, so the mesh buffers line up with the
+/// textures given to the entity. Each texture slot becomes a brush. The skeleton nodes become
+/// `NODE` chunks with `BONE` weights and the bone animations become `KEYS`.
+///
+/// B3D has no inverse bind matrices, minetest binds the mesh to the transforms of the `NODE`
+/// chunks. So joints are written in the bind pose of the inverse bind matrices. Skins with a
+/// bind pose that can't be written as a translation, rotation and scale are an error.
+///
+pub fn write_b3d(minetest_gltf: &MinetestGLTF) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+  let model = match &minetest_gltf.model {
+    Some(model) => model,
+    None => return Err("B3D: Model is broken, there is nothing to write.".into()),
+  };
+
+  let mut writer = ChunkWriter::default();
+  writer.begin(b"BB3D");
+  writer.int(1);
+
+  // One brush per texture slot.
+  writer.begin(b"BRUS");
+  writer.int(0);
+  for i in 0..model.texture_slot_count() {
+    writer.string(&format!("brush_{}", i));
+    for _ in 0..4 {
+      writer.float(1.0);
+    }
+    // Shininess, blend, fx.
+    writer.float(0.0);
+    writer.int(1);
+    writer.int(0);
+  }
+  writer.end();
+
+  writer.begin(b"NODE");
+  writer.string("root");
+  writer.vec3(Vec3::ZERO);
+  writer.vec3(Vec3::ONE);
+  writer.quat(Quat::IDENTITY);
+
+  // All the vertices go into one VRTS, so they get an id that is unique in the mesh.
+  let has_normals = model
+    .primitives
+    .iter()
+    .any(|primitive| primitive.has_normals);
+  let has_tex_coords = model
+    .primitives
+    .iter()
+    .any(|primitive| primitive.has_tex_coords);
+
+  // Joint index -> (vertex id, weight).
+  let mut bone_weights: AHashMap<usize, Vec<(i32, f32)>> = AHashMap::new();
+
+  writer.begin(b"MESH");
+  writer.int(-1);
+
+  writer.begin(b"VRTS");
+  writer.int(if has_normals { 1 } else { 0 });
+  writer.int(if has_tex_coords { 1 } else { 0 });
+  writer.int(2);
+  let mut vertex_offset = 0;
+  for primitive in &model.primitives {
+    for (i, vertex) in primitive.vertices.iter().enumerate() {
+      writer.vec3(vertex.position);
+      if has_normals {
+        writer.vec3(vertex.normal);
+      }
+      if has_tex_coords {
+        writer.float(vertex.tex_coords.x);
+        writer.float(vertex.tex_coords.y);
+      }
+
+      if let (Some(joints), Some(weights)) = (primitive.joints.get(i), primitive.weights.get(i)) {
+        for (joint, weight) in joints.iter().zip(weights) {
+          if *weight > 0.0 {
+            bone_weights
+              .entry(*joint as usize)
+              .or_default()
+              .push(((vertex_offset + i) as i32, *weight));
+          }
+        }
+      }
+    }
+    vertex_offset += primitive.vertices.len();
+  }
+  writer.end();
+
+  let vertex_offsets: Vec<usize> = model
+    .primitives
+    .iter()
+    .scan(0, |vertex_offset, primitive| {
+      let offset = *vertex_offset;
+      *vertex_offset += primitive.vertices.len();
+      Some(offset)
+    })
+    .collect();
+  let mut order: Vec<usize> = (0..model.primitives.len()).collect();
+  order.sort_by_key(|i| model.primitives[*i].texture_slot);
+  for i in order {
+    let primitive = &model.primitives[i];
+    // * B3D can only hold triangles.
+    let triangles = match primitive.triangle_indices() {
+      Ok(triangles) => triangles,
+      Err(e) => return Err(format!("B3D: Primitive {} can't be written. {}", i, e).into()),
+    };
+    writer.begin(b"TRIS");
+    writer.int(primitive.texture_slot as i32);
+    for triangle in triangles {
+      for index in triangle {
+        writer.int((vertex_offsets[i] + index as usize) as i32);
+      }
+    }
+    writer.end();
+  }
+
+  // MESH
+  writer.end();
+
+  if let Some(bone_animations) = &minetest_gltf.bone_animations {
+    let (frames, fps) = frame_range(minetest_gltf);
+    writer.begin(b"ANIM");
+    writer.int(0);
+    writer.int(frames);
+    writer.float(fps);
+    writer.end();
+
+    if let Some(skeleton) = &minetest_gltf.skeleton {
+      let bind_pose = bind_pose(skeleton)?;
+      let bone_weights = remap_joint_weights(skeleton, bone_weights);
+      for node in skeleton.nodes.iter().filter(|node| node.parent.is_none()) {
+        write_node(
+          &mut writer,
+          skeleton,
+          node.id,
+          &bind_pose,
+          &bone_weights,
+          Some(bone_animations),
+          fps,
+        );
+      }
+    }
+  } else if let Some(skeleton) = &minetest_gltf.skeleton {
+    let bind_pose = bind_pose(skeleton)?;
+    let bone_weights = remap_joint_weights(skeleton, bone_weights);
+    for node in skeleton.nodes.iter().filter(|node| node.parent.is_none()) {
+      write_node(
+        &mut writer,
+        skeleton,
+        node.id,
+        &bind_pose,
+        &bone_weights,
+        None,
+        1.0,
+      );
+    }
+  }
+
+  // NODE
+  writer.end();
+  // BB3D
+  writer.end();
+
+  Ok(writer.bytes)
+}
+
+///
+/// Write a loaded model into a B3D file.
+///
+pub fn save_b3d(
+  minetest_gltf: &MinetestGLTF,
+  path: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+  let bytes = write_b3d(minetest_gltf)?;
+  let mut file = BufWriter::new(File::create(path)?);
+  file.write_all(&bytes)?;
+  file.flush()?;
+  Ok(())
+}
+
+///
+/// Turn the joint indexed weights into node id indexed weights.
+///
+fn remap_joint_weights(
+  skeleton: &Skeleton,
+  bone_weights: AHashMap<usize, Vec<(i32, f32)>>,
+) -> AHashMap<i32, Vec<(i32, f32)>> {
+  bone_weights
+    .into_iter()
+    .filter_map(|(joint, weights)| Some((*skeleton.joints.get(joint)?, weights)))
+    .collect()
+}
+
+///
+/// Get the local transform every skeleton node is written with. Indexed by node id.
+///
+/// Joints get the bind pose out of their inverse bind matrix, so minetest binds the mesh the
+/// same way the skin does. Other nodes keep their rest transform.
+///
+fn bind_pose(skeleton: &Skeleton) -> Result<Vec<BoneTransform>, Box<dyn Error + Send + Sync>> {
+  let mut bind_globals: Vec<Option<Mat4>> = vec![None; skeleton.nodes.len()];
+  for (joint, inverse_bind) in skeleton.joints.iter().zip(&skeleton.inverse_bind_matrices) {
+    if inverse_bind.determinant().abs() <= f32::EPSILON {
+      return Err(
+        format!(
+          "B3D: Joint {} has an inverse bind matrix that can't be inverted.",
+          joint
+        )
+        .into(),
+      );
+    }
+    if let Some(global) = bind_globals.get_mut(*joint as usize) {
+      *global = Some(inverse_bind.inverse());
+    }
+  }
+
+  let rest_pose = Pose::rest(skeleton);
+  let global = |id: i32| -> Mat4 {
+    bind_globals[id as usize].unwrap_or_else(|| {
+      // Walk up to the nearest joint, and bring the rest transforms down from there.
+      let mut local = Mat4::IDENTITY;
+      let mut current = id;
+      loop {
+        let node = &skeleton.nodes[current as usize];
+        local = rest_pose.get(current).to_matrix() * local;
+        match node.parent {
+          Some(parent) => match bind_globals[parent as usize] {
+            Some(parent_global) => return parent_global * local,
+            None => current = parent,
+          },
+          None => return local,
+        }
+      }
+    })
+  };
+
+  skeleton
+    .nodes
+    .iter()
+    .map(|node| {
+      let rest = rest_pose.get(node.id);
+      if bind_globals[node.id as usize].is_none() {
+        return Ok(rest);
+      }
+      let parent_global = match node.parent {
+        Some(parent) => global(parent),
+        None => Mat4::IDENTITY,
+      };
+      let local = parent_global.inverse() * global(node.id);
+      let tolerance = 0.0001
+        * local
+          .to_cols_array()
+          .iter()
+          .fold(1.0_f32, |largest, value| largest.max(value.abs()));
+      if local.abs_diff_eq(rest.to_matrix(), tolerance) {
+        return Ok(rest);
+      }
+
+      let (scale, rotation, translation) = local.to_scale_rotation_translation();
+      let bind = BoneTransform {
+        translation,
+        rotation,
+        scale,
+      };
+      if !bind.to_matrix().abs_diff_eq(local, tolerance) {
+        return Err(
+          format!(
+            "B3D: The bind pose of node {} is sheared, it can't be written.",
+            node.id
+          )
+          .into(),
+        );
+      }
+      Ok(bind)
+    })
+    .collect()
+}
+
+///
+/// Get the frame count and frames per second of the bone animations.
+///
+/// Bone animations are resampled so every frame is equal distance from eachother.
+///
+fn frame_range(minetest_gltf: &MinetestGLTF) -> (i32, f32) {
+  let mut max_time: f32 = 0.0;
+  let mut min_distance = f32::MAX;
+
+  if let Some(bone_animations) = &minetest_gltf.bone_animations {
+    for channel in bone_animations.values() {
+      for timestamps in [
+        &channel.translation_timestamps,
+        &channel.rotation_timestamps,
+        &channel.scale_timestamps,
+      ] {
+        for pair in timestamps.windows(2) {
+          let distance = pair[1] - pair[0];
+          if distance > 0.0 && distance < min_distance {
+            min_distance = distance;
+          }
+        }
+        if let Some(last) = timestamps.last() {
+          max_time = max_time.max(*last);
+        }
+      }
+    }
+  }
+
+  if min_distance == f32::MAX {
+    return (1, 1.0);
+  }
+
+  let fps = 1.0 / min_distance;
+  ((max_time * fps).round() as i32 + 1, fps)
+}
+
+///
+/// Turn a timestamp into a B3D frame. B3D frames start at 1.
+///
+fn to_frame(time: f32, fps: f32) -> i32 {
+  (time * fps).round() as i32 + 1
+}
+
+///
+/// Write a skeleton node and everything underneath it.
+///
+fn write_node(
+  writer: &mut ChunkWriter,
+  skeleton: &Skeleton,
+  id: i32,
+  bind_pose: &[BoneTransform],
+  bone_weights: &AHashMap<i32, Vec<(i32, f32)>>,
+  bone_animations: Option<&AHashMap<i32, BoneAnimationChannel>>,
+  fps: f32,
+) {
+  let (node, bind) = match (skeleton.node(id), bind_pose.get(id as usize)) {
+    (Some(node), Some(bind)) => (node, bind),
+    _ => return,
+  };
+
+  writer.begin(b"NODE");
+  writer.string(&node_name(skeleton, id));
+  writer.vec3(bind.translation);
+  writer.vec3(bind.scale);
+  writer.quat(bind.rotation);
+
+  if let Some(weights) = bone_weights.get(&id) {
+    writer.begin(b"BONE");
+    for (vertex, weight) in weights {
+      writer.int(*vertex);
+      writer.float(*weight);
+    }
+    writer.end();
+  }
+
+  // Tracks without keys sit at the transform of the NODE, so nodes that are bound away from
+  // their rest pose get a key to hold them at rest.
+  let rest = BoneTransform {
+    translation: node.translation,
+    rotation: node.rotation,
+    scale: node.scale,
+  };
+  let animated = bone_animations.and_then(|bone_animations| bone_animations.get(&id));
+  let held = match bone_animations {
+    Some(_) if *bind != rest => {
+      let mut held = animated.cloned().unwrap_or_default();
+      if held.translations.is_empty() {
+        held.translation_timestamps = vec![0.0];
+        held.translations = vec![rest.translation];
+      }
+      if held.rotations.is_empty() {
+        held.rotation_timestamps = vec![0.0];
+        held.rotations = vec![rest.rotation];
+      }
+      if held.scales.is_empty() {
+        held.scale_timestamps = vec![0.0];
+        held.scales = vec![rest.scale];
+      }
+      Some(held)
+    }
+    _ => None,
+  };
+
+  if let Some(channel) = held.as_ref().or(animated) {
+    if !channel.translations.is_empty() {
+      writer.begin(b"KEYS");
+      writer.int(1);
+      for (time, translation) in channel
+        .translation_timestamps
+        .iter()
+        .zip(&channel.translations)
+      {
+        writer.int(to_frame(*time, fps));
+        writer.vec3(*translation);
+      }
+      writer.end();
+    }
+    if !channel.scales.is_empty() {
+      writer.begin(b"KEYS");
+      writer.int(2);
+      for (time, scale) in channel.scale_timestamps.iter().zip(&channel.scales) {
+        writer.int(to_frame(*time, fps));
+        writer.vec3(*scale);
+      }
+      writer.end();
+    }
+    if !channel.rotations.is_empty() {
+      writer.begin(b"KEYS");
+      writer.int(4);
+      for (time, rotation) in channel.rotation_timestamps.iter().zip(&channel.rotations) {
+        writer.int(to_frame(*time, fps));
+        writer.quat(*rotation);
+      }
+      writer.end();
+    }
+  }
+
+  for child in &node.children {
+    write_node(
+      writer,
+      skeleton,
+      *child,
+      bind_pose,
+      bone_weights,
+      bone_animations,
+      fps,
+    );
+  }
+
+  writer.end();
+}
+
+#[cfg(feature = "names")]
+fn node_name(skeleton: &Skeleton, id: i32) -> String {
+  match skeleton.node(id).and_then(|node| node.name.clone()) {
+    Some(name) => name,
+    None => format!("node_{}", id),
+  }
+}
+
+#[cfg(not(feature = "names"))]
+fn node_name(_skeleton: &Skeleton, id: i32) -> String {
+  format!("node_{}", id)
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+
+/// glTF extensions that get loaded.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+  "EXT_meshopt_compression",
+  "KHR_lights_punctual",
+  "KHR_mesh_quantization",
+  "KHR_texture_transform",
+];
+
+/// glTF extensions that only add things this crate doesn't load. Models that require them still
+/// come out right, just without the extra data.
+pub const IGNORABLE_EXTENSIONS: &[&str] = &[
+  "KHR_materials_clearcoat",
+  "KHR_materials_emissive_strength",
+  "KHR_materials_ior",
+  "KHR_materials_sheen",
+  "KHR_materials_specular",
+  "KHR_materials_transmission",
+  "KHR_materials_unlit",
+  "KHR_materials_variants",
+  "KHR_materials_volume",
+  "KHR_xmp_json_ld",
+];
+
+/// Represents a runtime error. Triggered when a model requires glTF extensions that aren't
+/// supported, so it would load with wrong or missing data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnsupportedExtensions {
+  /// The required extensions that aren't supported.
+  pub extensions: Vec<String>,
+}
+
+impl fmt::Display for UnsupportedExtensions {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "Unsupported required glTF extensions: {}",
+      self.extensions.join(", ")
+    )
+  }
+}
+
+impl std::error::Error for UnsupportedExtensions {}
+
+///
+/// Check the extensions of a document against the ones this crate knows.
+///
+/// Returns the extensions that are used but not loaded.
+///
+pub(crate) fn check_extensions(
+  document: &gltf::Document,
+) -> Result<Vec<String>, UnsupportedExtensions> {
+  let unsupported: Vec<String> = document
+    .extensions_required()
+    .filter(|extension| {
+      !SUPPORTED_EXTENSIONS.contains(extension) && !IGNORABLE_EXTENSIONS.contains(extension)
+    })
+    .map(String::from)
+    .collect();
+  if !unsupported.is_empty() {
+    return Err(UnsupportedExtensions {
+      extensions: unsupported,
+    });
+  }
+
+  Ok(
+    document
+      .extensions_used()
+      .filter(|extension| !SUPPORTED_EXTENSIONS.contains(extension))
+      .map(String::from)
+      .collect(),
+  )
+}
+
+///
+/// Validate a document like the gltf crate does, except for its check of the required extensions.
+///
+/// Newer gltf versions reject every required extension they don't load themselves, which would
+/// include the ones this crate decodes. `check_extensions` covers those instead.
+///
+pub(crate) fn validate(json: &gltf::json::Root) -> Result<(), gltf::Error> {
+  use gltf::json::validation::Validate;
+
+  let mut errors = Vec::new();
+  json.validate(json, gltf::json::Path::new, &mut |path, error| {
+    let path = path();
+    if !path.as_str().starts_with("extensionsRequired") {
+      errors.push((path, error));
+    }
+  });
+  if errors.is_empty() {
+    Ok(())
+  } else {
+    Err(gltf::Error::Validation(errors))
+  }
+}
@@ -0,0 +1,930 @@
+//! glTF 2.0 writer.
+//!
+//! Saves a loaded model back out as `.gltf` (with an embedded or external buffer) or `.glb`.
+//!
+//! **Note**: The loader bakes the node transforms into the vertices, so the meshes go on nodes
+//! without transforms. They are nested so the primitives load back in the same order and with
+//! the same texture slots. Skeleton nodes keep their ids, so the skin joints and the bone
+//! animations still line up. Without a skeleton, the nodes on the way down to the cameras keep
+//! their ids instead, and only their animations are written.
+
+use std::{
+  borrow::Cow,
+  error::Error,
+  fs::File,
+  io::{BufWriter, Write},
+  path::Path,
+};
+
+use base64::Engine;
+use gltf::json::{
+  self,
+  accessor::{ComponentType, GenericComponentType, Type},
+  animation::{Interpolation, Property},
+  buffer::Target,
+  mesh::Semantic,
+  validation::{Checked, USize64},
+  Index,
+};
+
+use ahash::AHashMap;
+
+use crate::{
+  animation::BoneAnimationChannel,
+  camera::{Camera, Projection},
+  minetest_gltf::MinetestGLTF,
+  primitive::{Mode, Primitive},
+  skeleton::Skeleton,
+  Model,
+};
+
+///
+/// How the glTF file stores its buffer.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GltfFormat {
+  /// A `.gltf` file with the buffer embedded as a base64 data uri.
+  #[default]
+  Embedded,
+  /// A `.gltf` file with the buffer in a `.bin` file next to it.
+  Separate,
+  /// A binary `.glb` file.
+  Binary,
+}
+
+///
+/// Builds up the single binary buffer, its views and accessors.
+///
+struct BufferBuilder {
+  root: json::Root,
+  bytes: Vec<u8>,
+}
+
+impl BufferBuilder {
+  ///
+  /// Add raw little endian data as a new buffer view and an accessor pointing at it.
+  ///
+  #[allow(clippy::too_many_arguments)]
+  fn accessor(
+    &mut self,
+    data: &[u8],
+    count: usize,
+    component_type: ComponentType,
+    type_: Type,
+    target: Option<Target>,
+    min: Option<json::Value>,
+    max: Option<json::Value>,
+  ) -> Index<json::Accessor> {
+    // * Every accessor has to start on a multiple of its component size. 4 covers them all.
+    while !self.bytes.len().is_multiple_of(4) {
+      self.bytes.push(0);
+    }
+
+    let view = Index::new(self.root.buffer_views.len() as u32);
+    self.root.buffer_views.push(json::buffer::View {
+      buffer: Index::new(0),
+      byte_length: USize64::from(data.len()),
+      byte_offset: Some(USize64::from(self.bytes.len())),
+      byte_stride: None,
+      name: None,
+      target: target.map(Checked::Valid),
+      extensions: None,
+      extras: Default::default(),
+    });
+    self.bytes.extend_from_slice(data);
+
+    let accessor = Index::new(self.root.accessors.len() as u32);
+    self.root.accessors.push(json::Accessor {
+      buffer_view: Some(view),
+      byte_offset: None,
+      count: USize64::from(count),
+      component_type: Checked::Valid(GenericComponentType(component_type)),
+      extensions: None,
+      extras: Default::default(),
+      type_: Checked::Valid(type_),
+      min,
+      max,
+      name: None,
+      normalized: false,
+      sparse: None,
+    });
+    accessor
+  }
+
+  ///
+  /// Add a list of float vectors. Optionally with min and max, which some accessors require.
+  ///
+  fn floats<const N: usize>(
+    &mut self,
+    values: &[[f32; N]],
+    type_: Type,
+    target: Option<Target>,
+    bounds: bool,
+  ) -> Index<json::Accessor> {
+    let data: Vec<u8> = values
+      .iter()
+      .flatten()
+      .flat_map(|value| value.to_le_bytes())
+      .collect();
+
+    let (min, max) = if bounds {
+      let mut min = [f32::MAX; N];
+      let mut max = [f32::MIN; N];
+      for value in values {
+        for i in 0..N {
+          min[i] = min[i].min(value[i]);
+          max[i] = max[i].max(value[i]);
+        }
+      }
+      (
+        Some(json::Value::from(min.to_vec())),
+        Some(json::Value::from(max.to_vec())),
+      )
+    } else {
+      (None, None)
+    };
+
+    self.accessor(
+      &data,
+      values.len(),
+      ComponentType::F32,
+      type_,
+      target,
+      min,
+      max,
+    )
+  }
+}
+
+///
+/// Write a loaded model into a glTF document and its binary buffer.
+///
+/// The buffer uri is left empty, the caller decides where the buffer goes.
+///
+fn build_document(
+  minetest_gltf: &MinetestGLTF,
+) -> Result<(json::Root, Vec<u8>), Box<dyn Error + Send + Sync>> {
+  let model = match &minetest_gltf.model {
+    Some(model) => model,
+    None => return Err("glTF: Model is broken, there is nothing to write.".into()),
+  };
+
+  let mut builder = BufferBuilder {
+    root: json::Root::default(),
+    bytes: vec![],
+  };
+  builder.root.asset.generator = Some(String::from("minetest-gltf"));
+
+  let skin = minetest_gltf.skeleton.as_ref().map(|skeleton| {
+    write_skeleton(&mut builder, skeleton);
+    let inverse_bind_matrices: Vec<[f32; 16]> = skeleton
+      .inverse_bind_matrices
+      .iter()
+      .map(|matrix| matrix.to_cols_array())
+      .collect();
+    let inverse_bind_matrices = builder.floats(&inverse_bind_matrices, Type::Mat4, None, false);
+
+    builder.root.skins.push(json::Skin {
+      extensions: None,
+      extras: Default::default(),
+      inverse_bind_matrices: Some(inverse_bind_matrices),
+      joints: skeleton
+        .joints
+        .iter()
+        .map(|joint| Index::new(*joint as u32))
+        .collect(),
+      name: None,
+      skeleton: None,
+    });
+    Index::new(0)
+  });
+
+  // * The materials keep their indices, so the primitives that share one still do when read back.
+  let material_count = model
+    .primitives
+    .iter()
+    .filter_map(|primitive| primitive.material)
+    .map(|material| material + 1)
+    .max()
+    .unwrap_or(0)
+    .max(model.materials.len());
+  builder.root.materials = (0..material_count)
+    .map(|i| model.materials.get(i).cloned().unwrap_or_default())
+    .collect();
+
+  let mut scene_nodes: Vec<Index<json::Node>> = vec![];
+  if let Some(skeleton) = &minetest_gltf.skeleton {
+    scene_nodes.extend(
+      skeleton
+        .nodes
+        .iter()
+        .filter(|node| node.parent.is_none())
+        .map(|node| Index::new(node.id as u32)),
+    );
+  } else {
+    scene_nodes.extend(write_camera_paths(&mut builder, &model.cameras));
+  }
+  let camera_tracks = write_cameras(&mut builder, &model.cameras);
+
+  // The mesh nodes go at the end, so the skeleton node ids stay the same.
+  let slots: Vec<usize> = model
+    .primitives
+    .iter()
+    .map(|primitive| primitive.texture_slot)
+    .collect();
+  let mut next_slot = 0;
+  let layout = match mesh_layout(&slots, 0..slots.len(), &mut next_slot) {
+    Some(layout) => layout,
+    None => {
+      // ? Some orders can't be nested into the same slots, so the primitives are sorted by slot.
+      let mut order: Vec<usize> = (0..slots.len()).collect();
+      order.sort_by_key(|i| slots[*i]);
+      vec![MeshNode {
+        primitives: order,
+        children: vec![],
+      }]
+    }
+  };
+  for mesh_node in &layout {
+    scene_nodes.push(write_mesh_node(&mut builder, model, mesh_node, skin));
+  }
+
+  builder.root.scenes.push(json::Scene {
+    extensions: None,
+    extras: Default::default(),
+    name: scene_name(minetest_gltf),
+    nodes: scene_nodes,
+  });
+  builder.root.scene = Some(Index::new(0));
+
+  if let Some(bone_animations) = &minetest_gltf.bone_animations {
+    write_animation(&mut builder, minetest_gltf, bone_animations, &camera_tracks);
+  }
+
+  let BufferBuilder { mut root, bytes } = builder;
+  root.buffers.push(json::Buffer {
+    byte_length: USize64::from(bytes.len()),
+    name: None,
+    uri: None,
+    extensions: None,
+    extras: Default::default(),
+  });
+
+  Ok((root, bytes))
+}
+
+///
+/// A node that holds a mesh, and the nodes nested underneath it.
+///
+struct MeshNode {
+  /// The primitives of the mesh.
+  primitives: Vec<usize>,
+  children: Vec<MeshNode>,
+}
+
+///
+/// Nest the primitives into mesh nodes, so they load back in the same order with the same
+/// texture slots.
+///
+/// The loader reads the meshes of the children before the mesh of their parent, but minetest
+/// sees the parent first, and the texture slots go in the order minetest sees the materials.
+/// So primitives that come before the first use of the slot minetest needs next are nested under
+/// the node of that primitive. `next_slot` is the next slot minetest has to see.
+///
+/// None if the primitives can't be nested into the same slots.
+///
+fn mesh_layout(
+  slots: &[usize],
+  range: std::ops::Range<usize>,
+  next_slot: &mut usize,
+) -> Option<Vec<MeshNode>> {
+  let mut layout = vec![];
+  let mut start = range.start;
+  while start < range.end {
+    // The mesh of the node starts at the first primitive minetest may see next.
+    let first = (start..range.end).find(|i| slots[*i] <= *next_slot)?;
+    let mut end = first;
+    while end < range.end && slots[end] <= *next_slot {
+      if slots[end] == *next_slot {
+        *next_slot += 1;
+      }
+      end += 1;
+    }
+    let children = mesh_layout(slots, start..first, next_slot)?;
+    layout.push(MeshNode {
+      primitives: (first..end).collect(),
+      children,
+    });
+    start = end;
+  }
+  Some(layout)
+}
+
+///
+/// Write a mesh node and its children. Returns the node index.
+///
+fn write_mesh_node(
+  builder: &mut BufferBuilder,
+  model: &Model,
+  mesh_node: &MeshNode,
+  skin: Option<Index<json::Skin>>,
+) -> Index<json::Node> {
+  let children: Vec<Index<json::Node>> = mesh_node
+    .children
+    .iter()
+    .map(|child| write_mesh_node(builder, model, child, skin))
+    .collect();
+
+  let primitives = mesh_node
+    .primitives
+    .iter()
+    .map(|i| write_primitive(builder, &model.primitives[*i], skin.is_some()))
+    .collect();
+  let mesh = Index::new(builder.root.meshes.len() as u32);
+  builder.root.meshes.push(json::Mesh {
+    extensions: None,
+    extras: Default::default(),
+    name: mesh_name(mesh_node.primitives.first().map(|i| &model.primitives[*i])),
+    primitives,
+    weights: None,
+  });
+
+  let node = Index::new(builder.root.nodes.len() as u32);
+  builder.root.nodes.push(json::Node {
+    camera: None,
+    children: if children.is_empty() {
+      None
+    } else {
+      Some(children)
+    },
+    extensions: None,
+    extras: Default::default(),
+    matrix: None,
+    mesh: Some(mesh),
+    name: None,
+    rotation: None,
+    scale: None,
+    translation: None,
+    skin,
+    weights: None,
+  });
+  node
+}
+
+///
+/// Write the nodes on the way down to the cameras, for models without a skeleton. They keep
+/// their ids, the ones in between are left empty.
+///
+/// Returns the root nodes.
+///
+fn write_camera_paths(builder: &mut BufferBuilder, cameras: &[Camera]) -> Vec<Index<json::Node>> {
+  let empty_node = || json::Node {
+    camera: None,
+    children: None,
+    extensions: None,
+    extras: Default::default(),
+    matrix: None,
+    mesh: None,
+    name: None,
+    rotation: None,
+    scale: None,
+    translation: None,
+    skin: None,
+    weights: None,
+  };
+
+  let mut roots = vec![];
+  for camera in cameras {
+    let mut parent: Option<usize> = None;
+    for path_node in &camera.path {
+      let id = path_node.id as usize;
+      if builder.root.nodes.len() <= id {
+        builder.root.nodes.resize_with(id + 1, empty_node);
+      }
+      let node = &mut builder.root.nodes[id];
+      if node.translation.is_none() {
+        let rest = path_node.rest;
+        node.rotation = Some(json::scene::UnitQuaternion(rest.rotation.to_array()));
+        node.scale = Some(rest.scale.to_array());
+        node.translation = Some(rest.translation.to_array());
+        let index = Index::new(id as u32);
+        match parent {
+          Some(parent) => builder.root.nodes[parent]
+            .children
+            .get_or_insert_with(Vec::new)
+            .push(index),
+          None => roots.push(index),
+        }
+      }
+      parent = Some(id);
+    }
+  }
+  roots
+}
+
+///
+/// Write the cameras onto their nodes.
+///
+/// Returns which tracks of the nodes on the way down to the cameras are animated. The rest were
+/// filled in when the animations got finalized, and would override the rest transforms.
+///
+fn write_cameras(builder: &mut BufferBuilder, cameras: &[Camera]) -> AHashMap<i32, [bool; 3]> {
+  let mut tracks = AHashMap::new();
+  for camera in cameras {
+    let (orthographic, perspective, type_) = match camera.projection {
+      Projection::Orthographic { scale } => (
+        Some(json::camera::Orthographic {
+          xmag: scale.x,
+          ymag: scale.y,
+          zfar: camera.zfar,
+          znear: camera.znear,
+          extensions: None,
+          extras: Default::default(),
+        }),
+        None,
+        json::camera::Type::Orthographic,
+      ),
+      Projection::Perspective { yfov, aspect_ratio } => (
+        None,
+        Some(json::camera::Perspective {
+          aspect_ratio,
+          yfov,
+          zfar: Some(camera.zfar).filter(|zfar| zfar.is_finite()),
+          znear: camera.znear,
+          extensions: None,
+          extras: Default::default(),
+        }),
+        json::camera::Type::Perspective,
+      ),
+    };
+    let index = Index::new(builder.root.cameras.len() as u32);
+    builder.root.cameras.push(json::Camera {
+      name: camera_name(camera),
+      orthographic,
+      perspective,
+      type_: Checked::Valid(type_),
+      extensions: None,
+      extras: Default::default(),
+    });
+    if let Some(node) = builder.root.nodes.get_mut(camera.node as usize) {
+      node.camera = Some(index);
+    }
+
+    for path_node in &camera.path {
+      let animated = tracks.entry(path_node.id).or_insert([false; 3]);
+      for (track, path_animated) in animated.iter_mut().zip(path_node.animated) {
+        *track |= path_animated;
+      }
+    }
+  }
+  tracks
+}
+
+///
+/// Write every skeleton node, in id order, so the ids are kept.
+///
+fn write_skeleton(builder: &mut BufferBuilder, skeleton: &Skeleton) {
+  for node in &skeleton.nodes {
+    builder.root.nodes.push(json::Node {
+      camera: None,
+      children: if node.children.is_empty() {
+        None
+      } else {
+        Some(
+          node
+            .children
+            .iter()
+            .map(|child| Index::new(*child as u32))
+            .collect(),
+        )
+      },
+      extensions: None,
+      extras: Default::default(),
+      matrix: None,
+      mesh: None,
+      #[cfg(feature = "names")]
+      name: node.name.clone(),
+      #[cfg(not(feature = "names"))]
+      name: None,
+      rotation: Some(json::scene::UnitQuaternion(node.rotation.to_array())),
+      scale: Some(node.scale.to_array()),
+      translation: Some(node.translation.to_array()),
+      skin: None,
+      weights: None,
+    });
+  }
+}
+
+///
+/// Write the vertex attributes and indices of a primitive.
+///
+fn write_primitive(
+  builder: &mut BufferBuilder,
+  primitive: &Primitive,
+  skinned: bool,
+) -> json::mesh::Primitive {
+  let mut attributes = std::collections::BTreeMap::new();
+
+  let positions: Vec<[f32; 3]> = primitive
+    .vertices
+    .iter()
+    .map(|vertex| vertex.position.to_array())
+    .collect();
+  attributes.insert(
+    Checked::Valid(Semantic::Positions),
+    builder.floats(&positions, Type::Vec3, Some(Target::ArrayBuffer), true),
+  );
+
+  if primitive.has_normals {
+    let normals: Vec<[f32; 3]> = primitive
+      .vertices
+      .iter()
+      .map(|vertex| vertex.normal.to_array())
+      .collect();
+    attributes.insert(
+      Checked::Valid(Semantic::Normals),
+      builder.floats(&normals, Type::Vec3, Some(Target::ArrayBuffer), false),
+    );
+  }
+
+  if primitive.has_tangents {
+    let tangents: Vec<[f32; 4]> = primitive
+      .vertices
+      .iter()
+      .map(|vertex| vertex.tangent.to_array())
+      .collect();
+    attributes.insert(
+      Checked::Valid(Semantic::Tangents),
+      builder.floats(&tangents, Type::Vec4, Some(Target::ArrayBuffer), false),
+    );
+  }
+
+  if primitive.has_tex_coords {
+    let tex_coords: Vec<[f32; 2]> = primitive
+      .vertices
+      .iter()
+      .map(|vertex| vertex.tex_coords.to_array())
+      .collect();
+    attributes.insert(
+      Checked::Valid(Semantic::TexCoords(0)),
+      builder.floats(&tex_coords, Type::Vec2, Some(Target::ArrayBuffer), false),
+    );
+  }
+
+  let vertex_count = primitive.vertices.len();
+  for (set, tex_coords) in primitive.extra_tex_coords.iter().enumerate() {
+    if tex_coords.len() != vertex_count {
+      continue;
+    }
+    let tex_coords: Vec<[f32; 2]> = tex_coords.iter().map(|uv| uv.to_array()).collect();
+    attributes.insert(
+      Checked::Valid(Semantic::TexCoords(set as u32 + 1)),
+      builder.floats(&tex_coords, Type::Vec2, Some(Target::ArrayBuffer), false),
+    );
+  }
+
+  if primitive.has_colors && primitive.colors.len() == vertex_count {
+    attributes.insert(
+      Checked::Valid(Semantic::Colors(0)),
+      builder.floats(
+        &primitive.colors,
+        Type::Vec4,
+        Some(Target::ArrayBuffer),
+        false,
+      ),
+    );
+  }
+
+  // ? Joints without a skin are meaningless, and glTF validators complain about them.
+  if skinned
+    && primitive.has_joints
+    && primitive.joints.len() == vertex_count
+    && primitive.weights.len() == vertex_count
+  {
+    let joints: Vec<u8> = primitive
+      .joints
+      .iter()
+      .flatten()
+      .flat_map(|joint| joint.to_le_bytes())
+      .collect();
+    attributes.insert(
+      Checked::Valid(Semantic::Joints(0)),
+      builder.accessor(
+        &joints,
+        vertex_count,
+        ComponentType::U16,
+        Type::Vec4,
+        Some(Target::ArrayBuffer),
+        None,
+        None,
+      ),
+    );
+    attributes.insert(
+      Checked::Valid(Semantic::Weights(0)),
+      builder.floats(
+        &primitive.weights,
+        Type::Vec4,
+        Some(Target::ArrayBuffer),
+        false,
+      ),
+    );
+  }
+
+  let indices = primitive.indices.as_ref().map(|indices| {
+    let data: Vec<u8> = indices
+      .iter()
+      .flat_map(|index| index.to_le_bytes())
+      .collect();
+    builder.accessor(
+      &data,
+      indices.len(),
+      ComponentType::U32,
+      Type::Scalar,
+      Some(Target::ElementArrayBuffer),
+      None,
+      None,
+    )
+  });
+
+  json::mesh::Primitive {
+    attributes,
+    extensions: None,
+    #[cfg(feature = "extras")]
+    extras: primitive.primitive_extras.clone(),
+    #[cfg(not(feature = "extras"))]
+    extras: Default::default(),
+    indices,
+    material: primitive
+      .material
+      .map(|material| Index::new(material as u32)),
+    mode: Checked::Valid(json_mode(&primitive.mode)),
+    targets: None,
+  }
+}
+
+///
+/// Write the bone animations as a single animation. One sampler per track.
+///
+fn write_animation(
+  builder: &mut BufferBuilder,
+  minetest_gltf: &MinetestGLTF,
+  bone_animations: &AHashMap<i32, BoneAnimationChannel>,
+  camera_tracks: &AHashMap<i32, [bool; 3]>,
+) {
+  let mut channels = vec![];
+  let mut samplers = vec![];
+
+  // Sorted so the output is the same every time.
+  let mut ids: Vec<&i32> = bone_animations.keys().collect();
+  ids.sort();
+
+  for id in ids {
+    // ? Without a skeleton, only the nodes on the way down to the cameras were written.
+    let animated = match (camera_tracks.get(id), &minetest_gltf.skeleton) {
+      (Some(animated), _) => *animated,
+      (None, Some(_)) => [true; 3],
+      (None, None) => continue,
+    };
+    let channel = &bone_animations[id];
+    let translations: Vec<[f32; 3]> = channel
+      .translations
+      .iter()
+      .map(|translation| translation.to_array())
+      .collect();
+    let rotations: Vec<[f32; 4]> = channel
+      .rotations
+      .iter()
+      .map(|rotation| rotation.to_array())
+      .collect();
+    let scales: Vec<[f32; 3]> = channel
+      .scales
+      .iter()
+      .map(|scale| scale.to_array())
+      .collect();
+
+    let mut track = AnimationTrack {
+      builder: &mut *builder,
+      channels: &mut channels,
+      samplers: &mut samplers,
+      node: *id,
+    };
+    if animated[0] {
+      track.write(
+        Property::Translation,
+        &channel.translation_timestamps,
+        &translations,
+        Type::Vec3,
+      );
+    }
+    if animated[1] {
+      track.write(
+        Property::Rotation,
+        &channel.rotation_timestamps,
+        &rotations,
+        Type::Vec4,
+      );
+    }
+    if animated[2] {
+      track.write(
+        Property::Scale,
+        &channel.scale_timestamps,
+        &scales,
+        Type::Vec3,
+      );
+    }
+  }
+
+  if channels.is_empty() {
+    return;
+  }
+
+  builder.root.animations.push(json::Animation {
+    extensions: None,
+    extras: Default::default(),
+    channels,
+    name: animation_name(minetest_gltf),
+    samplers,
+  });
+}
+
+///
+/// Writes the tracks of one animated node (bone) as samplers and channels.
+///
+struct AnimationTrack<'a> {
+  builder: &'a mut BufferBuilder,
+  channels: &'a mut Vec<json::animation::Channel>,
+  samplers: &'a mut Vec<json::animation::Sampler>,
+  node: i32,
+}
+
+impl AnimationTrack<'_> {
+  fn write<const N: usize>(
+    &mut self,
+    property: Property,
+    timestamps: &[f32],
+    values: &[[f32; N]],
+    type_: Type,
+  ) {
+    if timestamps.is_empty() || timestamps.len() != values.len() {
+      return;
+    }
+
+    let times: Vec<[f32; 1]> = timestamps.iter().map(|time| [*time]).collect();
+    let input = self.builder.floats(&times, Type::Scalar, None, true);
+    let output = self.builder.floats(values, type_, None, false);
+
+    self.channels.push(json::animation::Channel {
+      sampler: Index::new(self.samplers.len() as u32),
+      target: json::animation::Target {
+        extensions: None,
+        extras: Default::default(),
+        node: Index::new(self.node as u32),
+        path: Checked::Valid(property),
+      },
+      extensions: None,
+      extras: Default::default(),
+    });
+    self.samplers.push(json::animation::Sampler {
+      extensions: None,
+      extras: Default::default(),
+      input,
+      interpolation: Checked::Valid(Interpolation::Linear),
+      output,
+    });
+  }
+}
+
+///
+/// Turn the primitive mode into the glTF json mode.
+///
+fn json_mode(mode: &Mode) -> json::mesh::Mode {
+  match mode {
+    Mode::Points => json::mesh::Mode::Points,
+    Mode::Lines => json::mesh::Mode::Lines,
+    Mode::LineLoop => json::mesh::Mode::LineLoop,
+    Mode::LineStrip => json::mesh::Mode::LineStrip,
+    Mode::Triangles => json::mesh::Mode::Triangles,
+    Mode::TriangleStrip => json::mesh::Mode::TriangleStrip,
+    Mode::TriangleFan => json::mesh::Mode::TriangleFan,
+  }
+}
+
+#[cfg(feature = "names")]
+fn mesh_name(primitive: Option<&Primitive>) -> Option<String> {
+  primitive.and_then(|primitive| primitive.mesh_name.clone())
+}
+
+#[cfg(not(feature = "names"))]
+fn mesh_name(_primitive: Option<&Primitive>) -> Option<String> {
+  None
+}
+
+#[cfg(feature = "names")]
+fn scene_name(minetest_gltf: &MinetestGLTF) -> Option<String> {
+  minetest_gltf
+    .model
+    .as_ref()
+    .and_then(|model| model.name.clone())
+}
+
+#[cfg(not(feature = "names"))]
+fn scene_name(_minetest_gltf: &MinetestGLTF) -> Option<String> {
+  None
+}
+
+#[cfg(feature = "names")]
+fn camera_name(camera: &Camera) -> Option<String> {
+  camera.name.clone()
+}
+
+#[cfg(not(feature = "names"))]
+fn camera_name(_camera: &Camera) -> Option<String> {
+  None
+}
+
+#[cfg(feature = "names")]
+fn animation_name(minetest_gltf: &MinetestGLTF) -> Option<String> {
+  minetest_gltf.animation_name(0).map(String::from)
+}
+
+#[cfg(not(feature = "names"))]
+fn animation_name(_minetest_gltf: &MinetestGLTF) -> Option<String> {
+  None
+}
+
+///
+/// Write a loaded model into a `.gltf` document with the buffer embedded as base64.
+///
+pub fn write_gltf(minetest_gltf: &MinetestGLTF) -> Result<String, Box<dyn Error + Send + Sync>> {
+  let (mut root, bytes) = build_document(minetest_gltf)?;
+  if let Some(buffer) = root.buffers.first_mut() {
+    buffer.uri = Some(format!(
+      "data:application/octet-stream;base64,{}",
+      base64::engine::general_purpose::STANDARD.encode(&bytes)
+    ));
+  }
+  Ok(json::serialize::to_string_pretty(&root)?)
+}
+
+///
+/// Write a loaded model into `.glb` bytes.
+///
+pub fn write_glb(minetest_gltf: &MinetestGLTF) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+  let (root, bytes) = build_document(minetest_gltf)?;
+  let json = json::serialize::to_vec(&root)?;
+  let glb = gltf::Glb {
+    // * The length is worked out when writing.
+    header: gltf::binary::Header {
+      magic: *b"glTF",
+      version: 2,
+      length: 0,
+    },
+    json: Cow::Owned(json),
+    bin: if bytes.is_empty() {
+      None
+    } else {
+      Some(Cow::Owned(bytes))
+    },
+  };
+  Ok(glb.to_vec()?)
+}
+
+///
+/// Write a loaded model into a glTF file.
+///
+/// With `GltfFormat::Separate` the buffer is written next to the file, with a `.bin` extension.
+///
+pub fn save_gltf(
+  minetest_gltf: &MinetestGLTF,
+  path: &str,
+  format: GltfFormat,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+  let bytes = match format {
+    GltfFormat::Embedded => write_gltf(minetest_gltf)?.into_bytes(),
+    GltfFormat::Binary => write_glb(minetest_gltf)?,
+    GltfFormat::Separate => {
+      let (mut root, bytes) = build_document(minetest_gltf)?;
+      let bin_path = Path::new(path).with_extension("bin");
+      let bin_name = match bin_path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name.to_string(),
+        None => return Err(format!("glTF: Can't name the buffer of {}", path).into()),
+      };
+      if let Some(buffer) = root.buffers.first_mut() {
+        buffer.uri = Some(bin_name);
+      }
+      write_file(&bin_path, &bytes)?;
+      json::serialize::to_vec_pretty(&root)?
+    }
+  };
+  write_file(Path::new(path), &bytes)
+}
+
+///
+/// Dump bytes into a file.
+///
+fn write_file(path: &Path, bytes: &[u8]) -> Result<(), Box<dyn Error + Send + Sync>> {
+  let mut file = BufWriter::new(File::create(path)?);
+  file.write_all(bytes)?;
+  file.flush()?;
+  Ok(())
+}
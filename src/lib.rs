@@ -27,18 +27,27 @@
 //! }
 //! ```
 
+pub mod b3d;
+mod extensions;
+pub mod gltf_writer;
+mod load_options;
+pub mod meshopt;
 mod minetest_gltf;
 mod model;
+pub mod obj;
 
 use gltf::Gltf;
 use log::error;
 use minetest_gltf::MinetestGLTF;
 use model::animation::finalize_animations;
+use model::skeleton::Skeleton;
 use std::error::Error;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
 
+pub use extensions::{UnsupportedExtensions, IGNORABLE_EXTENSIONS, SUPPORTED_EXTENSIONS};
+pub use load_options::LoadOptions;
 pub use model::*;
 
 /// Load scenes from path to a glTF 2.0.
@@ -55,11 +64,142 @@ pub use model::*;
 /// println!("Primitives: #{}", model.primitives.len());
 /// ```
 pub fn load(path: &str) -> Result<MinetestGLTF, Box<dyn Error + Send + Sync>> {
-  // Run gltf
+  load_with_options(path, &LoadOptions::default())
+}
 
+/// Load scenes from path to a glTF 2.0, running the optional processing in `options`.
+///
+/// Legacy `.b3d` and `.obj` models are loaded into the same output. The format is picked by the
+/// file extension, or by the magic bytes if the extension is unknown.
+///
+/// # Example
+///
+/// ```
+/// # use minetest_gltf::{animation::ReductionTolerance, LoadOptions};
+/// let options = LoadOptions {
+///   keyframe_reduction: Some(ReductionTolerance::default()),
+///   ..Default::default()
+/// };
+/// let minetest_gltf = minetest_gltf::load_with_options("tests/minetest_sam.gltf", &options)
+///   .expect("Failed to load glTF");
+/// assert!(minetest_gltf.is_animated());
+/// ```
+pub fn load_with_options(
+  path: &str,
+  options: &LoadOptions,
+) -> Result<MinetestGLTF, Box<dyn Error + Send + Sync>> {
   // Try to get the file name. If this fails, the path probably doesn't exist.
   let file_name = file_name_from_path(path)?;
 
+  let mut minetest_gltf = match detect_format(path)? {
+    ModelFormat::Gltf => load_gltf(path, file_name)?,
+    ModelFormat::B3D => b3d::read_b3d(&std::fs::read(path)?, path)?,
+    ModelFormat::Obj => obj::read_obj(&std::fs::read_to_string(path)?, path)?,
+  };
+
+  if let Some(tolerance) = &options.keyframe_reduction {
+    minetest_gltf.reduce_keyframes(tolerance);
+  }
+
+  if let Some(conversion) = &options.coordinate_conversion {
+    conversion.convert(&mut minetest_gltf);
+  }
+
+  if let (true, Some(model)) = (options.bake_texture_transforms, &mut minetest_gltf.model) {
+    model.bake_texture_transforms();
+  }
+
+  if let (Some(optimization), Some(model)) = (&options.optimization, &mut minetest_gltf.model) {
+    model.optimize(optimization);
+  }
+
+  Ok(minetest_gltf)
+}
+
+///
+/// The model formats this crate can load.
+///
+enum ModelFormat {
+  Gltf,
+  B3D,
+  Obj,
+}
+
+///
+/// Figure out the model format by the file extension. If that fails, by the magic bytes.
+///
+fn detect_format(path: &str) -> Result<ModelFormat, Box<dyn Error + Send + Sync>> {
+  let extension = Path::new(path)
+    .extension()
+    .and_then(|extension| extension.to_str())
+    .map(|extension| extension.to_ascii_lowercase());
+
+  match extension.as_deref() {
+    Some("gltf") | Some("glb") => return Ok(ModelFormat::Gltf),
+    Some("b3d") => return Ok(ModelFormat::B3D),
+    Some("obj") => return Ok(ModelFormat::Obj),
+    _ => {}
+  }
+
+  let mut magic = [0; 4];
+  let mut reader = read_path_to_buf_read(path)?;
+  let read = std::io::Read::read(&mut reader, &mut magic)?;
+
+  match &magic[..read] {
+    b"BB3D" => Ok(ModelFormat::B3D),
+    b"glTF" => Ok(ModelFormat::Gltf),
+    bytes if bytes.first() == Some(&b'{') => Ok(ModelFormat::Gltf),
+    _ => Err(format!("Unknown model format. {}", path).into()),
+  }
+}
+
+///
+/// Make sure every Draco compressed primitive has uncompressed data to fall back on.
+///
+/// Draco isn't decoded. Primitives whose attributes and indices also have regular accessors get
+/// loaded from those, like the extension allows. The rest would come out empty or wrong, so the
+/// load fails instead.
+///
+fn check_draco(
+  document: &gltf::Document,
+  file_name: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+  for mesh in document.meshes() {
+    for primitive in mesh.primitives() {
+      if primitive
+        .extension_value("KHR_draco_mesh_compression")
+        .is_none()
+      {
+        continue;
+      }
+      let has_data =
+        |accessor: gltf::Accessor| accessor.view().is_some() || accessor.sparse().is_some();
+      let has_fallback = primitive
+        .attributes()
+        .all(|(_, accessor)| has_data(accessor))
+        && primitive.indices().is_none_or(has_data);
+      if !has_fallback {
+        return Err(
+          format!(
+            "Mesh {} primitive {} is Draco compressed, which isn't supported. {}",
+            mesh.index(),
+            primitive.index(),
+            file_name
+          )
+          .into(),
+        );
+      }
+    }
+  }
+  Ok(())
+}
+
+///
+/// Load a glTF 2.0 model.
+///
+fn load_gltf(path: &str, file_name: &str) -> Result<MinetestGLTF, Box<dyn Error + Send + Sync>> {
+  // Run gltf
+
   // We need the base path for the GLTF lib. We want to choose if we load textures.
   let base = Path::new(path).parent().unwrap_or_else(|| Path::new("./"));
 
@@ -67,13 +207,30 @@ pub fn load(path: &str) -> Result<MinetestGLTF, Box<dyn Error + Send + Sync>> {
   let model_reader = read_path_to_buf_read(path)?;
 
   // Now we need to get the "Document" from the GLTF lib.
-  let gltf_data = Gltf::from_reader(model_reader)?;
+  // ? Extensions and Draco primitives are checked first, since validation would only say that
+  // ? their data is missing.
+  let Gltf { document, blob } = Gltf::from_reader_without_validation(model_reader)?;
+  let ignored_extensions = extensions::check_extensions(&document)?;
+  check_draco(&document, file_name)?;
+  let json = document.into_json();
+  extensions::validate(&json)?;
+  let materials = json.materials.clone();
+  let gltf_data = Gltf {
+    document: gltf::Document::from_json_without_validation(json),
+    blob,
+  };
 
   // We always want the buffer data. We have to clone this, it's basically ripping out ownership from our hands.
-  let buffers = gltf::import_buffers(&gltf_data.clone(), Some(base), gltf_data.blob.clone())?;
+  let buffers = meshopt::import_buffers(&gltf_data, Some(base), gltf_data.blob.clone())?;
 
   // Init data and collection useful for conversion
   let mut minetest_gltf = MinetestGLTF::new(buffers.clone(), path);
+  minetest_gltf.ignored_extensions = ignored_extensions;
+
+  #[cfg(feature = "names")]
+  {
+    minetest_gltf.load_names(&gltf_data);
+  }
 
   // Convert gltf -> minetest_gltf
   let scene_attempt = gltf_data.scenes().next();
@@ -86,10 +243,11 @@ pub fn load(path: &str) -> Result<MinetestGLTF, Box<dyn Error + Send + Sync>> {
     panic!("blew up after check somehow.")
   };
 
-  let model = Model::load(scene, &mut minetest_gltf);
+  let mut model = Model::load(scene, &mut minetest_gltf);
+  model.load_materials(materials);
 
-  // Double check that this model actually exists.
-  if model.primitives.first().is_none() {
+  // Double check that this model actually exists. Cutscenes can get by with only cameras or lights.
+  if model.primitives.is_empty() && model.cameras.is_empty() && model.lights.is_empty() {
     return Err("Model has no primitives!".into());
   }
 
@@ -112,10 +270,20 @@ pub fn load(path: &str) -> Result<MinetestGLTF, Box<dyn Error + Send + Sync>> {
     }
   }
 
+  // ? Cutscenes animate cameras in models that aren't skinned.
+  let has_animated_cameras = !model.cameras.is_empty() && gltf_data.animations().next().is_some();
+  for camera in &mut model.cameras {
+    camera.mark_animated(&gltf_data);
+  }
+
   // Now apply the data.
   if is_skinned {
+    minetest_gltf.skeleton = Skeleton::load(&gltf_data, &minetest_gltf.buffers);
+
     // If there's an error parsing, raw return the error.
-    finalize_animations(&mut minetest_gltf, gltf_data, buffers, file_name)?
+    finalize_animations(&mut minetest_gltf, gltf_data, buffers, file_name)?;
+  } else if has_animated_cameras {
+    finalize_animations(&mut minetest_gltf, gltf_data, buffers, file_name)?;
   } else {
     minetest_gltf.is_animated = false;
   }
@@ -165,8 +333,6 @@ fn file_name_from_path(path: &str) -> Result<&str, &str> {
 mod tests {
   use crate::*;
 
-  use self::primitive::Mode;
-
   // #[test]
   // fn check_cube_glb() {
   //   drop(env_logger::try_init());
@@ -428,4 +594,1908 @@ mod tests {
       None => panic!("ain't got no bones!"),
     }
   }
+
+  #[test]
+  fn blend_sam_poses() {
+    drop(env_logger::try_init());
+
+    let sam = match load("tests/minetest_sam.gltf") {
+      Ok(sam) => sam,
+      Err(e) => panic!("minetest_sam: failed to load. {}", e),
+    };
+
+    let skeleton = match &sam.skeleton {
+      Some(skeleton) => skeleton,
+      None => panic!("sam has no skeleton!"),
+    };
+    let bone_animations = match &sam.bone_animations {
+      Some(bone_animations) => bone_animations,
+      None => panic!("sam has no bone animations!"),
+    };
+
+    // Body (5) has the head, the arms, and the legs underneath it.
+    assert_eq!(skeleton.subtree(5).len(), 6);
+    assert_eq!(skeleton.joints.len(), skeleton.inverse_bind_matrices.len());
+
+    let start = animation::Pose::sample_with_rest(skeleton, bone_animations, 0.0);
+    let middle = animation::Pose::sample_with_rest(skeleton, bone_animations, 1.0);
+
+    // Full weight gives back the other pose. No weight keeps this one.
+    let full = start.blend(&middle, 1.0, None);
+    let none = start.blend(&middle, 0.0, None);
+    for (id, transform) in &middle.transforms {
+      assert!(full
+        .get(*id)
+        .translation
+        .abs_diff_eq(transform.translation, 0.0001));
+      assert!(none
+        .get(*id)
+        .rotation
+        .abs_diff_eq(start.get(*id).rotation, 0.0001));
+    }
+
+    // Only the head (0) is allowed to move.
+    let head_mask = animation::BoneMask::from_subtree(skeleton, 0);
+    let masked = start.blend(&middle, 1.0, Some(&head_mask));
+    assert!(masked
+      .get(0)
+      .rotation
+      .abs_diff_eq(middle.get(0).rotation, 0.0001));
+    assert!(masked
+      .get(1)
+      .rotation
+      .abs_diff_eq(start.get(1).rotation, 0.0001));
+
+    // Layering the pose on top of itself does nothing.
+    let layered = start.add_layer(&middle, &middle, 1.0, None);
+    for (id, transform) in &start.transforms {
+      assert!(layered
+        .get(*id)
+        .rotation
+        .abs_diff_eq(transform.rotation, 0.0001));
+    }
+
+    let mut fade = animation::CrossFade::new(0.5);
+    assert_eq!(fade.weight(), 0.0);
+    fade.advance(0.25);
+    assert!((fade.weight() - 0.5).abs() < 0.0001);
+    fade.advance(1.0);
+    assert!(fade.is_finished());
+  }
+
+  #[test]
+  fn override_sam_bones() {
+    drop(env_logger::try_init());
+
+    let sam = match load("tests/minetest_sam.gltf") {
+      Ok(sam) => sam,
+      Err(e) => panic!("minetest_sam: failed to load. {}", e),
+    };
+
+    let skeleton = match &sam.skeleton {
+      Some(skeleton) => skeleton,
+      None => panic!("sam has no skeleton!"),
+    };
+
+    // The rest pose should give back (about) identity skinning matrices.
+    let rest = animation::Pose::rest(skeleton);
+    let matrices = skeleton.skinning_matrices(&rest);
+    assert_eq!(matrices.len(), skeleton.joints.len());
+    for matrix in &matrices {
+      assert!(matrix.abs_diff_eq(glam::Mat4::IDENTITY, 0.001));
+    }
+
+    let look = glam::Quat::from_rotation_y(1.0);
+    let mut overrides = animation::BoneOverrides::new();
+    overrides.set(
+      0,
+      animation::BoneOverride {
+        rotation: Some(animation::OverrideProperty::absolute(look)),
+        position: Some(animation::OverrideProperty::relative(glam::Vec3::Y)),
+        scale: None,
+      },
+    );
+
+    // Joint 1 is the head (0) in sam's skin.
+    assert!(overrides.set_joint(
+      skeleton,
+      1,
+      animation::BoneOverride {
+        rotation: Some(animation::OverrideProperty::absolute(look)),
+        position: Some(animation::OverrideProperty::relative(glam::Vec3::Y)),
+        scale: None,
+      },
+    ));
+    assert!(!overrides.set_joint(skeleton, 100, animation::BoneOverride::default()));
+
+    let posed = overrides.apply(&rest);
+    assert!(posed.get(0).rotation.abs_diff_eq(look, 0.0001));
+    assert!(posed
+      .get(0)
+      .translation
+      .abs_diff_eq(rest.get(0).translation + glam::Vec3::Y, 0.0001));
+    assert_eq!(posed.get(1), rest.get(1));
+
+    // Now interpolate back to no rotation over one second.
+    overrides.set(
+      0,
+      animation::BoneOverride {
+        rotation: Some(
+          animation::OverrideProperty::absolute(glam::Quat::IDENTITY).with_interpolation(1.0),
+        ),
+        position: None,
+        scale: None,
+      },
+    );
+    overrides.update(0.5);
+    let halfway = overrides.apply(&rest);
+    assert!(halfway
+      .get(0)
+      .rotation
+      .abs_diff_eq(look.slerp(glam::Quat::IDENTITY, 0.5), 0.0001));
+    overrides.update(0.5);
+    let finished = overrides.apply(&rest);
+    assert!(finished
+      .get(0)
+      .rotation
+      .abs_diff_eq(glam::Quat::IDENTITY, 0.0001));
+  }
+
+  #[cfg(feature = "names")]
+  #[test]
+  fn sam_names() {
+    drop(env_logger::try_init());
+
+    let sam = match load("tests/minetest_sam.gltf") {
+      Ok(sam) => sam,
+      Err(e) => panic!("minetest_sam: failed to load. {}", e),
+    };
+
+    assert_eq!(sam.node_id("Head"), Some(0));
+    assert_eq!(sam.node_name(5), Some("Body"));
+    assert_eq!(sam.node_id("Tail"), None);
+
+    // Sam's skin starts with the body, then the head.
+    assert_eq!(sam.joint_name(1), Some("Head"));
+    assert_eq!(sam.joint_index("Body"), Some(0));
+
+    assert_eq!(sam.animation_name(0), Some("ArmatureAction"));
+    assert_eq!(sam.animation_index("ArmatureAction"), Some(0));
+
+    match sam.bone_animation_by_name("Arm_Right") {
+      Some(channel) => assert_eq!(channel.name.as_deref(), Some("Arm_Right")),
+      None => panic!("sam has no right arm animation!"),
+    }
+  }
+
+  #[test]
+  fn sam_root_motion() {
+    drop(env_logger::try_init());
+
+    let sam = match load("tests/minetest_sam.gltf") {
+      Ok(sam) => sam,
+      Err(e) => panic!("minetest_sam: failed to load. {}", e),
+    };
+
+    let bone_animations = match &sam.bone_animations {
+      Some(bone_animations) => bone_animations,
+      None => panic!("sam has no bone animations!"),
+    };
+
+    // Only pull out the horizontal movement.
+    let axes = glam::BVec3::new(true, false, true);
+    let (motion, in_place) = match sam.extract_root_motion(animation::RootBone::FirstJoint, axes) {
+      Some(extracted) => extracted,
+      None => panic!("sam has no root motion!"),
+    };
+
+    // The body (5) is the first joint.
+    assert_eq!(motion.root, 5);
+
+    let original = &bone_animations[&5].translations;
+    let pinned = &in_place[&5].translations;
+    assert_eq!(motion.deltas.len(), original.len());
+    assert_eq!(pinned.len(), original.len());
+
+    for (original, pinned) in original.iter().zip(pinned) {
+      assert_eq!(pinned.x, bone_animations[&5].translations[0].x);
+      assert_eq!(pinned.y, original.y);
+      assert_eq!(pinned.z, bone_animations[&5].translations[0].z);
+    }
+
+    let expected = (original[original.len() - 1] - original[0]) * glam::Vec3::new(1.0, 0.0, 1.0);
+    assert!(motion.total().abs_diff_eq(expected, 0.0001));
+    assert!(motion.displacement(f32::MAX).abs_diff_eq(expected, 0.0001));
+    assert_eq!(motion.displacement(0.0), glam::Vec3::ZERO);
+
+    // Bones that aren't the root are left alone.
+    assert_eq!(in_place[&0].translations, bone_animations[&0].translations);
+  }
+
+  #[test]
+  fn reduce_sam_keyframes() {
+    drop(env_logger::try_init());
+
+    let mut sam = match load("tests/minetest_sam.gltf") {
+      Ok(sam) => sam,
+      Err(e) => panic!("minetest_sam: failed to load. {}", e),
+    };
+
+    let original = match &sam.bone_animations {
+      Some(bone_animations) => bone_animations.clone(),
+      None => panic!("sam has no bone animations!"),
+    };
+
+    let tolerance = animation::ReductionTolerance::default();
+    let report = sam.reduce_keyframes(&tolerance);
+
+    assert!(report.keyframes_after < report.keyframes_before);
+    assert!(report.bytes_saved() > 0);
+    // Sam never scales anything.
+    assert!(report.constant_tracks >= original.len());
+
+    let reduced = match &sam.bone_animations {
+      Some(bone_animations) => bone_animations,
+      None => panic!("sam lost the bone animations!"),
+    };
+
+    // Every original keyframe should be rebuilt within the tolerance.
+    for (id, channel) in &original {
+      let reduced_channel = &reduced[id];
+      assert_eq!(reduced_channel.scales.len(), 1);
+      for (i, time) in channel.translation_timestamps.iter().enumerate() {
+        let sampled = reduced_channel.sample(*time);
+        assert!(sampled
+          .translation
+          .abs_diff_eq(channel.translations[i], tolerance.translation * 2.0));
+        assert!(
+          sampled.rotation.angle_between(channel.rotations[i]) <= tolerance.rotation * 2.0 + 0.001
+        );
+      }
+    }
+
+    // The same thing should happen through the load options.
+    let options = LoadOptions {
+      keyframe_reduction: Some(tolerance),
+      ..Default::default()
+    };
+    let reduced_on_load = match load_with_options("tests/minetest_sam.gltf", &options) {
+      Ok(sam) => sam,
+      Err(e) => panic!("minetest_sam: failed to load. {}", e),
+    };
+    match reduced_on_load.bone_animations {
+      Some(bone_animations) => {
+        for channel in bone_animations.values() {
+          assert_eq!(channel.scales.len(), 1);
+        }
+      }
+      None => panic!("sam has no bone animations!"),
+    }
+  }
+
+  #[test]
+  fn convert_coordinates() {
+    drop(env_logger::try_init());
+
+    let options = LoadOptions {
+      coordinate_conversion: Some(coordinates::CoordinateConversion::minetest()),
+      ..Default::default()
+    };
+
+    let original = match load("tests/cube_classic.gltf") {
+      Ok(cube) => cube,
+      Err(e) => panic!("cube_classic: failed to load. {}", e),
+    };
+    let converted = match load_with_options("tests/cube_classic.gltf", &options) {
+      Ok(cube) => cube,
+      Err(e) => panic!("cube_classic: failed to load. {}", e),
+    };
+
+    let (original, converted) = match (&original.model, &converted.model) {
+      (Some(original), Some(converted)) => (&original.primitives[0], &converted.primitives[0]),
+      _ => panic!("cube_classic has no model!"),
+    };
+
+    for (before, after) in original.vertices.iter().zip(&converted.vertices) {
+      let expected =
+        glam::Vec3::new(before.position.x, before.position.y, -before.position.z) * coordinates::BS;
+      assert!(after.position.abs_diff_eq(expected, 0.0001));
+      assert_eq!(after.tangent.w, -before.tangent.w);
+    }
+
+    // The triangles still face the way their normals point.
+    let triangles = match converted.triangles() {
+      Ok(triangles) => triangles,
+      Err(e) => panic!("cube_classic: {}", e),
+    };
+    for [a, b, c] in triangles {
+      let face = (b.position - a.position).cross(c.position - a.position);
+      assert!(face.dot(a.normal) > 0.0);
+    }
+
+    // Converting the skeleton keeps the rest pose skinning matrices at identity.
+    let sam = match load_with_options("tests/minetest_sam.gltf", &options) {
+      Ok(sam) => sam,
+      Err(e) => panic!("minetest_sam: failed to load. {}", e),
+    };
+    let skeleton = match &sam.skeleton {
+      Some(skeleton) => skeleton,
+      None => panic!("sam has no skeleton!"),
+    };
+    for matrix in skeleton.skinning_matrices(&animation::Pose::rest(skeleton)) {
+      assert!(matrix.abs_diff_eq(glam::Mat4::IDENTITY, 0.001));
+    }
+  }
+
+  ///
+  /// Walk a B3D chunk tree and collect every chunk with a tag. Returns the chunk bodies.
+  ///
+  fn find_b3d_chunks<'a>(bytes: &'a [u8], tag: &[u8; 4], found: &mut Vec<&'a [u8]>) {
+    // These chunks have a header before their children.
+    let header = |chunk_tag: &[u8], body: &[u8]| -> usize {
+      match chunk_tag {
+        b"BB3D" => 4,
+        b"MESH" => 4,
+        b"NODE" => match body.iter().position(|byte| *byte == 0) {
+          Some(end) => end + 1 + 40,
+          None => body.len(),
+        },
+        _ => body.len(),
+      }
+    };
+
+    let mut position = 0;
+    while position + 8 <= bytes.len() {
+      let chunk_tag = &bytes[position..position + 4];
+      let mut length = [0; 4];
+      length.copy_from_slice(&bytes[position + 4..position + 8]);
+      let length = i32::from_le_bytes(length) as usize;
+      let body = &bytes[position + 8..position + 8 + length];
+      if chunk_tag == tag {
+        found.push(body);
+      }
+      let skip = header(chunk_tag, body);
+      if skip < body.len() {
+        find_b3d_chunks(&body[skip..], tag, found);
+      }
+      position += 8 + length;
+    }
+  }
+
+  #[test]
+  fn write_sam_b3d() {
+    drop(env_logger::try_init());
+
+    let sam = match load("tests/minetest_sam.gltf") {
+      Ok(sam) => sam,
+      Err(e) => panic!("minetest_sam: failed to load. {}", e),
+    };
+
+    let bytes = match b3d::write_b3d(&sam) {
+      Ok(bytes) => bytes,
+      Err(e) => panic!("minetest_sam: failed to write b3d. {}", e),
+    };
+
+    assert_eq!(&bytes[0..4], b"BB3D");
+
+    let primitive = match &sam.model {
+      Some(model) => &model.primitives[0],
+      None => panic!("sam has no model!"),
+    };
+
+    // Flags, tex coord sets, tex coord set size. Then position, normal, tex coords.
+    let mut vertices = vec![];
+    find_b3d_chunks(&bytes, b"VRTS", &mut vertices);
+    assert_eq!(vertices.len(), 1);
+    assert_eq!((vertices[0].len() - 12) / (4 * 8), primitive.vertices.len());
+
+    let mut triangles = vec![];
+    find_b3d_chunks(&bytes, b"TRIS", &mut triangles);
+    assert_eq!(triangles.len(), 1);
+    match primitive.indices() {
+      Some(indices) => assert_eq!((triangles[0].len() - 4) / 4, indices.len()),
+      None => panic!("sam has no indices!"),
+    }
+
+    // Every bone has translation, scale and rotation keys for every frame.
+    let bone_animations = match &sam.bone_animations {
+      Some(bone_animations) => bone_animations,
+      None => panic!("sam has no bone animations!"),
+    };
+    let mut keys = vec![];
+    find_b3d_chunks(&bytes, b"KEYS", &mut keys);
+    assert_eq!(keys.len(), bone_animations.len() * 3);
+    let frames: usize = keys
+      .iter()
+      .map(|chunk| match chunk[0] {
+        1 | 2 => (chunk.len() - 4) / 16,
+        _ => (chunk.len() - 4) / 20,
+      })
+      .sum();
+    assert_eq!(frames, bone_animations.len() * 3 * 221);
+
+    let mut animations = vec![];
+    find_b3d_chunks(&bytes, b"ANIM", &mut animations);
+    assert_eq!(animations.len(), 1);
+    assert_eq!(animations[0][4..8], 221_i32.to_le_bytes());
+
+    let mut bones = vec![];
+    find_b3d_chunks(&bytes, b"BONE", &mut bones);
+    assert_eq!(bones.len(), 6);
+  }
+
+  #[test]
+  fn b3d_round_trip() {
+    drop(env_logger::try_init());
+
+    let sam = match load("tests/minetest_sam.gltf") {
+      Ok(sam) => sam,
+      Err(e) => panic!("minetest_sam: failed to load. {}", e),
+    };
+    let bytes = match b3d::write_b3d(&sam) {
+      Ok(bytes) => bytes,
+      Err(e) => panic!("minetest_sam: failed to write b3d. {}", e),
+    };
+
+    // Unknown extensions fall back to the magic bytes.
+    let path = std::env::temp_dir().join("minetest_gltf_sam_round_trip.model");
+    if let Err(e) = std::fs::write(&path, &bytes) {
+      panic!("failed to write temp b3d. {}", e);
+    }
+    let path = match path.to_str() {
+      Some(path) => path.to_string(),
+      None => panic!("temp dir isn't utf8!"),
+    };
+    let round_trip = match load(&path) {
+      Ok(round_trip) => round_trip,
+      Err(e) => panic!("minetest_sam: failed to read b3d. {}", e),
+    };
+    drop(std::fs::remove_file(&path));
+
+    let (original, read) = match (&sam.model, &round_trip.model) {
+      (Some(original), Some(read)) => (&original.primitives[0], &read.primitives[0]),
+      _ => panic!("sam lost the model!"),
+    };
+    assert_eq!(read.vertices.len(), original.vertices.len());
+    assert_eq!(
+      read.indices().map(|indices| indices.len()),
+      original.indices().map(|indices| indices.len())
+    );
+    assert!(read.has_joints && read.has_weights);
+    assert_eq!(read.joints.len(), read.vertices.len());
+
+    match &round_trip.skeleton {
+      Some(skeleton) => assert_eq!(skeleton.joints.len(), 6),
+      None => panic!("sam lost the skeleton!"),
+    }
+
+    match &round_trip.bone_animations {
+      Some(bone_animations) => {
+        assert_eq!(bone_animations.len(), 6);
+        for channel in bone_animations.values() {
+          assert_eq!(channel.translations.len(), 221);
+          assert_eq!(channel.rotations.len(), 221);
+          assert_eq!(channel.scales.len(), 221);
+        }
+      }
+      None => panic!("sam lost the bone animations!"),
+    }
+  }
+
+  #[test]
+  fn gltf_round_trip() {
+    drop(env_logger::try_init());
+
+    let formats = [
+      (gltf_writer::GltfFormat::Binary, "glb"),
+      (gltf_writer::GltfFormat::Embedded, "gltf"),
+      (gltf_writer::GltfFormat::Separate, "gltf"),
+    ];
+
+    for model_name in [
+      "cube.glb",
+      "cube_classic.gltf",
+      "simple_skin.gltf",
+      "minetest_sam.gltf",
+      "spider_animated.gltf",
+      "complete.glb",
+      "texture_slots.gltf",
+      "camera_cutscene.gltf",
+      "camera_only.gltf",
+    ] {
+      let original = match load(&format!("tests/{}", model_name)) {
+        Ok(original) => original,
+        Err(e) => panic!("{}: failed to load. {}", model_name, e),
+      };
+
+      for (format, extension) in formats {
+        let path = std::env::temp_dir().join(format!(
+          "minetest_gltf_round_trip_{:?}_{}.{}",
+          format, model_name, extension
+        ));
+        let path = match path.to_str() {
+          Some(path) => path.to_string(),
+          None => panic!("temp dir isn't utf8!"),
+        };
+        if let Err(e) = gltf_writer::save_gltf(&original, &path, format) {
+          panic!("{}: failed to write {:?}. {}", model_name, format, e);
+        }
+        let round_trip = match load(&path) {
+          Ok(round_trip) => round_trip,
+          Err(e) => panic!("{}: failed to read {:?}. {}", model_name, format, e),
+        };
+        drop(std::fs::remove_file(&path));
+        drop(std::fs::remove_file(
+          std::path::Path::new(&path).with_extension("bin"),
+        ));
+
+        let (original_model, read_model) = match (&original.model, &round_trip.model) {
+          (Some(original_model), Some(read_model)) => (original_model, read_model),
+          _ => panic!("{}: lost the model!", model_name),
+        };
+        assert_eq!(read_model.primitives.len(), original_model.primitives.len());
+        assert_eq!(read_model.materials.len(), original_model.materials.len());
+        for (read, original) in read_model.materials.iter().zip(&original_model.materials) {
+          assert_eq!(
+            gltf::json::serialize::to_string(read).ok(),
+            gltf::json::serialize::to_string(original).ok()
+          );
+        }
+        for (read, original) in read_model.primitives.iter().zip(&original_model.primitives) {
+          assert_eq!(read.mode, original.mode);
+          assert_eq!(read.vertices.len(), original.vertices.len());
+          assert_eq!(read.indices, original.indices);
+          assert_eq!(read.has_normals, original.has_normals);
+          assert_eq!(read.has_tangents, original.has_tangents);
+          assert_eq!(read.has_tex_coords, original.has_tex_coords);
+          assert_eq!(read.joints, original.joints);
+          assert_eq!(read.colors, original.colors);
+          assert_eq!(read.material, original.material);
+          assert_eq!(read.texture_slot, original.texture_slot);
+          for (read, original) in read.vertices.iter().zip(&original.vertices) {
+            assert!(read.position.abs_diff_eq(original.position, 0.0001));
+          }
+        }
+
+        assert_eq!(read_model.cameras.len(), original_model.cameras.len());
+        for (read_camera, camera) in read_model.cameras.iter().zip(&original_model.cameras) {
+          assert_eq!(read_camera.node, camera.node);
+          assert_eq!(read_camera.path, camera.path);
+          assert_eq!(read_camera.projection, camera.projection);
+          assert_eq!(
+            (read_camera.znear, read_camera.zfar),
+            (camera.znear, camera.zfar)
+          );
+          if let (Some(read), Some(original)) =
+            (&round_trip.bone_animations, &original.bone_animations)
+          {
+            for time in [0.0, 0.5, 1.0, 2.0] {
+              assert!(read_camera
+                .sample(read, time)
+                .abs_diff_eq(camera.sample(original, time), 0.0001));
+            }
+          }
+        }
+
+        assert_eq!(round_trip.is_animated(), original.is_animated());
+        if let (Some(read), Some(original)) = (&round_trip.skeleton, &original.skeleton) {
+          assert_eq!(read.joints, original.joints);
+          assert_eq!(read.inverse_bind_matrices, original.inverse_bind_matrices);
+        }
+        if let (Some(read), Some(original)) =
+          (&round_trip.bone_animations, &original.bone_animations)
+        {
+          assert_eq!(read.len(), original.len());
+          for (id, channel) in original {
+            let read = match read.get(id) {
+              Some(read) => read,
+              None => panic!("{}: lost the animation of node {}!", model_name, id),
+            };
+            assert_eq!(read.translations.len(), channel.translations.len());
+            assert_eq!(read.rotations.len(), channel.rotations.len());
+            assert_eq!(read.scales.len(), channel.scales.len());
+          }
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn load_obj_cube() {
+    drop(env_logger::try_init());
+
+    let cube = match load("tests/cube.obj") {
+      Ok(cube) => cube,
+      Err(e) => panic!("cube.obj: failed to load. {}", e),
+    };
+
+    let model = match cube.model {
+      Some(model) => model,
+      None => panic!("cube.obj has no model!"),
+    };
+
+    // One primitive per material.
+    assert_eq!(model.primitives.len(), 2);
+
+    let sides = &model.primitives[0];
+    assert_eq!(sides.vertices.len(), 16);
+    assert_eq!(sides.indices().map(|indices| indices.len()), Some(24));
+    assert!(sides.has_normals() && sides.has_tex_coords());
+
+    let caps = &model.primitives[1];
+    assert_eq!(caps.vertices.len(), 8);
+    assert_eq!(
+      caps.triangles().map(|triangles| triangles.len()).ok(),
+      Some(4)
+    );
+
+    // V is flipped to match glTF.
+    assert_eq!(sides.vertices[0].tex_coords, glam::Vec2::new(0.0, 1.0));
+  }
+
+  #[test]
+  fn texture_slots() {
+    drop(env_logger::try_init());
+
+    let minetest_gltf = match load("tests/texture_slots.gltf") {
+      Ok(minetest_gltf) => minetest_gltf,
+      Err(e) => panic!("texture_slots: failed to load. {}", e),
+    };
+    let mut model = match minetest_gltf.model {
+      Some(model) => model,
+      None => panic!("texture_slots has no model!"),
+    };
+
+    // Children are loaded first, but minetest sees the parent mesh first.
+    let slots: Vec<(Option<usize>, usize)> = model
+      .primitives
+      .iter()
+      .map(|primitive| (primitive.material, primitive.texture_slot))
+      .collect();
+    assert_eq!(
+      slots,
+      vec![(Some(0), 1), (Some(1), 0), (Some(1), 0), (Some(2), 2)]
+    );
+    assert_eq!(model.texture_slot_count(), 3);
+    assert_eq!(model.texture_slot_primitives(0).count(), 2);
+
+    // Minetest can't see slot 3 before slot 1 in any nesting of these, so the writer sorts them.
+    let mut unnestable = minetest_gltf::MinetestGLTF::new(vec![], "unnestable.gltf");
+    let mut unnestable_model = model.clone();
+    for (primitive, slot) in unnestable_model.primitives.iter_mut().zip([2, 0, 3, 1]) {
+      primitive.material = Some(slot);
+      primitive.texture_slot = slot;
+    }
+    unnestable.model = Some(unnestable_model);
+    let path = std::env::temp_dir().join("minetest_gltf_unnestable.gltf");
+    let path = match path.to_str() {
+      Some(path) => path.to_string(),
+      None => panic!("temp dir isn't utf8!"),
+    };
+    if let Err(e) = gltf_writer::save_gltf(&unnestable, &path, gltf_writer::GltfFormat::Embedded) {
+      panic!("unnestable: failed to write. {}", e);
+    }
+    let read = match load(&path) {
+      Ok(read) => read,
+      Err(e) => panic!("unnestable: failed to read. {}", e),
+    };
+    drop(std::fs::remove_file(&path));
+    let read_slots: Vec<(Option<usize>, usize)> = match &read.model {
+      Some(read) => read
+        .primitives
+        .iter()
+        .map(|primitive| (primitive.material, primitive.texture_slot))
+        .collect(),
+      None => panic!("unnestable lost the model!"),
+    };
+    assert_eq!(
+      read_slots,
+      vec![(Some(0), 0), (Some(1), 1), (Some(2), 2), (Some(3), 3)]
+    );
+
+    if let Err(e) = model.merge_texture_slots() {
+      panic!("texture_slots: failed to merge. {}", e);
+    }
+    assert_eq!(model.primitives.len(), 3);
+    for (slot, primitive) in model.primitives.iter().enumerate() {
+      assert_eq!(primitive.texture_slot, slot);
+    }
+    assert_eq!(model.primitives[0].material, Some(1));
+    assert_eq!(model.primitives[0].vertices.len(), 6);
+    assert_eq!(model.primitives[0].indices(), Some(&vec![0, 1, 2, 3, 4, 5]));
+  }
+
+  #[test]
+  fn model_bounds() {
+    drop(env_logger::try_init());
+
+    let cube = match load("tests/cube.glb") {
+      Ok(cube) => cube,
+      Err(e) => panic!("cube: failed to load. {}", e),
+    };
+    let bounds = match cube.model.as_ref().and_then(|model| model.bounds()) {
+      Some(bounds) => bounds,
+      None => panic!("cube has no bounds!"),
+    };
+    assert_eq!(bounds.bounding_box.min, glam::Vec3::ZERO);
+    assert_eq!(bounds.bounding_box.max, glam::Vec3::ONE);
+    assert_eq!(bounds.bounding_sphere.center, glam::Vec3::splat(0.5));
+    assert!((bounds.bounding_sphere.radius - 3.0_f32.sqrt() * 0.5).abs() < 0.0001);
+
+    let sam = match load("tests/minetest_sam.gltf") {
+      Ok(sam) => sam,
+      Err(e) => panic!("minetest_sam: failed to load. {}", e),
+    };
+    let mut model = match sam.model.clone() {
+      Some(model) => model,
+      None => panic!("minetest_sam has no model!"),
+    };
+
+    // The accessor min and max match the vertices.
+    let rest = match model.bounds() {
+      Some(rest) => rest,
+      None => panic!("minetest_sam has no bounds!"),
+    };
+    model.update_bounds();
+    assert_eq!(model.bounds(), Some(rest));
+    assert!((rest.bounding_box.max.y - 17.0).abs() < 0.0001);
+
+    // Sam swings the arms and legs around.
+    let animated = match sam.animated_bounds() {
+      Some(animated) => animated,
+      None => panic!("minetest_sam has no animated bounds!"),
+    };
+    assert!(animated.bounding_box.max.y > rest.bounding_box.max.y);
+    assert!(animated.bounding_box.size().z > rest.bounding_box.size().z);
+    assert!(animated.bounding_sphere.radius > rest.bounding_sphere.radius);
+
+    // Every keyframe is held, even though they're folded together one at a time.
+    let frames = match sam.animated_frames() {
+      Some(frames) => frames,
+      None => panic!("minetest_sam isn't animated!"),
+    };
+    for position in frames.flatten() {
+      assert!(animated.bounding_box.contains(position));
+      assert!(
+        animated.bounding_sphere.center.distance(position)
+          <= animated.bounding_sphere.radius + 0.0001
+      );
+    }
+  }
+
+  #[test]
+  fn sam_entity_box() {
+    drop(env_logger::try_init());
+
+    let sam = match load("tests/minetest_sam.gltf") {
+      Ok(sam) => sam,
+      Err(e) => panic!("minetest_sam: failed to load. {}", e),
+    };
+
+    let rest = match sam.entity_box(&entity_box::EntityBoxOptions::default()) {
+      Some(rest) => rest,
+      None => panic!("minetest_sam has no entity box!"),
+    };
+    assert_eq!(rest.to_lua(), "{-0.42, 0, -0.23, 0.42, 1.7, 0.23}");
+
+    // Trimming vertices can only shrink the box.
+    let tight = match sam.entity_box(&entity_box::EntityBoxOptions {
+      percentile: Some(0.8),
+      ..Default::default()
+    }) {
+      Some(tight) => tight,
+      None => panic!("minetest_sam has no tight entity box!"),
+    };
+    assert!(tight.min.cmpge(rest.min).all() && tight.max.cmple(rest.max).all());
+    assert!(tight.max.y < rest.max.y);
+
+    let animated = match sam.entity_box(&entity_box::EntityBoxOptions {
+      pose: entity_box::BoxPose::Animated,
+      visual_size: glam::Vec3::splat(2.0),
+      ..Default::default()
+    }) {
+      Some(animated) => animated,
+      None => panic!("minetest_sam has no animated entity box!"),
+    };
+    assert!(animated.max.y > rest.max.y * 2.0);
+
+    // Trimmed animated boxes find the same values as sorting every vertex of every keyframe.
+    let trimmed = match sam.entity_box(&entity_box::EntityBoxOptions {
+      pose: entity_box::BoxPose::Animated,
+      percentile: Some(0.9),
+      ..Default::default()
+    }) {
+      Some(trimmed) => trimmed,
+      None => panic!("minetest_sam has no trimmed animated entity box!"),
+    };
+    let conversion = coordinates::CoordinateConversion::default();
+    let positions: Vec<glam::Vec3> = match sam.animated_frames() {
+      Some(frames) => frames
+        .flatten()
+        .map(|position| conversion.position(position) * (glam::Vec3::ONE / coordinates::BS))
+        .collect(),
+      None => panic!("minetest_sam isn't animated!"),
+    };
+    let last = positions.len() - 1;
+    let trim = ((0.1 * 0.5 * last as f32).round() as usize).min(last / 2);
+    for axis in 0..3 {
+      let mut values: Vec<f32> = positions.iter().map(|position| position[axis]).collect();
+      values.sort_by(f32::total_cmp);
+      assert_eq!(trimmed.min[axis], values[trim]);
+      assert_eq!(trimmed.max[axis], values[last - trim]);
+    }
+  }
+
+  #[test]
+  fn optimize_primitives() {
+    drop(env_logger::try_init());
+
+    // Sorted triangles, by the bits of the corner positions.
+    let triangle_soup = |primitive: &Primitive| -> Vec<[[u32; 3]; 3]> {
+      let mut soup: Vec<[[u32; 3]; 3]> = match primitive.triangles() {
+        Ok(triangles) => triangles
+          .iter()
+          .map(|triangle| triangle.map(|vertex| vertex.position.to_array().map(f32::to_bits)))
+          .collect(),
+        Err(e) => panic!("not triangles! {}", e),
+      };
+      soup.sort();
+      soup
+    };
+
+    let options = LoadOptions {
+      optimization: Some(primitive::OptimizeOptions::default()),
+      ..Default::default()
+    };
+
+    // ? Spider has no shared vertices to begin with, so the cache can't do any better.
+    for (model_name, primitive_index, improves) in [
+      ("spider_animated.gltf", 0, false),
+      ("head.glb", 0, true),
+      ("complete.glb", 19, true),
+    ] {
+      let path = format!("tests/{}", model_name);
+      let (original, optimized) = match (load(&path), load_with_options(&path, &options)) {
+        (Ok(original), Ok(optimized)) => (original, optimized),
+        _ => panic!("{}: failed to load.", model_name),
+      };
+      let (original, optimized) = match (&original.model, &optimized.model) {
+        (Some(original), Some(optimized)) => (
+          &original.primitives[primitive_index],
+          &optimized.primitives[primitive_index],
+        ),
+        _ => panic!("{}: lost the model!", model_name),
+      };
+
+      assert_eq!(triangle_soup(original), triangle_soup(optimized));
+      assert!(optimized.indices().is_some());
+      assert!(optimized.vertices.len() <= original.vertices.len());
+      assert_eq!(
+        optimized.joints.len(),
+        original.joints.len().min(optimized.vertices.len())
+      );
+      let (before, after) = (
+        original.average_cache_miss_ratio(16),
+        optimized.average_cache_miss_ratio(16),
+      );
+      assert!(after <= before, "{}: {} -> {}", model_name, before, after);
+      if improves {
+        assert!(
+          after < before * 0.5,
+          "{}: {} -> {}",
+          model_name,
+          before,
+          after
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn simplify_head_lods() {
+    drop(env_logger::try_init());
+
+    let head = match load("tests/head.glb") {
+      Ok(head) => head,
+      Err(e) => panic!("head: failed to load. {}", e),
+    };
+    let original = match &head.model {
+      Some(model) => &model.primitives[0],
+      None => panic!("head has no model!"),
+    };
+    let triangle_count = |primitive: &Primitive| match primitive.triangles() {
+      Ok(triangles) => triangles.len(),
+      Err(e) => panic!("head isn't triangles! {}", e),
+    };
+    let vertex_key = |vertex: &primitive::Vertex| {
+      [
+        vertex.position.to_array().as_slice(),
+        vertex.tex_coords.to_array().as_slice(),
+      ]
+      .concat()
+      .into_iter()
+      .map(f32::to_bits)
+      .collect::<Vec<u32>>()
+    };
+    let original_vertices: std::collections::HashSet<Vec<u32>> =
+      original.vertices.iter().map(vertex_key).collect();
+
+    // Vertices that share a position with another vertex are on a UV seam.
+    let mut position_uses: std::collections::HashMap<[u32; 3], usize> =
+      std::collections::HashMap::new();
+    for vertex in &original.vertices {
+      *position_uses
+        .entry(vertex.position.to_array().map(f32::to_bits))
+        .or_default() += 1;
+    }
+    let seam_vertices: Vec<Vec<u32>> = original
+      .vertices
+      .iter()
+      .filter(|vertex| position_uses[&vertex.position.to_array().map(f32::to_bits)] > 1)
+      .map(vertex_key)
+      .collect();
+    assert!(!seam_vertices.is_empty());
+
+    let lods = match original.generate_lods(&[0.5, 0.25]) {
+      Ok(lods) => lods,
+      Err(e) => panic!("head: failed to simplify. {}", e),
+    };
+    let mut previous = triangle_count(original);
+    for (lod, ratio) in lods.iter().zip([0.5, 0.25]) {
+      let count = triangle_count(lod);
+      assert!(count > 0 && count < previous);
+      assert!(count as f32 <= (triangle_count(original) as f32 * ratio).ceil());
+      previous = count;
+
+      // Vertices only ever move onto other vertices, and seams stay put.
+      let lod_vertices: std::collections::HashSet<Vec<u32>> =
+        lod.vertices.iter().map(vertex_key).collect();
+      assert!(lod_vertices.is_subset(&original_vertices));
+      for seam_vertex in &seam_vertices {
+        assert!(lod_vertices.contains(seam_vertex));
+      }
+    }
+
+    // Every skinned vertex that's left keeps the joints and weights it had.
+    let spider = match load("tests/spider_animated.gltf") {
+      Ok(spider) => spider,
+      Err(e) => panic!("spider: failed to load. {}", e),
+    };
+    let original = match &spider.model {
+      Some(model) => &model.primitives[0],
+      None => panic!("spider has no model!"),
+    };
+    let lod = match original.simplify(0.5) {
+      Ok(lod) => lod,
+      Err(e) => panic!("spider: failed to simplify. {}", e),
+    };
+    assert_eq!(lod.joints.len(), lod.vertices.len());
+    for (i, vertex) in lod.vertices.iter().enumerate() {
+      let found = original.vertices.iter().enumerate().any(|(j, other)| {
+        vertex_key(other) == vertex_key(vertex)
+          && original.joints[j] == lod.joints[i]
+          && original.weights[j] == lod.weights[i]
+      });
+      assert!(found);
+    }
+  }
+
+  #[test]
+  fn topology_lists() {
+    use crate::primitive::Mode;
+
+    let spider = match load("tests/spider_animated.gltf") {
+      Ok(spider) => spider,
+      Err(e) => panic!("spider: failed to load. {}", e),
+    };
+    let original = match &spider.model {
+      Some(model) => &model.primitives[0],
+      None => panic!("spider has no model!"),
+    };
+
+    let to_list = |primitive: &Primitive| match primitive.to_triangle_list() {
+      Ok(list) => list,
+      Err(e) => panic!("failed to make a triangle list. {}", e),
+    };
+
+    // Odd strip triangles swap their first two corners, so the winding stays the same.
+    let mut strip = original.clone();
+    strip.mode = Mode::TriangleStrip;
+    strip.indices = Some(vec![0, 1, 2, 3, 3, 4, 4, 5, 6]);
+    let list = to_list(&strip);
+    assert_eq!(list.mode, Mode::Triangles);
+    assert_eq!(list.indices, Some(vec![0, 1, 2, 2, 1, 3, 4, 5, 6]));
+    assert_eq!(list.vertices.len(), original.vertices.len());
+    assert_eq!(list.joints, original.joints);
+    assert_eq!(list.weights, original.weights);
+
+    let mut fan = original.clone();
+    fan.mode = Mode::TriangleFan;
+    fan.indices = None;
+    fan.vertices.truncate(4);
+    assert_eq!(to_list(&fan).indices, Some(vec![0, 1, 2, 0, 2, 3]));
+
+    assert!(original.to_line_list().is_err());
+
+    let complete = match load("tests/complete.glb") {
+      Ok(complete) => complete,
+      Err(e) => panic!("complete: failed to load. {}", e),
+    };
+    let model = match &complete.model {
+      Some(model) => model,
+      None => panic!("complete has no model!"),
+    };
+    let mut checked = 0;
+    for primitive in &model.primitives {
+      let lines = match primitive.lines() {
+        Ok(lines) => lines,
+        Err(_) => continue,
+      };
+      let list = match primitive.to_line_list() {
+        Ok(list) => list,
+        Err(e) => panic!("failed to make a line list. {}", e),
+      };
+      assert_eq!(list.mode, Mode::Lines);
+      let list_lines = match list.lines() {
+        Ok(list_lines) => list_lines,
+        Err(e) => panic!("failed to read the line list. {}", e),
+      };
+      // Lines have no direction, so a loop may close either way around.
+      assert_eq!(list_lines.len(), lines.len());
+      for (line, expected) in list_lines.iter().zip(&lines) {
+        assert!(*line == *expected || [line[1], line[0]] == *expected);
+      }
+      checked += 1;
+    }
+    assert!(checked >= 3);
+  }
+
+  #[test]
+  fn split_u16_indices() {
+    use crate::primitive::{Mode, MAX_U16_VERTICES};
+
+    let spider = match load("tests/spider_animated.gltf") {
+      Ok(spider) => spider,
+      Err(e) => panic!("spider: failed to load. {}", e),
+    };
+    let original = match &spider.model {
+      Some(model) => &model.primitives[0],
+      None => panic!("spider has no model!"),
+    };
+    let triangles = |primitive: &Primitive| match primitive.triangles() {
+      Ok(triangles) => triangles,
+      Err(e) => panic!("failed to get triangles. {}", e),
+    };
+
+    // Small enough already.
+    assert_eq!(original.split(MAX_U16_VERTICES).len(), 1);
+
+    let chunks = original.split(300);
+    assert!(chunks.len() >= 4);
+    let mut split_triangles = vec![];
+    for chunk in &chunks {
+      assert!(chunk.vertices.len() <= 300);
+      assert_eq!(chunk.mode, Mode::Triangles);
+      assert_eq!(chunk.material, original.material);
+      assert_eq!(chunk.texture_slot, original.texture_slot);
+      assert_eq!(chunk.joints.len(), chunk.vertices.len());
+      assert_eq!(chunk.weights.len(), chunk.vertices.len());
+      assert!(chunk.indices_u16().is_some());
+
+      // Every vertex keeps its own joints and weights.
+      for (i, vertex) in chunk.vertices.iter().enumerate() {
+        let found = original.vertices.iter().enumerate().any(|(j, other)| {
+          other == vertex
+            && original.joints[j] == chunk.joints[i]
+            && original.weights[j] == chunk.weights[i]
+        });
+        assert!(found);
+      }
+      split_triangles.extend(triangles(chunk));
+    }
+    assert_eq!(split_triangles, triangles(original));
+
+    // Too many vertices for 16 bit indices.
+    let points = Primitive {
+      mode: Mode::Points,
+      vertices: vec![Default::default(); MAX_U16_VERTICES + 10],
+      ..Default::default()
+    };
+    assert!(points.indices_u16().is_none());
+    let mut model = Model::from_primitives(vec![points]);
+    model.primitives[0].texture_slot = 2;
+    model.split_for_u16_indices();
+    assert_eq!(model.primitives.len(), 2);
+    for chunk in &model.primitives {
+      assert_eq!(chunk.texture_slot, 2);
+      assert!(chunk.indices_u16().is_some());
+    }
+  }
+
+  #[test]
+  fn vertex_layout() {
+    use crate::primitive::{Attribute, Format, LayoutError, VertexLayout};
+
+    let read_f32 = |data: &[u8], offset: usize| {
+      f32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+      ])
+    };
+
+    // Colors and a texture coordinate set that isn't there.
+    let complete = match load("tests/complete.glb") {
+      Ok(complete) => complete,
+      Err(e) => panic!("complete: failed to load. {}", e),
+    };
+    let primitive = match complete
+      .model
+      .iter()
+      .flat_map(|model| &model.primitives)
+      .find(|primitive| primitive.has_colors())
+    {
+      Some(primitive) => primitive,
+      None => panic!("complete has no vertex colors!"),
+    };
+    assert_eq!(primitive.colors.len(), primitive.vertices.len());
+
+    let layout = VertexLayout::new()
+      .with(Attribute::Position, Format::Float32x3)
+      .with(Attribute::Color, Format::Unorm8x4)
+      .with(Attribute::TexCoords(1), Format::Float32x2);
+    assert_eq!(layout.stride(), 24);
+    let buffer = match primitive.vertex_buffer(&layout) {
+      Ok(buffer) => buffer,
+      Err(e) => panic!("complete: failed to build the vertex buffer. {}", e),
+    };
+    assert_eq!(buffer.data.len(), primitive.vertices.len() * 24);
+    for (i, vertex) in primitive.vertices.iter().enumerate() {
+      let start = i * buffer.stride;
+      for axis in 0..3 {
+        assert_eq!(
+          read_f32(&buffer.data, start + axis * 4),
+          vertex.position[axis]
+        );
+      }
+      for (channel, value) in primitive.colors[i].iter().enumerate() {
+        assert_eq!(
+          buffer.data[start + 12 + channel],
+          (value * 255.0).round() as u8
+        );
+      }
+      assert_eq!(read_f32(&buffer.data, start + 16), 0.0);
+    }
+
+    // Skin attributes.
+    let spider = match load("tests/spider_animated.gltf") {
+      Ok(spider) => spider,
+      Err(e) => panic!("spider: failed to load. {}", e),
+    };
+    let primitive = match &spider.model {
+      Some(model) => &model.primitives[0],
+      None => panic!("spider has no model!"),
+    };
+    let layout = VertexLayout::new()
+      .with(Attribute::Joints, Format::Uint16x4)
+      .with(Attribute::Weights, Format::Float32x4)
+      .with(Attribute::TexCoords(0), Format::Unorm16x2);
+    let buffer = match layout.build(primitive) {
+      Ok(buffer) => buffer,
+      Err(e) => panic!("spider: failed to build the vertex buffer. {}", e),
+    };
+    assert_eq!(buffer.stride, 28);
+    assert_eq!(buffer.elements[2].offset, 24);
+    for (i, (joints, weights)) in primitive.joints.iter().zip(&primitive.weights).enumerate() {
+      let start = i * buffer.stride;
+      for component in 0..4 {
+        let joint = u16::from_le_bytes([
+          buffer.data[start + component * 2],
+          buffer.data[start + component * 2 + 1],
+        ]);
+        assert_eq!(joint, joints[component]);
+        assert_eq!(
+          read_f32(&buffer.data, start + 8 + component * 4),
+          weights[component]
+        );
+      }
+    }
+
+    // Joints need integers, and nothing else takes them.
+    let bad = VertexLayout::new().with(Attribute::Joints, Format::Float32x4);
+    assert_eq!(
+      bad.validate(),
+      Err(LayoutError::BadFormat {
+        attribute: Attribute::Joints,
+        format: Format::Float32x4,
+      })
+    );
+    let bad = VertexLayout::new().with(Attribute::Position, Format::Float32x2);
+    assert!(bad.build(primitive).is_err());
+  }
+
+  #[test]
+  fn quantize_attributes() {
+    use crate::primitive::{f16_to_f32, f32_to_f16, octahedral_decode};
+
+    assert_eq!(f32_to_f16(1.0), 0x3c00);
+    assert_eq!(f32_to_f16(-0.5), 0xb800);
+    assert_eq!(f32_to_f16(65504.0), 0x7bff);
+    assert_eq!(f32_to_f16(100000.0), 0x7c00);
+    assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+    for value in [0.0, 0.25, 0.333, -1.5, 1.0e-6, 2048.0] {
+      let error = (f16_to_f32(f32_to_f16(value)) - value).abs();
+      assert!(error <= value.abs() / 2048.0 + 2f32.powi(-25));
+    }
+
+    let head = match load("tests/head.glb") {
+      Ok(head) => head,
+      Err(e) => panic!("head: failed to load. {}", e),
+    };
+    let primitive = match &head.model {
+      Some(model) => &model.primitives[0],
+      None => panic!("head has no model!"),
+    };
+    let quantized = primitive.quantize();
+    let report = quantized.report;
+    assert_eq!(quantized.positions.len(), primitive.vertices.len());
+
+    // Half a step of 16 bits on every axis.
+    let size = primitive.bounds().bounding_box.size().max_element();
+    assert!(report.position > 0.0);
+    assert!(report.position <= size / 65534.0 * 3f32.sqrt());
+    for (vertex, position) in primitive.vertices.iter().zip(&quantized.positions) {
+      let decoded = quantized.dequantization.position(*position);
+      assert!(decoded.distance(vertex.position) <= report.position);
+      let normalized = glam::Vec3::from_array(position.map(|value| value as f32 / 32767.0));
+      let matrix = quantized.dequantization.matrix();
+      assert!(matrix
+        .transform_point3(normalized)
+        .abs_diff_eq(decoded, 0.0001));
+    }
+
+    assert!(primitive.has_normals());
+    assert!(report.normal < 0.001);
+    for (vertex, normal) in primitive.vertices.iter().zip(&quantized.normals) {
+      assert!(octahedral_decode(*normal).abs_diff_eq(vertex.normal, 0.001));
+    }
+    assert!(report.tex_coords < 0.001);
+
+    // Moved vertices are quantized without refreshing the cached bounds.
+    let mut moved = primitive.clone();
+    for vertex in &mut moved.vertices {
+      vertex.position *= 4.0;
+    }
+    let quantized = moved.quantize();
+    assert!(quantized.report.position <= size * 4.0 / 65534.0 * 3f32.sqrt());
+    for (vertex, position) in moved.vertices.iter().zip(&quantized.positions) {
+      let decoded = quantized.dequantization.position(*position);
+      assert!(decoded.distance(vertex.position) <= quantized.report.position);
+    }
+
+    // Weights still add up.
+    let spider = match load("tests/spider_animated.gltf") {
+      Ok(spider) => spider,
+      Err(e) => panic!("spider: failed to load. {}", e),
+    };
+    let primitive = match &spider.model {
+      Some(model) => &model.primitives[0],
+      None => panic!("spider has no model!"),
+    };
+    let quantized = primitive.quantize();
+    assert_eq!(quantized.weights.len(), primitive.weights.len());
+    assert!(quantized.report.weights <= 2.0 / 255.0);
+    for (weights, original) in quantized.weights.iter().zip(&primitive.weights) {
+      if (original.iter().sum::<f32>() - 1.0).abs() < 0.01 {
+        assert_eq!(
+          weights.iter().map(|weight| *weight as u32).sum::<u32>(),
+          255
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn load_mesh_quantization() {
+    use glam::{Vec2, Vec3, Vec4};
+
+    let quantized = match load("tests/quantized.gltf") {
+      Ok(quantized) => quantized,
+      Err(e) => panic!("quantized: failed to load. {}", e),
+    };
+    let primitive = match &quantized.model {
+      Some(model) => &model.primitives[0],
+      None => panic!("quantized has no model!"),
+    };
+    assert!(primitive.has_normals() && primitive.has_tangents() && primitive.has_tex_coords());
+
+    // Unsigned shorts, dequantized by the node scale.
+    let positions = [
+      Vec3::new(1.0, 0.0, 0.0),
+      Vec3::new(2.0, 0.0, 0.0),
+      Vec3::new(1.0, 1.0, 0.5),
+    ];
+    // Normalized unsigned shorts.
+    let tex_coords = [
+      Vec2::new(0.0, 0.0),
+      Vec2::new(1.0, 0.0),
+      Vec2::new(0.0, 32768.0 / 65535.0),
+    ];
+    for (i, vertex) in primitive.vertices.iter().enumerate() {
+      assert!(vertex.position.abs_diff_eq(positions[i], 0.00001));
+      // Normalized bytes.
+      assert!(vertex.normal.abs_diff_eq(Vec3::Z, 0.00001));
+      assert!(vertex
+        .tangent
+        .abs_diff_eq(Vec4::new(1.0, 0.0, 0.0, -1.0), 0.00001));
+      assert!(vertex.tex_coords.abs_diff_eq(tex_coords[i], 0.00001));
+    }
+    // Shorts that aren't normalized.
+    assert_eq!(
+      primitive.extra_tex_coords,
+      vec![vec![
+        Vec2::new(1.0, 2.0),
+        Vec2::new(3.0, 4.0),
+        Vec2::new(-5.0, 6.0)
+      ]]
+    );
+
+    // The accessor min and max are in the same units as the positions.
+    let bounding_box = primitive.bounds().bounding_box;
+    assert!(bounding_box
+      .min
+      .abs_diff_eq(Vec3::new(1.0, 0.0, 0.0), 0.00001));
+    assert!(bounding_box
+      .max
+      .abs_diff_eq(Vec3::new(2.0, 1.0, 0.5), 0.00001));
+  }
+
+  #[test]
+  fn load_meshopt_compression() {
+    let load_model = |path: &str| match load(path) {
+      Ok(model) => model,
+      Err(e) => panic!("{}: failed to load. {}", path, e),
+    };
+    let original = load_model("tests/spider_animated.gltf");
+    let compressed = load_model("tests/spider_meshopt.glb");
+
+    let (original_model, compressed_model) = match (&original.model, &compressed.model) {
+      (Some(original), Some(compressed)) => (original, compressed),
+      _ => panic!("spider has no model!"),
+    };
+    let expected = &original_model.primitives[0];
+    // The triangle codec may rotate the corners of a triangle, but keeps the winding.
+    let rotated = |indices: &Option<Vec<u32>>| -> Vec<[u32; 3]> {
+      indices
+        .iter()
+        .flatten()
+        .copied()
+        .collect::<Vec<u32>>()
+        .chunks_exact(3)
+        .map(|triangle| {
+          let first = (0..3).fold(
+            0,
+            |min, i| if triangle[i] < triangle[min] { i } else { min },
+          );
+          [0, 1, 2].map(|i| triangle[(first + i) % 3])
+        })
+        .collect()
+    };
+    // The second primitive has the same triangles, compressed as an index sequence.
+    assert_eq!(compressed_model.primitives.len(), 2);
+    assert_eq!(compressed_model.primitives[1].indices, expected.indices);
+    for primitive in &compressed_model.primitives {
+      assert_eq!(rotated(&primitive.indices), rotated(&expected.indices));
+      assert_eq!(primitive.joints, expected.joints);
+      assert_eq!(primitive.weights, expected.weights);
+      assert_eq!(primitive.vertices.len(), expected.vertices.len());
+      for (vertex, expected) in primitive.vertices.iter().zip(&expected.vertices) {
+        assert_eq!(vertex.position, expected.position);
+        assert_eq!(vertex.tex_coords, expected.tex_coords);
+        // Octahedral bytes.
+        assert!(vertex.normal.abs_diff_eq(expected.normal, 0.02));
+      }
+    }
+
+    let (original_animations, compressed_animations) =
+      match (&original.bone_animations, &compressed.bone_animations) {
+        (Some(original), Some(compressed)) => (original, compressed),
+        _ => panic!("spider has no animations!"),
+      };
+    assert_eq!(original_animations.len(), compressed_animations.len());
+    for (id, expected) in original_animations {
+      let channel = match compressed_animations.get(id) {
+        Some(channel) => channel,
+        None => panic!("spider: bone {} has no animation!", id),
+      };
+      assert_eq!(channel.rotation_timestamps, expected.rotation_timestamps);
+      assert_eq!(
+        channel.translation_timestamps,
+        expected.translation_timestamps
+      );
+      // 12 bit quaternions.
+      for (rotation, expected) in channel.rotations.iter().zip(&expected.rotations) {
+        assert!(rotation.dot(*expected).abs() > 0.9999);
+      }
+      // 24 bit mantissas.
+      for (translation, expected) in channel.translations.iter().zip(&expected.translations) {
+        assert!(translation.abs_diff_eq(*expected, 0.0001));
+      }
+    }
+  }
+
+  #[test]
+  fn reject_draco_without_fallback() {
+    // Required, so there is nothing to fall back on.
+    match load("tests/draco.gltf") {
+      Ok(_) => panic!("draco: loaded without any vertex data!"),
+      Err(e) => match e.downcast_ref::<UnsupportedExtensions>() {
+        Some(unsupported) => {
+          assert_eq!(unsupported.extensions, vec!["KHR_draco_mesh_compression"])
+        }
+        None => panic!("draco: wrong error. {}", e),
+      },
+    }
+
+    // Uncompressed positions, but the indices are only in the Draco data.
+    let json = r#"{"asset":{"version":"2.0"},
+      "extensionsUsed":["KHR_draco_mesh_compression"],
+      "scenes":[{"nodes":[0]}],"nodes":[{"mesh":0}],
+      "meshes":[{"primitives":[{"attributes":{"POSITION":0},"indices":1,"extensions":{
+        "KHR_draco_mesh_compression":{"bufferView":1,"attributes":{"POSITION":0}}
+      }}]}],
+      "accessors":[
+        {"bufferView":0,"componentType":5126,"count":3,"type":"VEC3","min":[0,0,0],"max":[1,1,0]},
+        {"componentType":5123,"count":3,"type":"SCALAR"}
+      ],
+      "bufferViews":[{"buffer":0,"byteLength":36},{"buffer":0,"byteOffset":36,"byteLength":4}],
+      "buffers":[{"byteLength":40,
+        "uri":"data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAA=="}]}"#;
+    let path = std::env::temp_dir().join("minetest_gltf_draco_indices.gltf");
+    if let Err(e) = std::fs::write(&path, json) {
+      panic!("failed to write temp gltf. {}", e);
+    }
+    let path = match path.to_str() {
+      Some(path) => path.to_string(),
+      None => panic!("temp dir isn't utf8!"),
+    };
+    match load(&path) {
+      Ok(_) => panic!("draco: loaded without any indices!"),
+      Err(e) => assert!(e.to_string().contains("Draco"), "{}", e),
+    }
+  }
+
+  #[test]
+  fn check_required_extensions() {
+    // Unlit materials don't change the model.
+    let complete = match load("tests/complete.glb") {
+      Ok(complete) => complete,
+      Err(e) => panic!("complete: failed to load. {}", e),
+    };
+    assert_eq!(complete.ignored_extensions, vec!["KHR_materials_unlit"]);
+
+    // The decoded extensions aren't ignored.
+    let spider = match load("tests/spider_meshopt.glb") {
+      Ok(spider) => spider,
+      Err(e) => panic!("spider: failed to load. {}", e),
+    };
+    assert!(spider.ignored_extensions.is_empty());
+
+    // Compressed textures would be missing.
+    match load("tests/basisu.gltf") {
+      Ok(_) => panic!("basisu: loaded without its textures!"),
+      Err(e) => match e.downcast_ref::<UnsupportedExtensions>() {
+        Some(unsupported) => assert_eq!(unsupported.extensions, vec!["KHR_texture_basisu"]),
+        None => panic!("basisu: wrong error. {}", e),
+      },
+    }
+
+    // Only this crate rules on required extensions, whichever gltf version got resolved.
+    let parse = |json: &str| match gltf::json::deserialize::from_str::<gltf::json::Root>(json) {
+      Ok(root) => root,
+      Err(e) => panic!("failed to parse the JSON. {}", e),
+    };
+    let quantized = parse(
+      r#"{"asset":{"version":"2.0"},"extensionsRequired":["KHR_mesh_quantization","EXT_meshopt_compression"]}"#,
+    );
+    if let Err(e) = extensions::validate(&quantized) {
+      panic!("quantized: failed to validate. {}", e);
+    }
+    // Everything else is still validated.
+    let broken = parse(
+      r#"{"asset":{"version":"2.0"},"accessors":[{"componentType":5126,"count":1,"type":"SCALAR"}]}"#,
+    );
+    assert!(extensions::validate(&broken).is_err());
+  }
+
+  #[test]
+  fn texture_transform() {
+    use self::primitive::TextureTransform;
+    use glam::Vec2;
+
+    let atlas = match load("tests/texture_transform.gltf") {
+      Ok(atlas) => atlas,
+      Err(e) => panic!("texture_transform: failed to load. {}", e),
+    };
+    assert!(atlas.ignored_extensions.is_empty());
+    let primitive = match &atlas.model {
+      Some(model) => &model.primitives[0],
+      None => panic!("texture_transform has no model!"),
+    };
+
+    let transforms = primitive.texture_transforms;
+    let base_color = match transforms.base_color {
+      Some(base_color) => base_color,
+      None => panic!("texture_transform: no base color transform!"),
+    };
+    assert_eq!(base_color.offset, Vec2::new(0.5, 0.0));
+    assert_eq!(base_color.scale, Vec2::new(0.5, 0.5));
+    assert_eq!(base_color.tex_coord, 1);
+    // A quarter turn counter-clockwise, with V pointing down.
+    assert!(base_color
+      .apply(Vec2::new(1.0, 0.0))
+      .abs_diff_eq(Vec2::new(0.5, -0.5), 0.00001));
+    assert!(base_color
+      .apply(Vec2::new(0.0, 1.0))
+      .abs_diff_eq(Vec2::new(1.0, 0.0), 0.00001));
+    // The normal map reads the set of its texture reference.
+    match transforms.normal {
+      Some(normal) => assert_eq!(
+        (normal.offset, normal.tex_coord),
+        (Vec2::new(0.25, 0.75), 0)
+      ),
+      None => panic!("texture_transform: no normal transform!"),
+    }
+    assert_eq!(transforms.metallic_roughness, None);
+
+    // Baking transforms the set the texture reads into the first set.
+    let options = LoadOptions {
+      bake_texture_transforms: true,
+      ..Default::default()
+    };
+    let baked = match load_with_options("tests/texture_transform.gltf", &options) {
+      Ok(baked) => baked,
+      Err(e) => panic!("texture_transform: failed to load. {}", e),
+    };
+    let baked = match &baked.model {
+      Some(model) => &model.primitives[0],
+      None => panic!("texture_transform has no model!"),
+    };
+    assert_eq!(baked.texture_transforms.base_color, None);
+    // The normal map still reads the original first set, moved to the end.
+    assert_eq!(baked.extra_tex_coords.len(), 2);
+    match (baked.texture_transforms.normal, transforms.normal) {
+      (Some(baked_normal), Some(normal)) => assert_eq!(
+        baked_normal,
+        TextureTransform {
+          tex_coord: 2,
+          ..normal
+        }
+      ),
+      _ => panic!("texture_transform: no normal transform!"),
+    }
+    for (tex_coords, vertex) in baked.extra_tex_coords[1].iter().zip(&primitive.vertices) {
+      assert_eq!(*tex_coords, vertex.tex_coords);
+    }
+
+    // Merging with a primitive that draws the first set as is bakes both.
+    let mut plain = primitive.clone();
+    plain.texture_transforms = Default::default();
+    let mut model = Model::from_primitives(vec![primitive.clone(), plain]);
+    if let Err(e) = model.merge_texture_slots() {
+      panic!("texture_transform: failed to merge. {}", e);
+    }
+    let merged = &model.primitives[0];
+    assert_eq!(merged.texture_transforms.base_color, None);
+    assert_eq!(merged.texture_transforms.normal, None);
+    let count = primitive.vertices.len();
+    for (i, vertex) in merged.vertices.iter().enumerate() {
+      let expected = if i < count {
+        base_color.apply(primitive.extra_tex_coords[0][i])
+      } else {
+        primitive.vertices[i - count].tex_coords
+      };
+      assert!(vertex.tex_coords.abs_diff_eq(expected, 0.00001));
+    }
+    for (vertex, tex_coords) in baked.vertices.iter().zip(&primitive.extra_tex_coords[0]) {
+      assert!(vertex
+        .tex_coords
+        .abs_diff_eq(base_color.apply(*tex_coords), 0.00001));
+    }
+  }
+
+  #[test]
+  fn load_lights() {
+    use crate::coordinates::{CoordinateConversion, BS};
+    use crate::light::LightKind;
+    use glam::Vec3;
+
+    let cube = match load("tests/cube.glb") {
+      Ok(cube) => cube,
+      Err(e) => panic!("cube: failed to load. {}", e),
+    };
+    assert!(cube.ignored_extensions.is_empty());
+    let lights = match &cube.model {
+      Some(model) => &model.lights,
+      None => panic!("cube has no model!"),
+    };
+    assert_eq!(lights.len(), 3);
+
+    let point = &lights[0];
+    assert_eq!((point.node, point.kind), (1, LightKind::Point));
+    assert!(point
+      .position
+      .abs_diff_eq(Vec3::new(4.076245, 5.903862, -1.005454), 0.0001));
+    assert_eq!(point.range, None);
+    // The reach is where 1000 candela drops to 1 lux.
+    assert!((point.reach() - 1000f32.sqrt()).abs() < 0.01);
+    assert_eq!(point.minetest_light_level(1.0), 14);
+    assert_eq!(point.minetest_light_level(4.0), 8);
+
+    let sun = &lights[1];
+    assert_eq!((sun.node, sun.kind), (9, LightKind::Directional));
+    assert!((sun.direction.length() - 1.0).abs() < 0.0001);
+    assert_eq!(sun.minetest_light_level(1.0), 14);
+
+    let spot = &lights[2];
+    assert_eq!(spot.node, 12);
+    match spot.kind {
+      LightKind::Spot {
+        inner_cone_angle,
+        outer_cone_angle,
+      } => assert!(inner_cone_angle < outer_cone_angle),
+      _ => panic!("cube: the spot light isn't a spot light!"),
+    }
+    // The brightest channel counts.
+    assert!((spot.reach() - 42f32.sqrt()).abs() < 0.01);
+    assert_eq!(spot.minetest_light_level(1.0), 7);
+
+    // A range closer than the reach cuts it off, and no light gives off nothing.
+    let mut short = point.clone();
+    short.range = Some(2.5);
+    assert_eq!(short.minetest_light_level(1.0), 3);
+    short.intensity = 0.0;
+    assert_eq!(short.minetest_light_level(1.0), 0);
+
+    // Converted lights reach as far, in nodes.
+    let mut converted = point.clone();
+    CoordinateConversion::minetest().convert_light(&mut converted);
+    assert!(converted
+      .position
+      .abs_diff_eq(Vec3::new(4.076245, 5.903862, 1.005454) * BS, 0.001));
+    assert_eq!(converted.minetest_light_level(BS), 14);
+    let mut converted_spot = spot.clone();
+    CoordinateConversion::minetest().convert_light(&mut converted_spot);
+    assert_eq!(converted_spot.minetest_light_level(BS), 7);
+    assert!(converted_spot
+      .direction
+      .abs_diff_eq(spot.direction * Vec3::new(1.0, 1.0, -1.0), 0.0001));
+  }
+
+  #[test]
+  fn load_cameras() {
+    use crate::camera::Projection;
+    use crate::coordinates::CoordinateConversion;
+    use glam::{Vec2, Vec3};
+
+    // Cameras that aren't animated are placed at rest.
+    let complete = match load("tests/complete.glb") {
+      Ok(complete) => complete,
+      Err(e) => panic!("complete: failed to load. {}", e),
+    };
+    let cameras = match &complete.model {
+      Some(model) => &model.cameras,
+      None => panic!("complete has no model!"),
+    };
+    assert_eq!(cameras.len(), 2);
+    assert_eq!(cameras[0].node, 0);
+    assert!(cameras[0]
+      .position()
+      .abs_diff_eq(Vec3::new(789.8062, 400.0, 127.30347), 0.001));
+    assert_eq!(
+      cameras[0].projection,
+      Projection::Perspective {
+        yfov: std::f32::consts::FRAC_PI_4,
+        aspect_ratio: Some(1.6731182),
+      }
+    );
+    assert_eq!(
+      cameras[1].projection,
+      Projection::Orthographic {
+        scale: Vec2::new(1556.0, 930.0),
+      }
+    );
+    assert_eq!((cameras[1].znear, cameras[1].zfar), (0.1, 10.0));
+
+    // A cutscene without a skin still loads its animation.
+    let cutscene = match load("tests/camera_cutscene.gltf") {
+      Ok(cutscene) => cutscene,
+      Err(e) => panic!("camera_cutscene: failed to load. {}", e),
+    };
+    let camera = match &cutscene.model {
+      Some(model) => &model.cameras[0],
+      None => panic!("camera_cutscene has no model!"),
+    };
+    let bone_animations = match &cutscene.bone_animations {
+      Some(bone_animations) => bone_animations,
+      None => panic!("camera_cutscene isn't animated!"),
+    };
+    assert_eq!(camera.node, 2);
+    assert_eq!(
+      camera.path.iter().map(|node| node.id).collect::<Vec<i32>>(),
+      vec![1, 2]
+    );
+    assert!(camera
+      .sample(bone_animations, 0.0)
+      .abs_diff_eq(camera.transform, 0.00001));
+
+    // The rig moves, the camera turns, and the camera keeps its rest offset from the rig.
+    let halfway = camera.sample(bone_animations, 1.0);
+    assert!(halfway
+      .transform_point3(Vec3::ZERO)
+      .abs_diff_eq(Vec3::new(5.0, 1.0, 5.0), 0.0001));
+    let half_turn = std::f32::consts::FRAC_1_SQRT_2;
+    assert!(halfway
+      .transform_vector3(Vec3::NEG_Z)
+      .abs_diff_eq(Vec3::new(-half_turn, 0.0, -half_turn), 0.0001));
+
+    // Converted cameras sample into the converted space.
+    let conversion = CoordinateConversion::minetest();
+    let mut converted = match load("tests/camera_cutscene.gltf") {
+      Ok(converted) => converted,
+      Err(e) => panic!("camera_cutscene: failed to load. {}", e),
+    };
+    conversion.convert(&mut converted);
+    let (converted_camera, converted_animations) =
+      match (&converted.model, &converted.bone_animations) {
+        (Some(model), Some(bone_animations)) => (&model.cameras[0], bone_animations),
+        _ => panic!("camera_cutscene: conversion lost the camera!"),
+      };
+    assert!(converted_camera
+      .sample(converted_animations, 1.0)
+      .abs_diff_eq(conversion.transform(&halfway), 0.001));
+
+    // Cutscenes don't need any meshes.
+    let camera_only = match load("tests/camera_only.gltf") {
+      Ok(camera_only) => camera_only,
+      Err(e) => panic!("camera_only: failed to load. {}", e),
+    };
+    let (model, bone_animations) = match (&camera_only.model, &camera_only.bone_animations) {
+      (Some(model), Some(bone_animations)) => (model, bone_animations),
+      _ => panic!("camera_only: lost the camera or its animation!"),
+    };
+    assert!(model.primitives.is_empty());
+    assert!(model.cameras[0]
+      .sample(bone_animations, 1.0)
+      .abs_diff_eq(halfway, 0.0001));
+  }
+
+  #[test]
+  fn b3d_bind_pose() {
+    drop(env_logger::try_init());
+
+    // Move a joint away from the pose the skin was bound in.
+    let mut sam = match load("tests/minetest_sam.gltf") {
+      Ok(sam) => sam,
+      Err(e) => panic!("minetest_sam: failed to load. {}", e),
+    };
+    if let Some(skeleton) = &mut sam.skeleton {
+      let joint = skeleton.joints[1] as usize;
+      skeleton.nodes[joint].translation += glam::Vec3::new(0.0, 2.0, 0.0);
+    }
+    let bytes = match b3d::write_b3d(&sam) {
+      Ok(bytes) => bytes,
+      Err(e) => panic!("minetest_sam: failed to write b3d. {}", e),
+    };
+    let round_trip = match b3d::read_b3d(&bytes, "minetest_sam.b3d") {
+      Ok(round_trip) => round_trip,
+      Err(e) => panic!("minetest_sam: failed to read b3d. {}", e),
+    };
+
+    // Minetest binds to the NODE chunks, so they hold the bind pose and the skin deforms the same.
+    // The reader puts the vertices in a different order, so the triangles get matched up.
+    let skinned =
+      |minetest_gltf: &minetest_gltf::MinetestGLTF, time: f32| -> Vec<[glam::Vec3; 3]> {
+        let (skeleton, bone_animations, primitive) = match (
+          &minetest_gltf.skeleton,
+          &minetest_gltf.bone_animations,
+          &minetest_gltf.model,
+        ) {
+          (Some(skeleton), Some(bone_animations), Some(model)) => {
+            (skeleton, bone_animations, &model.primitives[0])
+          }
+          _ => panic!("sam lost the skin!"),
+        };
+        let positions = minetest_gltf.skinned_positions(
+          skeleton,
+          &animation::Pose::sample_with_rest(skeleton, bone_animations, time),
+        );
+        match primitive.triangle_indices() {
+          Ok(triangles) => triangles
+            .iter()
+            .map(|triangle| triangle.map(|index| positions[index as usize]))
+            .collect(),
+          Err(e) => panic!("sam isn't triangles! {}", e),
+        }
+      };
+    for time in [0.0, 0.5, 2.0] {
+      let original = skinned(&sam, time);
+      let read = skinned(&round_trip, time);
+      assert_eq!(original.len(), read.len());
+      for triangle in &original {
+        assert!(read.iter().any(|other| triangle
+          .iter()
+          .zip(other)
+          .all(|(a, b)| a.abs_diff_eq(*b, 0.001))));
+      }
+    }
+
+    // A sheared bind pose can't be written.
+    if let Some(skeleton) = &mut sam.skeleton {
+      skeleton.inverse_bind_matrices[0] = glam::Mat4::from_cols_array(&[
+        1.0, 0.0, 0.0, 0.0, 0.5, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+      ]);
+    }
+    assert!(b3d::write_b3d(&sam).is_err());
+
+    // TRIS chunks come out in texture slot order, like the textures of the entity.
+    let slots = match load("tests/texture_slots.gltf") {
+      Ok(slots) => slots,
+      Err(e) => panic!("texture_slots: failed to load. {}", e),
+    };
+    let bytes = match b3d::write_b3d(&slots) {
+      Ok(bytes) => bytes,
+      Err(e) => panic!("texture_slots: failed to write b3d. {}", e),
+    };
+    let mut triangles = vec![];
+    find_b3d_chunks(&bytes, b"TRIS", &mut triangles);
+    let brushes: Vec<i32> = triangles
+      .iter()
+      .map(|chunk| i32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+      .collect();
+    assert_eq!(brushes, vec![0, 0, 1, 2]);
+  }
+
+  #[test]
+  fn meshopt_bad_sizes() {
+    // A whole lot of vertices out of a few bytes.
+    assert!(meshopt::decode_vertex_buffer(&[0xa0; 64], usize::MAX / 2, 4).is_err());
+
+    // An index sequence decoded into a single buffer view.
+    let load_view = |name: &str, data: &str, count: usize, length: usize| {
+      let json = format!(
+        r#"{{"asset":{{"version":"2.0"}},
+        "extensionsUsed":["EXT_meshopt_compression"],
+        "extensionsRequired":["EXT_meshopt_compression"],
+        "buffers":[
+          {{"byteLength":{compressed},"uri":"data:application/octet-stream;base64,{data}"}},
+          {{"byteLength":{length},"extensions":{{"EXT_meshopt_compression":{{"fallback":true}}}}}}
+        ],
+        "bufferViews":[{{"buffer":1,"byteLength":{length},"extensions":{{"EXT_meshopt_compression":
+          {{"buffer":0,"byteLength":{compressed},"byteStride":2,"count":{count},"mode":"INDICES"}}
+        }}}}],
+        "scenes":[{{"nodes":[]}}]}}"#,
+        compressed = data.len() / 4 * 3 - data.matches('=').count()
+      );
+      let path = std::env::temp_dir().join(format!("minetest_gltf_meshopt_{}.gltf", name));
+      if let Err(e) = std::fs::write(&path, json) {
+        panic!("failed to write temp gltf. {}", e);
+      }
+      match path.to_str() {
+        Some(path) => load(path),
+        None => panic!("temp dir isn't utf8!"),
+      }
+    };
+    // Index 70000 in 2 bytes.
+    match load_view("overflow", "0cCLEQAAAAA=", 1, 2) {
+      Ok(_) => panic!("overflow: loaded a truncated index!"),
+      Err(e) => assert!(e.to_string().contains("doesn't fit"), "{}", e),
+    }
+    // 2 indices in a 2 byte view.
+    match load_view("mismatch", "0RwEAAAAAA==", 2, 2) {
+      Ok(_) => panic!("mismatch: loaded half the indices!"),
+      Err(e) => assert!(e.to_string().contains("don't fill"), "{}", e),
+    }
+  }
 }
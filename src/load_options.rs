@@ -0,0 +1,32 @@
+use crate::{
+  animation::ReductionTolerance, coordinates::CoordinateConversion, primitive::OptimizeOptions,
+};
+
+///
+/// Optional processing that is run while loading a model.
+///
+/// Everything is turned off by default.
+///
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions {
+  ///
+  /// If set, the bone animation keyframes are reduced with this tolerance after they're loaded.
+  ///
+  pub keyframe_reduction: Option<ReductionTolerance>,
+
+  ///
+  /// If set, the model is converted into the minetest coordinate system after it's loaded.
+  ///
+  pub coordinate_conversion: Option<CoordinateConversion>,
+
+  ///
+  /// If set, every primitive is run through the optimization pipeline after it's loaded.
+  ///
+  pub optimization: Option<OptimizeOptions>,
+
+  ///
+  /// If set, the base color texture transform of every primitive is baked into its texture
+  /// coordinates after it's loaded. See `Primitive::bake_texture_transform`.
+  ///
+  pub bake_texture_transforms: bool,
+}
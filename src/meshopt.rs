@@ -0,0 +1,616 @@
+//! `EXT_meshopt_compression` support.
+//!
+//! gltfpack compresses buffer views with the meshoptimizer vertex and index codecs. The views
+//! are decoded into their fallback buffers when the glTF gets loaded, so accessors read them like
+//! any other data.
+//!
+//! The decoders are public, for compressed data that doesn't come out of a glTF.
+
+use std::{error::Error, path::Path};
+
+use gltf::buffer::Data;
+
+/// The name of the extension.
+pub const EXTENSION_NAME: &str = "EXT_meshopt_compression";
+
+const VERTEX_HEADER: u8 = 0xa0;
+const INDEX_HEADER: u8 = 0xe0;
+const SEQUENCE_HEADER: u8 = 0xd0;
+
+const VERTEX_BLOCK_SIZE_BYTES: usize = 8192;
+const VERTEX_BLOCK_MAX_SIZE: usize = 256;
+const BYTE_GROUP_SIZE: usize = 16;
+const TAIL_MIN_SIZE: usize = 32;
+
+///
+/// How a buffer view was compressed.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionMode {
+  /// Vertex attributes, or anything else with a fixed stride.
+  Attributes,
+  /// Triangle list indices.
+  Triangles,
+  /// Any other indices.
+  Indices,
+}
+
+///
+/// What was done to vertex attributes before they were compressed.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+  /// Nothing.
+  #[default]
+  None,
+  /// Octahedral encoded unit vectors, 4 i8s or 4 i16s. The 4th component is kept as is.
+  Octahedral,
+  /// Quaternions with the biggest component left out, 4 i16s.
+  Quaternion,
+  /// Floats as a 24 bit mantissa and an 8 bit exponent.
+  Exponential,
+}
+
+///
+/// Decode a vertex buffer of `count` vertices, `stride` bytes each.
+///
+pub fn decode_vertex_buffer(data: &[u8], count: usize, stride: usize) -> Result<Vec<u8>, String> {
+  if stride == 0 || stride > 256 || !stride.is_multiple_of(4) {
+    return Err(format!(
+      "meshopt: Vertex stride {} isn't supported.",
+      stride
+    ));
+  }
+  let tail_size = stride.max(TAIL_MIN_SIZE);
+  if data.len() < 1 + tail_size {
+    return Err("meshopt: Vertex buffer is too short.".into());
+  }
+  if data[0] != VERTEX_HEADER {
+    return Err(format!(
+      "meshopt: Vertex buffer header {:#x} isn't supported.",
+      data[0]
+    ));
+  }
+
+  // The first vertex is stored as is at the very end. Everything is a delta from it.
+  let mut last_vertex = data[data.len() - stride..].to_vec();
+  let block_size =
+    ((VERTEX_BLOCK_SIZE_BYTES / stride) & !(BYTE_GROUP_SIZE - 1)).min(VERTEX_BLOCK_MAX_SIZE);
+  // ? Every block takes at least a header byte per vertex byte, so short data can't claim a lot.
+  if count.div_ceil(block_size) > (data.len() - 1 - tail_size) / stride {
+    return Err("meshopt: Vertex buffer is too short.".into());
+  }
+  let size = count
+    .checked_mul(stride)
+    .ok_or("meshopt: Vertex buffer is too big.")?;
+
+  let mut output = vec![0; size];
+  let mut bytes = [0; VERTEX_BLOCK_MAX_SIZE];
+  let mut position = 1;
+  let mut start = 0;
+  while start < count {
+    let block_count = block_size.min(count - start);
+    let aligned_count = (block_count + BYTE_GROUP_SIZE - 1) & !(BYTE_GROUP_SIZE - 1);
+
+    // Every byte of the vertex is its own stream of deltas.
+    for (k, last) in last_vertex.iter().enumerate() {
+      position = decode_bytes(data, position, &mut bytes[..aligned_count])?;
+      let mut previous = *last;
+      for (i, byte) in bytes[..block_count].iter().enumerate() {
+        let value = unzigzag8(*byte).wrapping_add(previous);
+        output[(start + i) * stride + k] = value;
+        previous = value;
+      }
+    }
+
+    let last_start = (start + block_count - 1) * stride;
+    last_vertex.copy_from_slice(&output[last_start..last_start + stride]);
+    start += block_count;
+  }
+
+  if data.len() - position != tail_size {
+    return Err("meshopt: Vertex buffer has leftover data.".into());
+  }
+  Ok(output)
+}
+
+///
+/// Decode a byte stream of groups of 16, packed into 0, 2, 4 or 8 bits each.
+///
+fn decode_bytes(data: &[u8], mut position: usize, output: &mut [u8]) -> Result<usize, String> {
+  let too_short = || "meshopt: Vertex buffer is too short.".to_string();
+  let groups = output.len() / BYTE_GROUP_SIZE;
+  let header_size = groups.div_ceil(4);
+  let header = data
+    .get(position..position + header_size)
+    .ok_or_else(too_short)?;
+  position += header_size;
+
+  for (group, output) in output.chunks_exact_mut(BYTE_GROUP_SIZE).enumerate() {
+    match (header[group / 4] >> ((group % 4) * 2)) & 3 {
+      0 => output.fill(0),
+      3 => {
+        let bytes = data
+          .get(position..position + BYTE_GROUP_SIZE)
+          .ok_or_else(too_short)?;
+        output.copy_from_slice(bytes);
+        position += BYTE_GROUP_SIZE;
+      }
+      bits_log2 => {
+        let bits = 1 << bits_log2;
+        let packed_size = BYTE_GROUP_SIZE * bits / 8;
+        let packed = data
+          .get(position..position + packed_size)
+          .ok_or_else(too_short)?;
+        // ? Values with every bit set are escaped. The real value follows the packed bits.
+        let sentinel = (1 << bits) - 1;
+        let mut extra = position + packed_size;
+        for (i, value) in output.iter_mut().enumerate() {
+          let bit = i * bits;
+          let encoded = (packed[bit / 8] >> (8 - bits - bit % 8)) & sentinel;
+          *value = if encoded == sentinel {
+            extra += 1;
+            *data.get(extra - 1).ok_or_else(too_short)?
+          } else {
+            encoded
+          };
+        }
+        position = extra;
+      }
+    }
+  }
+  Ok(position)
+}
+
+fn unzigzag8(value: u8) -> u8 {
+  0u8.wrapping_sub(value & 1) ^ (value >> 1)
+}
+
+fn unzigzag32(value: u32) -> u32 {
+  0u32.wrapping_sub(value & 1) ^ (value >> 1)
+}
+
+///
+/// Read a variable length integer. 7 bits per byte, the high bit says if there is more.
+///
+fn decode_vbyte(data: &[u8], position: &mut usize) -> Result<u32, String> {
+  let mut result = 0;
+  for i in 0..5 {
+    let byte = *data
+      .get(*position)
+      .ok_or("meshopt: Index buffer is too short.")?;
+    *position += 1;
+    result |= ((byte & 127) as u32) << (7 * i);
+    if byte < 128 {
+      break;
+    }
+  }
+  Ok(result)
+}
+
+///
+/// Decode a triangle list of `count` indices.
+///
+pub fn decode_index_buffer(data: &[u8], count: usize) -> Result<Vec<u32>, String> {
+  if !count.is_multiple_of(3) {
+    return Err(format!(
+      "meshopt: {} indices aren't a triangle list.",
+      count
+    ));
+  }
+  // A header, a byte per triangle and the 16 byte code table.
+  if data.len() < 1 + count / 3 + 16 {
+    return Err("meshopt: Index buffer is too short.".into());
+  }
+  if data[0] & 0xf0 != INDEX_HEADER || data[0] & 0x0f > 1 {
+    return Err(format!(
+      "meshopt: Index buffer header {:#x} isn't supported.",
+      data[0]
+    ));
+  }
+  let version = data[0] & 0x0f;
+  let fec_max = if version >= 1 { 13 } else { 15 };
+
+  // Recently used edges and vertices. Reads wrap around.
+  let mut edge_fifo = [[u32::MAX; 2]; 16];
+  let mut vertex_fifo = [u32::MAX; 16];
+  let mut edge_offset = 0;
+  let mut vertex_offset = 0;
+  let push_vertex = |fifo: &mut [u32; 16], offset: &mut usize, vertex: u32, push: bool| {
+    fifo[*offset] = vertex;
+    *offset = (*offset + push as usize) & 15;
+  };
+  let push_edge = |fifo: &mut [[u32; 2]; 16], offset: &mut usize, a: u32, b: u32| {
+    fifo[*offset] = [a, b];
+    *offset = (*offset + 1) & 15;
+  };
+
+  let mut next: u32 = 0;
+  let mut last: u32 = 0;
+  let codes = &data[1..1 + count / 3];
+  let mut position = 1 + count / 3;
+  let safe_end = data.len() - 16;
+  let code_table = &data[safe_end..];
+
+  let mut indices = Vec::with_capacity(count);
+  for code in codes {
+    if position > safe_end {
+      return Err("meshopt: Index buffer is too short.".into());
+    }
+
+    let triangle = if *code < 0xf0 {
+      // One edge out of the fifo, and a vertex that's new, in the fifo or free.
+      let [a, b] = edge_fifo[(edge_offset + 15 - (*code >> 4) as usize) & 15];
+      let fec = (*code & 15) as u32;
+      let c = if fec < fec_max {
+        let c = if fec == 0 {
+          next
+        } else {
+          vertex_fifo[(vertex_offset + 15 - fec as usize) & 15]
+        };
+        if fec == 0 {
+          next += 1;
+        }
+        push_vertex(&mut vertex_fifo, &mut vertex_offset, c, fec == 0);
+        c
+      } else {
+        last = match fec {
+          13 => last.wrapping_sub(1),
+          14 => last.wrapping_add(1),
+          _ => last.wrapping_add(unzigzag32(decode_vbyte(data, &mut position)?)),
+        };
+        push_vertex(&mut vertex_fifo, &mut vertex_offset, last, true);
+        last
+      };
+      push_edge(&mut edge_fifo, &mut edge_offset, c, b);
+      push_edge(&mut edge_fifo, &mut edge_offset, a, c);
+      [a, b, c]
+    } else {
+      // Three vertices that are new, in the fifo or free.
+      let (fea, codeaux) = if *code < 0xfe {
+        (0, code_table[(*code & 15) as usize])
+      } else {
+        let codeaux = *data
+          .get(position)
+          .ok_or("meshopt: Index buffer is too short.")?;
+        position += 1;
+        (if *code == 0xfe { 0 } else { 15 }, codeaux)
+      };
+      let feb = (codeaux >> 4) as usize;
+      let fec = (codeaux & 15) as usize;
+
+      // ? `next` goes up for every new vertex before any free index is read, like the encoder.
+      let mut vertex = |fe: usize| {
+        if fe == 0 {
+          next += 1;
+          next - 1
+        } else {
+          vertex_fifo[(vertex_offset + 16 - fe) & 15]
+        }
+      };
+      let mut a = if fea == 0 { vertex(0) } else { 0 };
+      let mut b = vertex(feb);
+      let mut c = vertex(fec);
+      for (fe, index) in [(fea, &mut a), (feb, &mut b), (fec, &mut c)] {
+        if fe == 15 {
+          last = last.wrapping_add(unzigzag32(decode_vbyte(data, &mut position)?));
+          *index = last;
+        }
+      }
+
+      push_vertex(&mut vertex_fifo, &mut vertex_offset, a, true);
+      push_vertex(
+        &mut vertex_fifo,
+        &mut vertex_offset,
+        b,
+        feb == 0 || feb == 15,
+      );
+      push_vertex(
+        &mut vertex_fifo,
+        &mut vertex_offset,
+        c,
+        fec == 0 || fec == 15,
+      );
+      push_edge(&mut edge_fifo, &mut edge_offset, b, a);
+      push_edge(&mut edge_fifo, &mut edge_offset, c, b);
+      push_edge(&mut edge_fifo, &mut edge_offset, a, c);
+      [a, b, c]
+    };
+    indices.extend_from_slice(&triangle);
+  }
+
+  if position != safe_end {
+    return Err("meshopt: Index buffer has leftover data.".into());
+  }
+  Ok(indices)
+}
+
+///
+/// Decode `count` indices that aren't a triangle list.
+///
+pub fn decode_index_sequence(data: &[u8], count: usize) -> Result<Vec<u32>, String> {
+  // A header, a byte per index and a 4 byte tail.
+  if data.len() < 1 + count + 4 {
+    return Err("meshopt: Index sequence is too short.".into());
+  }
+  if data[0] & 0xf0 != SEQUENCE_HEADER || data[0] & 0x0f > 1 {
+    return Err(format!(
+      "meshopt: Index sequence header {:#x} isn't supported.",
+      data[0]
+    ));
+  }
+
+  let safe_end = data.len() - 4;
+  let mut position = 1;
+  // Deltas come off of one of two baselines. The low bit says which.
+  let mut last = [0u32; 2];
+  let mut indices = Vec::with_capacity(count);
+  for _ in 0..count {
+    if position >= safe_end {
+      return Err("meshopt: Index sequence is too short.".into());
+    }
+    let value = decode_vbyte(data, &mut position)?;
+    let baseline = (value & 1) as usize;
+    let index = last[baseline].wrapping_add(unzigzag32(value >> 1));
+    last[baseline] = index;
+    indices.push(index);
+  }
+
+  if position != safe_end {
+    return Err("meshopt: Index sequence has leftover data.".into());
+  }
+  Ok(indices)
+}
+
+///
+/// Undo a filter on decoded vertex data, in place.
+///
+pub fn decode_filter(data: &mut [u8], stride: usize, filter: Filter) -> Result<(), String> {
+  let bad_stride = || {
+    Err(format!(
+      "meshopt: Stride {} doesn't work with the {:?} filter.",
+      stride, filter
+    ))
+  };
+  let round = |value: f32| (value + if value >= 0.0 { 0.5 } else { -0.5 }) as i32;
+
+  match filter {
+    Filter::None => {}
+    Filter::Octahedral => {
+      let (size, max) = match stride {
+        4 => (1, i8::MAX as f32),
+        8 => (2, i16::MAX as f32),
+        _ => return bad_stride(),
+      };
+      let read = |bytes: &[u8]| match size {
+        1 => bytes[0] as i8 as f32,
+        _ => i16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+      };
+      for vertex in data.chunks_exact_mut(stride) {
+        let mut x = read(&vertex[0..]);
+        let mut y = read(&vertex[size..]);
+        // The third component holds what 1.0 is.
+        let z = read(&vertex[size * 2..]) - x.abs() - y.abs();
+        let fold = z.min(0.0);
+        x += if x >= 0.0 { fold } else { -fold };
+        y += if y >= 0.0 { fold } else { -fold };
+        let scale = max / (x * x + y * y + z * z).sqrt();
+
+        for (i, value) in [x, y, z].into_iter().enumerate() {
+          let value = round(value * scale);
+          match size {
+            1 => vertex[i] = value as i8 as u8,
+            _ => vertex[i * 2..i * 2 + 2].copy_from_slice(&(value as i16).to_le_bytes()),
+          }
+        }
+      }
+    }
+    Filter::Quaternion => {
+      if stride != 8 {
+        return bad_stride();
+      }
+      for vertex in data.chunks_exact_mut(8) {
+        let read = |i: usize| i16::from_le_bytes([vertex[i * 2], vertex[i * 2 + 1]]);
+        // The 4th component holds what 1.0 is, and which component was left out.
+        let missing = (read(3) & 3) as usize;
+        let scale = std::f32::consts::FRAC_1_SQRT_2 / (read(3) | 3) as f32;
+        let x = read(0) as f32 * scale;
+        let y = read(1) as f32 * scale;
+        let z = read(2) as f32 * scale;
+        let w = (1.0 - x * x - y * y - z * z).max(0.0).sqrt();
+
+        for (offset, value) in [(1, x), (2, y), (3, z), (0, w)] {
+          let i = (missing + offset) & 3;
+          let value = round(value * i16::MAX as f32) as i16;
+          vertex[i * 2..i * 2 + 2].copy_from_slice(&value.to_le_bytes());
+        }
+      }
+    }
+    Filter::Exponential => {
+      if !stride.is_multiple_of(4) {
+        return bad_stride();
+      }
+      for value in data.chunks_exact_mut(4) {
+        let bits = u32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+        let mantissa = ((bits << 8) as i32) >> 8;
+        let exponent = (bits as i32) >> 24;
+        let float = mantissa as f32 * 2f32.powi(exponent);
+        value.copy_from_slice(&float.to_le_bytes());
+      }
+    }
+  }
+  Ok(())
+}
+
+///
+/// Load the buffers of a glTF, then decode every compressed buffer view into its buffer.
+///
+/// Fallback buffers don't have any data of their own, so they start out zeroed.
+///
+pub(crate) fn import_buffers(
+  document: &gltf::Document,
+  base: Option<&Path>,
+  mut blob: Option<Vec<u8>>,
+) -> Result<Vec<Data>, Box<dyn Error + Send + Sync>> {
+  let mut buffers = vec![];
+  for buffer in document.buffers() {
+    let fallback = buffer
+      .extension_value(EXTENSION_NAME)
+      .and_then(|extension| extension.get("fallback"))
+      .and_then(|fallback| fallback.as_bool())
+      .unwrap_or(false);
+    if fallback {
+      buffers.push(Data(vec![0; fallback_length(document, &buffer)?]));
+      continue;
+    }
+
+    let data = Data::from_source_and_blob(buffer.source(), base, &mut blob)?;
+    if data.len() < buffer.length() {
+      return Err(
+        format!(
+          "Buffer {} is {} bytes, but should be {}.",
+          buffer.index(),
+          data.len(),
+          buffer.length()
+        )
+        .into(),
+      );
+    }
+    buffers.push(data);
+  }
+
+  for view in document.views() {
+    if let Some(extension) = view.extension_value(EXTENSION_NAME) {
+      decode_view(&view, extension, &mut buffers)
+        .map_err(|e| format!("Buffer view {}: {}", view.index(), e))?;
+    }
+  }
+  Ok(buffers)
+}
+
+///
+/// How much of a fallback buffer the compressed buffer views decode into.
+///
+/// Only that much gets allocated, the rest of the buffer can't hold any data.
+///
+fn fallback_length(
+  document: &gltf::Document,
+  buffer: &gltf::Buffer,
+) -> Result<usize, Box<dyn Error + Send + Sync>> {
+  let mut length = 0;
+  for view in document.views() {
+    if view.buffer().index() != buffer.index() || view.extension_value(EXTENSION_NAME).is_none() {
+      continue;
+    }
+    let end = view
+      .offset()
+      .checked_add(view.length())
+      .filter(|end| *end <= buffer.length())
+      .ok_or_else(|| {
+        format!(
+          "Buffer view {}: The buffer view is out of range.",
+          view.index()
+        )
+      })?;
+    length = length.max(end);
+  }
+  Ok(length)
+}
+
+///
+/// Decode one compressed buffer view into its buffer.
+///
+fn decode_view(
+  view: &gltf::buffer::View,
+  extension: &gltf::json::Value,
+  buffers: &mut [Data],
+) -> Result<(), String> {
+  let field = |name: &str| {
+    extension
+      .get(name)
+      .and_then(|value| value.as_u64())
+      .map(|value| value as usize)
+  };
+  let missing = |name: &str| format!("{} is missing {}.", EXTENSION_NAME, name);
+
+  let source = field("buffer").ok_or_else(|| missing("buffer"))?;
+  let offset = field("byteOffset").unwrap_or(0);
+  let length = field("byteLength").ok_or_else(|| missing("byteLength"))?;
+  let stride = field("byteStride").ok_or_else(|| missing("byteStride"))?;
+  let count = field("count").ok_or_else(|| missing("count"))?;
+  let mode = match extension.get("mode").and_then(|mode| mode.as_str()) {
+    Some("ATTRIBUTES") => CompressionMode::Attributes,
+    Some("TRIANGLES") => CompressionMode::Triangles,
+    Some("INDICES") => CompressionMode::Indices,
+    mode => return Err(format!("Compression mode {:?} isn't supported.", mode)),
+  };
+  let filter = match extension.get("filter").and_then(|filter| filter.as_str()) {
+    None | Some("NONE") => Filter::None,
+    Some("OCTAHEDRAL") => Filter::Octahedral,
+    Some("QUATERNION") => Filter::Quaternion,
+    Some("EXPONENTIAL") => Filter::Exponential,
+    Some(filter) => return Err(format!("Filter {} isn't supported.", filter)),
+  };
+
+  // ? Checked before decoding, so the size of the output is bounded by the view.
+  if count.checked_mul(stride) != Some(view.length()) {
+    return Err(format!(
+      "{} elements of {} bytes don't fill the {} bytes of the buffer view.",
+      count,
+      stride,
+      view.length()
+    ));
+  }
+
+  let compressed = buffers
+    .get(source)
+    .zip(offset.checked_add(length))
+    .and_then(|(buffer, end)| buffer.get(offset..end))
+    .ok_or("The compressed data is out of range.")?;
+
+  let decoded = match mode {
+    CompressionMode::Attributes => {
+      let mut decoded = decode_vertex_buffer(compressed, count, stride)?;
+      decode_filter(&mut decoded, stride, filter)?;
+      decoded
+    }
+    CompressionMode::Triangles | CompressionMode::Indices => {
+      let indices = if mode == CompressionMode::Triangles {
+        decode_index_buffer(compressed, count)?
+      } else {
+        decode_index_sequence(compressed, count)?
+      };
+      match stride {
+        2 => indices
+          .iter()
+          .map(|index| {
+            u16::try_from(*index)
+              .map(u16::to_le_bytes)
+              .map_err(|_| format!("Index {} doesn't fit in 2 bytes.", index))
+          })
+          .collect::<Result<Vec<_>, _>>()?
+          .concat(),
+        4 => indices
+          .iter()
+          .flat_map(|index| index.to_le_bytes())
+          .collect(),
+        _ => return Err(format!("Index stride {} isn't supported.", stride)),
+      }
+    }
+  };
+
+  let target = buffers
+    .get_mut(view.buffer().index())
+    .zip(view.offset().checked_add(view.length()))
+    .and_then(|(buffer, end)| buffer.0.get_mut(view.offset()..end))
+    .ok_or("The buffer view is out of range.")?;
+  if decoded.len() != target.len() {
+    return Err(format!(
+      "Decoded {} bytes, but the buffer view is {} bytes.",
+      decoded.len(),
+      target.len()
+    ));
+  }
+  target.copy_from_slice(&decoded);
+  Ok(())
+}
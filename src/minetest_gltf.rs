@@ -2,7 +2,7 @@ use std::path::{Path, PathBuf};
 
 use ahash::AHashMap;
 
-use crate::{animation::BoneAnimationChannel, Model};
+use crate::{animation::BoneAnimationChannel, skeleton::Skeleton, Model};
 
 // Helps to simplify the signature of import related functions.
 ///
@@ -17,6 +17,28 @@ pub struct MinetestGLTF {
   ///
   pub bone_animations: Option<AHashMap<i32, BoneAnimationChannel>>,
   pub is_animated: bool,
+  ///
+  /// The node (bone) hierarchy and first skin of the model.
+  ///
+  pub skeleton: Option<Skeleton>,
+  ///
+  /// glTF extensions the model uses that weren't loaded. The model is right without them, but
+  /// whatever they add is missing.
+  ///
+  pub ignored_extensions: Vec<String>,
+
+  #[cfg(feature = "names")]
+  ///
+  /// Node (bone) names, indexed by node id. Requires the `names` feature.
+  ///
+  pub node_names: Vec<Option<String>>,
+  #[cfg(feature = "names")]
+  node_ids: AHashMap<String, i32>,
+  #[cfg(feature = "names")]
+  ///
+  /// Animation names, indexed by animation index. Requires the `names` feature.
+  ///
+  pub animation_names: Vec<Option<String>>,
 
   pub(crate) buffers: Vec<gltf::buffer::Data>,
   pub base_dir: PathBuf,
@@ -30,6 +52,14 @@ impl MinetestGLTF {
       model: None,
       bone_animations: None,
       is_animated: false,
+      skeleton: None,
+      ignored_extensions: vec![],
+      #[cfg(feature = "names")]
+      node_names: vec![],
+      #[cfg(feature = "names")]
+      node_ids: AHashMap::new(),
+      #[cfg(feature = "names")]
+      animation_names: vec![],
       buffers,
       base_dir,
     }
@@ -48,4 +78,111 @@ impl MinetestGLTF {
   pub fn is_animated(&self) -> bool {
     self.bone_animations.is_some()
   }
+
+  #[cfg(feature = "names")]
+  ///
+  /// Collect the node (bone) and animation names of the document.
+  ///
+  /// If names are duplicated, the first node with the name wins.
+  ///
+  pub(crate) fn load_names(&mut self, gltf_data: &gltf::Gltf) {
+    self.set_node_names(
+      gltf_data
+        .nodes()
+        .map(|node| node.name().map(String::from))
+        .collect(),
+    );
+
+    self.animation_names = gltf_data
+      .animations()
+      .map(|animation| animation.name().map(String::from))
+      .collect();
+  }
+
+  #[cfg(feature = "names")]
+  ///
+  /// Set the node (bone) names, indexed by node id. The first node with a name wins.
+  ///
+  pub(crate) fn set_node_names(&mut self, node_names: Vec<Option<String>>) {
+    self.node_names = node_names;
+
+    self.node_ids.clear();
+    for (id, name) in self.node_names.iter().enumerate() {
+      if let Some(name) = name {
+        self.node_ids.entry(name.clone()).or_insert(id as i32);
+      }
+    }
+  }
+
+  #[cfg(feature = "names")]
+  ///
+  /// Get the name of a node (bone) by node id. Requires the `names` feature.
+  ///
+  pub fn node_name(&self, id: i32) -> Option<&str> {
+    if id < 0 {
+      return None;
+    }
+    self.node_names.get(id as usize)?.as_deref()
+  }
+
+  #[cfg(feature = "names")]
+  ///
+  /// Get the node id of a node (bone) by name. Requires the `names` feature.
+  ///
+  pub fn node_id(&self, name: &str) -> Option<i32> {
+    self.node_ids.get(name).copied()
+  }
+
+  #[cfg(feature = "names")]
+  ///
+  /// Get the name of a skin joint by its index in the skin (what `JOINTS_0` refers to). Requires the `names` feature.
+  ///
+  pub fn joint_name(&self, joint: usize) -> Option<&str> {
+    let id = *self.skeleton.as_ref()?.joints.get(joint)?;
+    self.node_name(id)
+  }
+
+  #[cfg(feature = "names")]
+  ///
+  /// Get the index in the skin (what `JOINTS_0` refers to) of a joint by name. Requires the `names` feature.
+  ///
+  pub fn joint_index(&self, name: &str) -> Option<usize> {
+    let id = self.node_id(name)?;
+    self
+      .skeleton
+      .as_ref()?
+      .joints
+      .iter()
+      .position(|joint| *joint == id)
+  }
+
+  #[cfg(feature = "names")]
+  ///
+  /// Get the name of an animation by index. Requires the `names` feature.
+  ///
+  /// Note: Only the first animation (index 0) is loaded into `bone_animations`.
+  ///
+  pub fn animation_name(&self, index: usize) -> Option<&str> {
+    self.animation_names.get(index)?.as_deref()
+  }
+
+  #[cfg(feature = "names")]
+  ///
+  /// Get the index of an animation by name. Requires the `names` feature.
+  ///
+  pub fn animation_index(&self, name: &str) -> Option<usize> {
+    self
+      .animation_names
+      .iter()
+      .position(|animation_name| animation_name.as_deref() == Some(name))
+  }
+
+  #[cfg(feature = "names")]
+  ///
+  /// Get the animation channel of a node (bone) by name. Requires the `names` feature.
+  ///
+  pub fn bone_animation_by_name(&self, name: &str) -> Option<&BoneAnimationChannel> {
+    let id = self.node_id(name)?;
+    self.bone_animations.as_ref()?.get(&id)
+  }
 }
@@ -1,12 +1,29 @@
 /// Contains animation data for the models.
 pub mod animation;
+/// Bounding boxes and spheres of the models.
+pub mod bounds;
+/// Cameras placed in the models, for cutscenes.
+pub mod camera;
+/// Converts models into the minetest coordinate system.
+pub mod coordinates;
+/// Generates minetest collision and selection boxes from the models.
+pub mod entity_box;
+/// KHR_lights_punctual lights placed in the models.
+pub mod light;
 /// Contains model and material
 /// # Usage
 /// Check [Model](struct.Model.html) for more information about how to use this module.
 pub mod primitive;
+/// Contains the node (bone) hierarchy and skin of the models.
+pub mod skeleton;
 
 use crate::minetest_gltf::MinetestGLTF;
-use glam::Mat4;
+use ahash::AHashMap;
+use animation::BoneTransform;
+use camera::{Camera, CameraPathNode};
+use glam::{Mat4, Quat, Vec3};
+use light::Light;
+use primitive::BadMode;
 
 pub use primitive::Primitive;
 
@@ -23,9 +40,51 @@ pub struct Model {
   pub extras: gltf::json::extras::Extras,
   /// List of models in the scene.
   pub primitives: Vec<Primitive>,
+  /// The lights attached to the nodes of the scene.
+  pub lights: Vec<Light>,
+  /// The cameras attached to the nodes of the scene.
+  pub cameras: Vec<Camera>,
+  /// The glTF materials of the file, without their texture references, since the textures
+  /// aren't loaded. The primitive `material`s index into this. Empty for B3D and OBJ models.
+  pub materials: Vec<gltf::json::Material>,
 }
 
 impl Model {
+  ///
+  /// Create a model out of primitives that came from somewhere other than a glTF scene.
+  ///
+  pub(crate) fn from_primitives(primitives: Vec<Primitive>) -> Self {
+    Model {
+      #[cfg(feature = "names")]
+      name: None,
+      #[cfg(feature = "extras")]
+      extras: Default::default(),
+      primitives,
+      lights: vec![],
+      cameras: vec![],
+      materials: vec![],
+    }
+    .with_file_order_texture_slots()
+    .with_bounds()
+  }
+
+  ///
+  /// Run the optimization pipeline on every primitive.
+  ///
+  pub fn optimize(&mut self, options: &primitive::OptimizeOptions) {
+    for primitive in &mut self.primitives {
+      primitive.optimize(options);
+    }
+  }
+
+  ///
+  /// Work out the cached bounds of every primitive.
+  ///
+  fn with_bounds(mut self) -> Self {
+    self.update_bounds();
+    self
+  }
+
   pub(crate) fn load(gltf_scene: gltf::Scene, data: &mut MinetestGLTF) -> Self {
     let mut scene = Self::default();
 
@@ -38,19 +97,74 @@ impl Model {
       scene.extras = gltf_scene.extras().clone();
     }
 
+    let mut walk = NodeWalk::default();
     for node in gltf_scene.nodes() {
-      scene.read_node(&node, &Mat4::IDENTITY, data);
+      scene.read_node(&node, &Mat4::IDENTITY, data, &mut walk);
     }
+    scene.assign_texture_slots(&walk.engine_order);
     scene
   }
 
-  fn read_node(&mut self, node: &Node, parent_transform: &Mat4, data: &mut MinetestGLTF) {
+  ///
+  /// Keep the materials of the file. Texture references are dropped, along with the material
+  /// extensions that can hold them.
+  ///
+  pub(crate) fn load_materials(&mut self, materials: Vec<gltf::json::Material>) {
+    self.materials = materials
+      .into_iter()
+      .map(|mut material| {
+        material.pbr_metallic_roughness.base_color_texture = None;
+        material.pbr_metallic_roughness.metallic_roughness_texture = None;
+        material.pbr_metallic_roughness.extensions = None;
+        material.normal_texture = None;
+        material.occlusion_texture = None;
+        material.emissive_texture = None;
+        material.extensions = None;
+        material
+      })
+      .collect();
+  }
+
+  fn read_node(
+    &mut self,
+    node: &Node,
+    parent_transform: &Mat4,
+    data: &mut MinetestGLTF,
+    walk: &mut NodeWalk,
+  ) {
     // Compute transform of the current node.
     let transform = *parent_transform * transform_to_matrix(node.transform());
+    let (translation, rotation, scale) = node.transform().decomposed();
+    walk.path.push(CameraPathNode {
+      id: node.index() as i32,
+      rest: BoneTransform {
+        translation: Vec3::from_array(translation),
+        rotation: Quat::from_array(rotation),
+        scale: Vec3::from_array(scale),
+      },
+      animated: [false; 3],
+    });
+
+    // ? Minetest adds the mesh of a node before walking its children.
+    let visit = walk.visited;
+    walk.visited += 1;
+
+    if let Some(camera) = node.camera() {
+      self
+        .cameras
+        .push(Camera::load(camera, &transform, walk.path.clone()));
+    }
 
     // Recurse on children.
     for child in node.children() {
-      self.read_node(&child, &transform, data);
+      self.read_node(&child, &transform, data, walk);
+    }
+    walk.path.pop();
+
+    if let Some(light) = node.light() {
+      self
+        .lights
+        .push(Light::load(light, node.index() as i32, &transform));
     }
 
     // Load model
@@ -59,9 +173,99 @@ impl Model {
         self
           .primitives
           .push(Primitive::load(&mesh, i, primitive, &transform, data));
+        walk.engine_order.push(visit);
       }
     }
   }
+
+  ///
+  /// Hand out the texture slots for primitives that are already in the order minetest creates
+  /// the mesh buffers.
+  ///
+  fn with_file_order_texture_slots(mut self) -> Self {
+    let engine_order: Vec<usize> = (0..self.primitives.len()).collect();
+    self.assign_texture_slots(&engine_order);
+    self
+  }
+
+  ///
+  /// Hand out the texture slots.
+  ///
+  /// `engine_order` is where each primitive lands in the order minetest creates the mesh buffers.
+  /// The first material to show up gets slot 0, the next new one slot 1, and so on.
+  ///
+  fn assign_texture_slots(&mut self, engine_order: &[usize]) {
+    let mut order: Vec<usize> = (0..self.primitives.len()).collect();
+    // * Stable, so the primitives of one mesh stay in order.
+    order.sort_by_key(|i| engine_order.get(*i).copied().unwrap_or(*i));
+
+    let mut slots: AHashMap<Option<usize>, usize> = AHashMap::new();
+    for i in order {
+      let next_slot = slots.len();
+      let primitive = &mut self.primitives[i];
+      primitive.texture_slot = *slots.entry(primitive.material).or_insert(next_slot);
+    }
+  }
+
+  ///
+  /// How many texture slots there are. This is how many textures a minetest `textures = {...}`
+  /// table needs for this model.
+  ///
+  pub fn texture_slot_count(&self) -> usize {
+    self
+      .primitives
+      .iter()
+      .map(|primitive| primitive.texture_slot + 1)
+      .max()
+      .unwrap_or(0)
+  }
+
+  ///
+  /// Get the primitives that use a texture slot.
+  ///
+  pub fn texture_slot_primitives(&self, slot: usize) -> impl Iterator<Item = &Primitive> {
+    self
+      .primitives
+      .iter()
+      .filter(move |primitive| primitive.texture_slot == slot)
+  }
+
+  ///
+  /// Merge all the primitives that share a texture slot into one primitive.
+  ///
+  /// Afterwards there is exactly one primitive per texture slot, sorted by slot, so
+  /// `primitives[i]` gets `textures[i + 1]` in lua.
+  ///
+  /// Strips, fans and loops are unrolled into lists.
+  ///
+  /// **Note**: This returns an error if a slot mixes triangles, lines and points.
+  ///
+  pub fn merge_texture_slots(&mut self) -> Result<(), BadMode> {
+    let mut merged = Vec::with_capacity(self.texture_slot_count());
+    for slot in 0..self.texture_slot_count() {
+      let group: Vec<&Primitive> = self.texture_slot_primitives(slot).collect();
+      match group.as_slice() {
+        [] => {}
+        [primitive] => merged.push((*primitive).clone()),
+        _ => merged.push(Primitive::merge(&group)?),
+      }
+    }
+    self.primitives = merged;
+    Ok(())
+  }
+}
+
+///
+/// Keeps track of the scene walk, so the primitives can be put in the order minetest sees them.
+///
+#[derive(Default)]
+struct NodeWalk {
+  // How many nodes have been visited so far.
+  visited: usize,
+  // The visit number of the node each primitive came from.
+  engine_order: Vec<usize>,
+  // The nodes from the root down to the current node.
+  path: Vec<CameraPathNode>,
 }
 
 fn transform_to_matrix(transform: Transform) -> Mat4 {
@@ -10,6 +10,20 @@ use log::error;
 
 use crate::minetest_gltf::MinetestGLTF;
 
+/// Pose sampling, blending and cross-fading.
+mod blend;
+/// Per node (bone) overrides on top of the animation.
+mod bone_override;
+/// Keyframe reduction.
+mod reduce;
+/// Root motion extraction.
+mod root_motion;
+
+pub use blend::*;
+pub use bone_override::*;
+pub use reduce::*;
+pub use root_motion::*;
+
 /// Raw animation data. Unionized.
 pub enum Keyframes {
   /// Translation raw data.
@@ -25,8 +39,12 @@ pub enum Keyframes {
 }
 
 /// Container containing raw TRS animation data for a node (bone).
-#[derive(Default)]
+#[derive(Default, Clone, Debug)]
 pub struct BoneAnimationChannel {
+  #[cfg(feature = "names")]
+  /// Name of the node (bone) this channel animates. Requires the `names` feature.
+  pub name: Option<String>,
+
   /// Translation data.
   pub translations: Vec<Vec3>,
   /// Translation timestamp data.
@@ -56,6 +74,8 @@ impl BoneAnimationChannel {
   ///
   pub(crate) fn new() -> Self {
     BoneAnimationChannel {
+      #[cfg(feature = "names")]
+      name: None,
       translations: vec![],
       translation_timestamps: vec![],
       rotations: vec![],
@@ -66,6 +86,68 @@ impl BoneAnimationChannel {
       weight_timestamps: vec![],
     }
   }
+
+  ///
+  /// Sample the TRS of this node (bone) at a point in time (in seconds).
+  ///
+  /// Time is clamped into the range of the channel. Empty tracks fall back to identity.
+  ///
+  pub fn sample(&self, time: f32) -> BoneTransform {
+    BoneTransform {
+      translation: sample_track(
+        &self.translation_timestamps,
+        &self.translations,
+        time,
+        Vec3::lerp,
+      )
+      .unwrap_or(Vec3::ZERO),
+      rotation: sample_track(
+        &self.rotation_timestamps,
+        &self.rotations,
+        time,
+        Quat::slerp,
+      )
+      .unwrap_or(Quat::IDENTITY),
+      scale: sample_track(&self.scale_timestamps, &self.scales, time, Vec3::lerp)
+        .unwrap_or(Vec3::ONE),
+    }
+  }
+}
+
+///
+/// Find the value of a keyframe track at a point in time.
+///
+/// Returns None if the track is empty.
+///
+pub(crate) fn sample_track<T: Copy>(
+  timestamps: &[f32],
+  values: &[T],
+  time: f32,
+  interpolate: fn(T, T, f32) -> T,
+) -> Option<T> {
+  let length = timestamps.len().min(values.len());
+  if length == 0 {
+    return None;
+  }
+
+  // The first keyframe that is after the time.
+  let following = timestamps[..length].partition_point(|stamp| *stamp <= time);
+
+  if following == 0 {
+    return Some(values[0]);
+  }
+  if following >= length {
+    return Some(values[length - 1]);
+  }
+
+  let leader = following - 1;
+  let scale = timestamps[following] - timestamps[leader];
+  if scale <= 0.0 {
+    return Some(values[leader]);
+  }
+
+  let percentile = (time - timestamps[leader]) / scale;
+  Some(interpolate(values[leader], values[following], percentile))
 }
 
 ///
@@ -136,25 +218,10 @@ pub(crate) fn grab_animations(
                 Keyframes::Translation(translation.map(Vec3::from_array).collect())
               }
 
-              util::ReadOutputs::Rotations(rotation) => match rotation {
-                util::Rotations::I8(_rotation) => generic_failure("i8", "rotation"),
-                util::Rotations::U8(_rotation) => generic_failure("u8", "rotation"),
-                util::Rotations::I16(_rotation) => generic_failure("i16", "rotation"),
-                util::Rotations::U16(_rotation) => generic_failure("u16", "rotation"),
-                util::Rotations::F32(rotation) => Keyframes::Rotation(
-                  rotation
-                    .map(|rot| {
-                      Quat::from_array({
-                        let mut returning_array: [f32; 4] = [0.0, 0.0, 0.0, 0.0];
-                        for (i, v) in rot.iter().enumerate() {
-                          returning_array[i] = *v;
-                        }
-                        returning_array
-                      })
-                    })
-                    .collect(),
-                ),
-              },
+              // ? Normalized integer rotations are allowed, and come out of quantized models.
+              util::ReadOutputs::Rotations(rotation) => {
+                Keyframes::Rotation(rotation.into_f32().map(Quat::from_array).collect())
+              }
               util::ReadOutputs::Scales(scale) => {
                 Keyframes::Scale(scale.map(Vec3::from_array).collect())
               }
@@ -216,6 +283,12 @@ pub(crate) fn grab_animations(
             println!("found target bone: {}", bone_id);
           }
 
+          #[cfg(feature = "names")]
+          {
+            bone_animation_channels.entry(bone_id).or_default().name =
+              channel.target().node().name().map(String::from);
+          }
+
           match keyframes {
             Keyframes::Translation(translations) => {
               let animation_channel = bone_animation_channels.entry(bone_id).or_default();
@@ -340,6 +413,18 @@ pub(crate) fn finalize_animations(
   // We're going to take the raw data.
   let bone_animations = grab_animations(gltf_data, buffers, file_name);
 
+  finalize_bone_animations(minetest_gltf, bone_animations)
+}
+
+///
+/// Interpolate raw bone animations so all the frames are equal distance from eachother in the scale of time.
+///
+/// This is shared by every format that produces bone animations.
+///
+pub(crate) fn finalize_bone_animations(
+  minetest_gltf: &mut MinetestGLTF,
+  bone_animations: AHashMap<i32, BoneAnimationChannel>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
   // Then finalize it.
   // (finalization is interpolating the frames so they're all equal distance from eachother in the scale of time.)
 
@@ -417,6 +502,11 @@ pub(crate) fn finalize_animations(
     // Add a channel to the current id in the finalized animations container.
     let mut new_finalized_channel = BoneAnimationChannel::new();
 
+    #[cfg(feature = "names")]
+    {
+      new_finalized_channel.name = animation.name.clone();
+    }
+
     // ? ////////////////////////////////////////////////////////////
     // ?            TRANSLATIONS
     // ? ////////////////////////////////////////////////////////////
@@ -0,0 +1,303 @@
+use ahash::AHashMap;
+use glam::{Mat4, Quat, Vec3};
+
+use super::BoneAnimationChannel;
+use crate::skeleton::Skeleton;
+
+///
+/// The local TRS of a single node (bone).
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoneTransform {
+  /// Translation.
+  pub translation: Vec3,
+  /// Rotation.
+  pub rotation: Quat,
+  /// Scale.
+  pub scale: Vec3,
+}
+
+impl Default for BoneTransform {
+  fn default() -> Self {
+    BoneTransform::IDENTITY
+  }
+}
+
+impl BoneTransform {
+  /// A transform that does nothing.
+  pub const IDENTITY: BoneTransform = BoneTransform {
+    translation: Vec3::ZERO,
+    rotation: Quat::IDENTITY,
+    scale: Vec3::ONE,
+  };
+
+  ///
+  /// Lerp the translation and scale, slerp the rotation.
+  ///
+  pub fn interpolate(&self, other: &BoneTransform, weight: f32) -> BoneTransform {
+    BoneTransform {
+      translation: self.translation.lerp(other.translation, weight),
+      rotation: self.rotation.slerp(other.rotation, weight),
+      scale: self.scale.lerp(other.scale, weight),
+    }
+  }
+
+  ///
+  /// Get the difference between this transform and a reference transform.
+  ///
+  /// Applying the result on top of the reference with `add` gives back this transform.
+  ///
+  pub fn difference(&self, reference: &BoneTransform) -> BoneTransform {
+    BoneTransform {
+      translation: self.translation - reference.translation,
+      rotation: reference.rotation.inverse() * self.rotation,
+      scale: self.scale / reference.scale,
+    }
+  }
+
+  ///
+  /// Apply an additive difference (from `difference`) on top of this transform, scaled by weight.
+  ///
+  pub fn add(&self, difference: &BoneTransform, weight: f32) -> BoneTransform {
+    BoneTransform {
+      translation: self.translation + difference.translation * weight,
+      rotation: self.rotation * Quat::IDENTITY.slerp(difference.rotation, weight),
+      scale: self.scale * Vec3::ONE.lerp(difference.scale, weight),
+    }
+  }
+
+  ///
+  /// Turn this into a local transformation matrix.
+  ///
+  pub fn to_matrix(&self) -> Mat4 {
+    Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+  }
+}
+
+///
+/// A sampled pose. The local transform of each node (bone), keyed by node id.
+///
+#[derive(Clone, Debug, Default)]
+pub struct Pose {
+  /// Local node (bone) transforms.
+  pub transforms: AHashMap<i32, BoneTransform>,
+}
+
+impl Pose {
+  ///
+  /// The rest pose of every node in the skeleton.
+  ///
+  pub fn rest(skeleton: &Skeleton) -> Pose {
+    let transforms = skeleton
+      .nodes
+      .iter()
+      .map(|node| {
+        (
+          node.id,
+          BoneTransform {
+            translation: node.translation,
+            rotation: node.rotation,
+            scale: node.scale,
+          },
+        )
+      })
+      .collect();
+    Pose { transforms }
+  }
+
+  ///
+  /// Sample every animated node (bone) at a point in time (in seconds).
+  ///
+  pub fn sample(bone_animations: &AHashMap<i32, BoneAnimationChannel>, time: f32) -> Pose {
+    let transforms = bone_animations
+      .iter()
+      .map(|(id, channel)| (*id, channel.sample(time)))
+      .collect();
+    Pose { transforms }
+  }
+
+  ///
+  /// Sample every animated node (bone) at a point in time (in seconds) on top of the rest pose.
+  ///
+  /// Nodes that are not animated keep their rest transform.
+  ///
+  pub fn sample_with_rest(
+    skeleton: &Skeleton,
+    bone_animations: &AHashMap<i32, BoneAnimationChannel>,
+    time: f32,
+  ) -> Pose {
+    let mut pose = Pose::rest(skeleton);
+    pose
+      .transforms
+      .extend(Pose::sample(bone_animations, time).transforms);
+    pose
+  }
+
+  ///
+  /// Get the transform of a node (bone). Identity if it's not in the pose.
+  ///
+  pub fn get(&self, id: i32) -> BoneTransform {
+    self.transforms.get(&id).copied().unwrap_or_default()
+  }
+
+  ///
+  /// Blend this pose towards another pose.
+  ///
+  /// A weight of 0.0 is this pose, 1.0 is the other pose.
+  ///
+  /// If a mask is given, the weight is multiplied by the mask weight of each node (bone).
+  ///
+  pub fn blend(&self, other: &Pose, weight: f32, mask: Option<&BoneMask>) -> Pose {
+    let mut transforms = self.transforms.clone();
+
+    for (id, other_transform) in &other.transforms {
+      let bone_weight = weight * mask.map_or(1.0, |mask| mask.weight(*id));
+      let blended = match self.transforms.get(id) {
+        Some(transform) => transform.interpolate(other_transform, bone_weight),
+        None => BoneTransform::IDENTITY.interpolate(other_transform, bone_weight),
+      };
+      transforms.insert(*id, blended);
+    }
+
+    Pose { transforms }
+  }
+
+  ///
+  /// Layer an additive pose on top of this pose.
+  ///
+  /// The layer is applied as the difference between the layer pose and the reference pose.
+  ///
+  /// If a mask is given, the weight is multiplied by the mask weight of each node (bone).
+  ///
+  pub fn add_layer(
+    &self,
+    layer: &Pose,
+    reference: &Pose,
+    weight: f32,
+    mask: Option<&BoneMask>,
+  ) -> Pose {
+    let mut transforms = self.transforms.clone();
+
+    for (id, layer_transform) in &layer.transforms {
+      let bone_weight = weight * mask.map_or(1.0, |mask| mask.weight(*id));
+      let difference = layer_transform.difference(&reference.get(*id));
+      let base = self.get(*id);
+      transforms.insert(*id, base.add(&difference, bone_weight));
+    }
+
+    Pose { transforms }
+  }
+}
+
+///
+/// A per node (bone) blend weight.
+///
+/// Used to limit a blend to a part of the skeleton, like the upper body.
+///
+#[derive(Clone, Debug, Default)]
+pub struct BoneMask {
+  /// Weight of each node (bone). Missing nodes have a weight of 0.0.
+  pub weights: AHashMap<i32, f32>,
+}
+
+impl BoneMask {
+  ///
+  /// A mask containing a node (bone) and everything underneath it.
+  ///
+  pub fn from_subtree(skeleton: &Skeleton, root: i32) -> BoneMask {
+    let weights = skeleton
+      .subtree(root)
+      .into_iter()
+      .map(|id| (id, 1.0))
+      .collect();
+    BoneMask { weights }
+  }
+
+  #[cfg(feature = "names")]
+  ///
+  /// A mask containing a named node (bone) and everything underneath it. Requires the `names` feature.
+  ///
+  /// Returns None if there is no node with that name.
+  ///
+  pub fn from_subtree_name(skeleton: &Skeleton, root_name: &str) -> Option<BoneMask> {
+    let root = skeleton.find_node(root_name)?;
+    Some(BoneMask::from_subtree(skeleton, root))
+  }
+
+  ///
+  /// Get the blend weight of a node (bone).
+  ///
+  pub fn weight(&self, id: i32) -> f32 {
+    self.weights.get(&id).copied().unwrap_or(0.0)
+  }
+
+  ///
+  /// Flip the mask so everything in the skeleton that was masked out is now masked in.
+  ///
+  /// Useful to get the lower body from an upper body mask.
+  ///
+  pub fn inverted(&self, skeleton: &Skeleton) -> BoneMask {
+    let weights = skeleton
+      .nodes
+      .iter()
+      .map(|node| (node.id, 1.0 - self.weight(node.id)))
+      .collect();
+    BoneMask { weights }
+  }
+}
+
+///
+/// Cross-fade from one animation clip to another over time.
+///
+/// This works like the `frame_blend` of minetest's `set_animation`.
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CrossFade {
+  /// How long the fade takes (in seconds).
+  pub duration: f32,
+  /// How long the fade has been going on (in seconds).
+  pub elapsed: f32,
+}
+
+impl CrossFade {
+  ///
+  /// Start a new cross-fade.
+  ///
+  pub fn new(duration: f32) -> Self {
+    CrossFade {
+      duration,
+      elapsed: 0.0,
+    }
+  }
+
+  ///
+  /// Move the fade forward in time.
+  ///
+  pub fn advance(&mut self, delta: f32) {
+    self.elapsed += delta;
+  }
+
+  ///
+  /// Get the blend weight of the clip that is being faded into.
+  ///
+  pub fn weight(&self) -> f32 {
+    if self.duration <= 0.0 {
+      return 1.0;
+    }
+    (self.elapsed / self.duration).clamp(0.0, 1.0)
+  }
+
+  ///
+  /// Get if the fade has finished.
+  ///
+  pub fn is_finished(&self) -> bool {
+    self.weight() >= 1.0
+  }
+
+  ///
+  /// Blend the outgoing pose into the incoming pose.
+  ///
+  pub fn apply(&self, from: &Pose, to: &Pose) -> Pose {
+    from.blend(to, self.weight(), None)
+  }
+}
@@ -0,0 +1,272 @@
+use ahash::AHashMap;
+use glam::{Quat, Vec3};
+
+use super::{BoneTransform, Pose};
+use crate::skeleton::Skeleton;
+
+///
+/// One overridden property (position, rotation or scale) of a node (bone).
+///
+/// This mirrors a property table of minetest's `set_bone_override`.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OverrideProperty<T> {
+  /// The value to apply.
+  pub value: T,
+  /// If true, the value replaces the animated value.
+  /// If false, it is applied on top of the animated value.
+  pub absolute: bool,
+  /// How long (in seconds) it takes to move from the previous value to this one.
+  pub interpolation: f32,
+
+  // The value this property is moving away from.
+  previous: Option<T>,
+  // How long the property has been moving.
+  elapsed: f32,
+}
+
+impl<T: Copy> OverrideProperty<T> {
+  ///
+  /// An override that replaces the animated value.
+  ///
+  pub fn absolute(value: T) -> Self {
+    OverrideProperty {
+      value,
+      absolute: true,
+      interpolation: 0.0,
+      previous: None,
+      elapsed: 0.0,
+    }
+  }
+
+  ///
+  /// An override that is applied on top of the animated value.
+  ///
+  pub fn relative(value: T) -> Self {
+    OverrideProperty {
+      value,
+      absolute: false,
+      interpolation: 0.0,
+      previous: None,
+      elapsed: 0.0,
+    }
+  }
+
+  ///
+  /// Set how long (in seconds) it takes to move into this value.
+  ///
+  pub fn with_interpolation(mut self, interpolation: f32) -> Self {
+    self.interpolation = interpolation;
+    self
+  }
+
+  ///
+  /// Get how far along the interpolation is. 0.0 - 1.0.
+  ///
+  fn progress(&self) -> f32 {
+    if self.interpolation <= 0.0 {
+      return 1.0;
+    }
+    (self.elapsed / self.interpolation).clamp(0.0, 1.0)
+  }
+
+  ///
+  /// Get the current value, taking interpolation into account.
+  ///
+  fn current(&self, interpolate: fn(T, T, f32) -> T) -> T {
+    match self.previous {
+      Some(previous) => interpolate(previous, self.value, self.progress()),
+      None => self.value,
+    }
+  }
+}
+
+///
+/// The overridden position, rotation and scale of a node (bone).
+///
+/// Properties that are None are left alone.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BoneOverride {
+  /// Position override.
+  pub position: Option<OverrideProperty<Vec3>>,
+  /// Rotation override.
+  pub rotation: Option<OverrideProperty<Quat>>,
+  /// Scale override.
+  pub scale: Option<OverrideProperty<Vec3>>,
+}
+
+impl BoneOverride {
+  ///
+  /// Apply this override onto an animated node (bone) transform.
+  ///
+  pub fn apply(&self, transform: &BoneTransform) -> BoneTransform {
+    let mut result = *transform;
+
+    if let Some(position) = &self.position {
+      let value = position.current(Vec3::lerp);
+      result.translation = if position.absolute {
+        value
+      } else {
+        transform.translation + value
+      };
+    }
+
+    if let Some(rotation) = &self.rotation {
+      let value = rotation.current(Quat::slerp);
+      result.rotation = if rotation.absolute {
+        value
+      } else {
+        value * transform.rotation
+      };
+    }
+
+    if let Some(scale) = &self.scale {
+      let value = scale.current(Vec3::lerp);
+      result.scale = if scale.absolute {
+        value
+      } else {
+        transform.scale * value
+      };
+    }
+
+    result
+  }
+}
+
+///
+/// All the bone overrides of an object, keyed by node id.
+///
+/// Apply this onto a sampled pose before computing the skinning matrices.
+///
+#[derive(Clone, Debug, Default)]
+pub struct BoneOverrides {
+  /// The overrides, keyed by node id.
+  pub overrides: AHashMap<i32, BoneOverride>,
+}
+
+impl BoneOverrides {
+  ///
+  /// Create a new empty set of bone overrides.
+  ///
+  pub fn new() -> Self {
+    BoneOverrides::default()
+  }
+
+  ///
+  /// Override a node (bone) by node id.
+  ///
+  /// Properties that are still moving, or were set before, interpolate from where they currently are.
+  ///
+  pub fn set(&mut self, id: i32, new_override: BoneOverride) {
+    let old = self.overrides.get(&id).copied().unwrap_or_default();
+
+    let new_override = BoneOverride {
+      position: continue_from(old.position, new_override.position, Vec3::lerp),
+      rotation: continue_from(old.rotation, new_override.rotation, Quat::slerp),
+      scale: continue_from(old.scale, new_override.scale, Vec3::lerp),
+    };
+
+    self.overrides.insert(id, new_override);
+  }
+
+  ///
+  /// Override a node (bone) by its index in the skin joints (what `JOINTS_0` refers to).
+  ///
+  /// Returns false if the joint does not exist.
+  ///
+  pub fn set_joint(
+    &mut self,
+    skeleton: &Skeleton,
+    joint: usize,
+    new_override: BoneOverride,
+  ) -> bool {
+    match skeleton.joints.get(joint) {
+      Some(id) => {
+        self.set(*id, new_override);
+        true
+      }
+      None => false,
+    }
+  }
+
+  #[cfg(feature = "names")]
+  ///
+  /// Override a node (bone) by name. Requires the `names` feature.
+  ///
+  /// Returns false if there is no node with that name.
+  ///
+  pub fn set_by_name(
+    &mut self,
+    skeleton: &Skeleton,
+    name: &str,
+    new_override: BoneOverride,
+  ) -> bool {
+    match skeleton.find_node(name) {
+      Some(id) => {
+        self.set(id, new_override);
+        true
+      }
+      None => false,
+    }
+  }
+
+  ///
+  /// Remove the override of a node (bone). It goes back to the animation.
+  ///
+  pub fn clear(&mut self, id: i32) {
+    self.overrides.remove(&id);
+  }
+
+  ///
+  /// Move the interpolation of every override forward in time.
+  ///
+  pub fn update(&mut self, delta: f32) {
+    for bone_override in self.overrides.values_mut() {
+      if let Some(position) = &mut bone_override.position {
+        position.elapsed += delta;
+      }
+      if let Some(rotation) = &mut bone_override.rotation {
+        rotation.elapsed += delta;
+      }
+      if let Some(scale) = &mut bone_override.scale {
+        scale.elapsed += delta;
+      }
+    }
+  }
+
+  ///
+  /// Apply all the overrides onto a sampled pose.
+  ///
+  /// Overridden nodes (bones) that are not in the pose are applied on top of identity.
+  ///
+  pub fn apply(&self, pose: &Pose) -> Pose {
+    let mut result = pose.clone();
+    for (id, bone_override) in &self.overrides {
+      result
+        .transforms
+        .insert(*id, bone_override.apply(&pose.get(*id)));
+    }
+    result
+  }
+}
+
+///
+/// Start a new override property from wherever the old one currently is.
+///
+fn continue_from<T: Copy>(
+  old: Option<OverrideProperty<T>>,
+  new: Option<OverrideProperty<T>>,
+  interpolate: fn(T, T, f32) -> T,
+) -> Option<OverrideProperty<T>> {
+  let mut new = new?;
+  new.elapsed = 0.0;
+  new.previous = match old {
+    // ? Moving from absolute to relative (or the other way) can't be interpolated. Snap it.
+    Some(old) if old.absolute == new.absolute && new.interpolation > 0.0 => {
+      Some(old.current(interpolate))
+    }
+    _ => None,
+  };
+  Some(new)
+}
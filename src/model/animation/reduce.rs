@@ -0,0 +1,200 @@
+use std::mem::size_of;
+
+use ahash::AHashMap;
+use glam::{Quat, Vec3};
+
+use super::BoneAnimationChannel;
+use crate::minetest_gltf::MinetestGLTF;
+
+///
+/// How far a reduced track is allowed to drift away from the original.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReductionTolerance {
+  /// Max translation error (in model units).
+  pub translation: f32,
+  /// Max rotation error (in radians).
+  pub rotation: f32,
+  /// Max scale error.
+  pub scale: f32,
+}
+
+impl Default for ReductionTolerance {
+  fn default() -> Self {
+    ReductionTolerance {
+      translation: 0.0001,
+      rotation: 0.0001,
+      scale: 0.0001,
+    }
+  }
+}
+
+///
+/// How much a keyframe reduction saved.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReductionReport {
+  /// Keyframes before the reduction.
+  pub keyframes_before: usize,
+  /// Keyframes after the reduction.
+  pub keyframes_after: usize,
+  /// Bytes of keyframe and timestamp data before the reduction.
+  pub bytes_before: usize,
+  /// Bytes of keyframe and timestamp data after the reduction.
+  pub bytes_after: usize,
+  /// How many tracks were collapsed into a single keyframe.
+  pub constant_tracks: usize,
+}
+
+impl ReductionReport {
+  ///
+  /// How many keyframes were dropped.
+  ///
+  pub fn keyframes_saved(&self) -> usize {
+    self.keyframes_before - self.keyframes_after
+  }
+
+  ///
+  /// How many bytes were dropped.
+  ///
+  pub fn bytes_saved(&self) -> usize {
+    self.bytes_before - self.bytes_after
+  }
+
+  ///
+  /// Add another report into this one.
+  ///
+  pub fn merge(&mut self, other: &ReductionReport) {
+    self.keyframes_before += other.keyframes_before;
+    self.keyframes_after += other.keyframes_after;
+    self.bytes_before += other.bytes_before;
+    self.bytes_after += other.bytes_after;
+    self.constant_tracks += other.constant_tracks;
+  }
+}
+
+impl BoneAnimationChannel {
+  ///
+  /// Drop the keyframes that interpolation can rebuild within the tolerance.
+  ///
+  /// Tracks that don't change are collapsed into a single keyframe.
+  ///
+  /// Note: The morph target weights are left alone.
+  ///
+  pub fn reduce(&mut self, tolerance: &ReductionTolerance) -> ReductionReport {
+    let mut report = ReductionReport::default();
+
+    reduce_track(
+      &mut self.translation_timestamps,
+      &mut self.translations,
+      Vec3::lerp,
+      |a, b| a.distance(b) <= tolerance.translation,
+      &mut report,
+    );
+    reduce_track(
+      &mut self.rotation_timestamps,
+      &mut self.rotations,
+      Quat::slerp,
+      |a, b| a.angle_between(b) <= tolerance.rotation,
+      &mut report,
+    );
+    reduce_track(
+      &mut self.scale_timestamps,
+      &mut self.scales,
+      Vec3::lerp,
+      |a, b| a.distance(b) <= tolerance.scale,
+      &mut report,
+    );
+
+    report
+  }
+}
+
+///
+/// Reduce the keyframes of every channel. See [BoneAnimationChannel::reduce].
+///
+pub fn reduce_keyframes(
+  bone_animations: &mut AHashMap<i32, BoneAnimationChannel>,
+  tolerance: &ReductionTolerance,
+) -> ReductionReport {
+  let mut report = ReductionReport::default();
+  for channel in bone_animations.values_mut() {
+    report.merge(&channel.reduce(tolerance));
+  }
+  report
+}
+
+impl MinetestGLTF {
+  ///
+  /// Reduce the keyframes of every bone animation channel. See [BoneAnimationChannel::reduce].
+  ///
+  pub fn reduce_keyframes(&mut self, tolerance: &ReductionTolerance) -> ReductionReport {
+    match &mut self.bone_animations {
+      Some(bone_animations) => reduce_keyframes(bone_animations, tolerance),
+      None => ReductionReport::default(),
+    }
+  }
+}
+
+///
+/// Greedily drop every keyframe that can be rebuilt from its neighbors.
+///
+fn reduce_track<T: Copy>(
+  timestamps: &mut Vec<f32>,
+  values: &mut Vec<T>,
+  interpolate: fn(T, T, f32) -> T,
+  close_enough: impl Fn(T, T) -> bool,
+  report: &mut ReductionReport,
+) {
+  let frame_size = size_of::<T>() + size_of::<f32>();
+  let length = timestamps.len().min(values.len());
+
+  report.keyframes_before += length;
+  report.bytes_before += length * frame_size;
+
+  if length > 1 {
+    // * If nothing ever changes, one keyframe is all it needs.
+    if values[..length]
+      .iter()
+      .all(|value| close_enough(values[0], *value))
+    {
+      timestamps.truncate(1);
+      values.truncate(1);
+      report.constant_tracks += 1;
+    } else {
+      let mut kept = vec![0];
+      let mut anchor = 0;
+
+      while anchor < length - 1 {
+        // Push the end out as far as it can go while everything in between still fits.
+        let mut end = anchor + 1;
+        while end + 1 < length {
+          let candidate = end + 1;
+          let span = timestamps[candidate] - timestamps[anchor];
+          let fits = (anchor + 1..candidate).all(|i| {
+            let percentile = if span > 0.0 {
+              (timestamps[i] - timestamps[anchor]) / span
+            } else {
+              0.0
+            };
+            let rebuilt = interpolate(values[anchor], values[candidate], percentile);
+            close_enough(rebuilt, values[i])
+          });
+          if !fits {
+            break;
+          }
+          end = candidate;
+        }
+        kept.push(end);
+        anchor = end;
+      }
+
+      *timestamps = kept.iter().map(|i| timestamps[*i]).collect();
+      *values = kept.iter().map(|i| values[*i]).collect();
+    }
+  }
+
+  let length = timestamps.len().min(values.len());
+  report.keyframes_after += length;
+  report.bytes_after += length * frame_size;
+}
@@ -0,0 +1,118 @@
+use ahash::AHashMap;
+use glam::{BVec3, Vec3};
+
+use super::{sample_track, BoneAnimationChannel};
+use crate::minetest_gltf::MinetestGLTF;
+
+///
+/// Which node (bone) is the root of the motion.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum RootBone {
+  /// A node (bone) by node id.
+  Node(i32),
+  /// The first joint of the skin. This is usually the root bone.
+  FirstJoint,
+  #[cfg(feature = "names")]
+  /// A node (bone) by name. Requires the `names` feature.
+  Name(String),
+}
+
+///
+/// The translation the root bone moves through each frame.
+///
+/// The deltas are in the space of the root bone's parent.
+///
+#[derive(Clone, Debug, Default)]
+pub struct RootMotion {
+  /// The node id of the root bone.
+  pub root: i32,
+  /// Timestamp of each frame.
+  pub timestamps: Vec<f32>,
+  /// How far the root moved since the previous frame. The first frame is always zero.
+  pub deltas: Vec<Vec3>,
+}
+
+impl RootMotion {
+  ///
+  /// How far the root moves over the whole animation.
+  ///
+  pub fn total(&self) -> Vec3 {
+    self.deltas.iter().sum()
+  }
+
+  ///
+  /// How far the root has moved from the start at a point in time (in seconds).
+  ///
+  pub fn displacement(&self, time: f32) -> Vec3 {
+    let mut accumulated = Vec::with_capacity(self.deltas.len());
+    let mut total = Vec3::ZERO;
+    for delta in &self.deltas {
+      total += *delta;
+      accumulated.push(total);
+    }
+    sample_track(&self.timestamps, &accumulated, time, Vec3::lerp).unwrap_or(Vec3::ZERO)
+  }
+}
+
+///
+/// Pull the translation of the root bone out of the animation.
+///
+/// Only the axes enabled in `axes` are extracted. In the returned in-place animation
+/// those axes are pinned to the first frame, so the model stays where it starts.
+///
+/// Returns None if the root bone is not animated.
+///
+pub fn extract_root_motion(
+  bone_animations: &AHashMap<i32, BoneAnimationChannel>,
+  root: i32,
+  axes: BVec3,
+) -> Option<(RootMotion, AHashMap<i32, BoneAnimationChannel>)> {
+  let root_channel = bone_animations.get(&root)?;
+  let first = *root_channel.translations.first()?;
+
+  let mask = Vec3::select(axes, Vec3::ONE, Vec3::ZERO);
+
+  let mut deltas = Vec::with_capacity(root_channel.translations.len());
+  let mut previous = first;
+  for translation in &root_channel.translations {
+    deltas.push((*translation - previous) * mask);
+    previous = *translation;
+  }
+
+  let mut in_place = bone_animations.clone();
+  if let Some(channel) = in_place.get_mut(&root) {
+    for translation in &mut channel.translations {
+      *translation = Vec3::select(axes, first, *translation);
+    }
+  }
+
+  let motion = RootMotion {
+    root,
+    timestamps: root_channel.translation_timestamps.clone(),
+    deltas,
+  };
+
+  Some((motion, in_place))
+}
+
+impl MinetestGLTF {
+  ///
+  /// Pull the translation of the root bone out of the animation.
+  ///
+  /// See [extract_root_motion](crate::animation::extract_root_motion).
+  ///
+  pub fn extract_root_motion(
+    &self,
+    root: RootBone,
+    axes: BVec3,
+  ) -> Option<(RootMotion, AHashMap<i32, BoneAnimationChannel>)> {
+    let root = match root {
+      RootBone::Node(id) => id,
+      RootBone::FirstJoint => *self.skeleton.as_ref()?.joints.first()?,
+      #[cfg(feature = "names")]
+      RootBone::Name(name) => self.node_id(&name)?,
+    };
+    extract_root_motion(self.bone_animations.as_ref()?, root, axes)
+  }
+}
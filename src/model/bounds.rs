@@ -0,0 +1,376 @@
+use glam::{Mat4, Vec3};
+
+use crate::{
+  animation::Pose, minetest_gltf::MinetestGLTF, primitive::Vertex, skeleton::Skeleton, Model,
+  Primitive,
+};
+
+///
+/// An axis-aligned bounding box.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BoundingBox {
+  /// The smallest corner.
+  pub min: Vec3,
+  /// The largest corner.
+  pub max: Vec3,
+}
+
+impl BoundingBox {
+  ///
+  /// Create a new bounding box. The corners are sorted out so min is smaller than max.
+  ///
+  pub fn new(a: Vec3, b: Vec3) -> Self {
+    BoundingBox {
+      min: a.min(b),
+      max: a.max(b),
+    }
+  }
+
+  ///
+  /// The smallest box that holds every point. None if there are no points.
+  ///
+  pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+    let mut points = points.into_iter();
+    let first = points.next()?;
+    Some(
+      points.fold(BoundingBox::new(first, first), |bounding_box, point| {
+        bounding_box.expanded(point)
+      }),
+    )
+  }
+
+  ///
+  /// Grow the box so it holds a point.
+  ///
+  pub fn expanded(&self, point: Vec3) -> Self {
+    BoundingBox {
+      min: self.min.min(point),
+      max: self.max.max(point),
+    }
+  }
+
+  ///
+  /// The smallest box that holds both boxes.
+  ///
+  pub fn union(&self, other: &BoundingBox) -> Self {
+    BoundingBox {
+      min: self.min.min(other.min),
+      max: self.max.max(other.max),
+    }
+  }
+
+  ///
+  /// The center of the box.
+  ///
+  pub fn center(&self) -> Vec3 {
+    (self.min + self.max) * 0.5
+  }
+
+  ///
+  /// The width, height and depth of the box.
+  ///
+  pub fn size(&self) -> Vec3 {
+    self.max - self.min
+  }
+
+  ///
+  /// Get if a point is inside of the box (or on the edge).
+  ///
+  pub fn contains(&self, point: Vec3) -> bool {
+    point.cmpge(self.min).all() && point.cmple(self.max).all()
+  }
+
+  ///
+  /// Get the 8 corners of the box.
+  ///
+  pub fn corners(&self) -> [Vec3; 8] {
+    let (min, max) = (self.min, self.max);
+    [
+      Vec3::new(min.x, min.y, min.z),
+      Vec3::new(max.x, min.y, min.z),
+      Vec3::new(min.x, max.y, min.z),
+      Vec3::new(max.x, max.y, min.z),
+      Vec3::new(min.x, min.y, max.z),
+      Vec3::new(max.x, min.y, max.z),
+      Vec3::new(min.x, max.y, max.z),
+      Vec3::new(max.x, max.y, max.z),
+    ]
+  }
+
+  ///
+  /// Move the box by a transform. The result holds the whole transformed box, so it
+  /// grows if the transform rotates.
+  ///
+  pub fn transformed(&self, transform: &Mat4) -> Self {
+    let corners = self
+      .corners()
+      .map(|corner| transform.transform_point3(corner));
+    let mut bounding_box = BoundingBox::new(corners[0], corners[0]);
+    for corner in &corners[1..] {
+      bounding_box = bounding_box.expanded(*corner);
+    }
+    bounding_box
+  }
+}
+
+///
+/// A bounding sphere.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BoundingSphere {
+  /// The center of the sphere.
+  pub center: Vec3,
+  /// The radius of the sphere.
+  pub radius: f32,
+}
+
+impl BoundingSphere {
+  ///
+  /// A sphere around the center of the bounding box that holds every point.
+  ///
+  /// This isn't the smallest possible sphere, but it's close and cheap.
+  ///
+  pub fn from_points(bounding_box: &BoundingBox, points: impl IntoIterator<Item = Vec3>) -> Self {
+    let center = bounding_box.center();
+    let radius = points
+      .into_iter()
+      .map(|point| point.distance(center))
+      .fold(0.0, f32::max);
+    BoundingSphere { center, radius }
+  }
+
+  ///
+  /// The smallest sphere around the center of `bounding_box` that holds every sphere.
+  ///
+  pub fn from_spheres(bounding_box: &BoundingBox, spheres: &[BoundingSphere]) -> Self {
+    let center = bounding_box.center();
+    let radius = spheres
+      .iter()
+      .map(|sphere| sphere.center.distance(center) + sphere.radius)
+      .fold(0.0, f32::max);
+    BoundingSphere { center, radius }
+  }
+
+  ///
+  /// The smallest sphere that holds both spheres.
+  ///
+  pub fn union(&self, other: &BoundingSphere) -> Self {
+    let distance = self.center.distance(other.center);
+    if distance + other.radius <= self.radius {
+      return *self;
+    }
+    if distance + self.radius <= other.radius {
+      return *other;
+    }
+    let radius = (distance + self.radius + other.radius) * 0.5;
+    BoundingSphere {
+      center: self.center + (other.center - self.center) * ((radius - self.radius) / distance),
+      radius,
+    }
+  }
+
+  ///
+  /// Get if a point is inside of the sphere (or on the edge).
+  ///
+  pub fn contains(&self, point: Vec3) -> bool {
+    point.distance_squared(self.center) <= self.radius * self.radius
+  }
+}
+
+///
+/// The bounding box and bounding sphere of some geometry.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Bounds {
+  /// Axis-aligned bounding box.
+  pub bounding_box: BoundingBox,
+  /// Bounding sphere.
+  pub bounding_sphere: BoundingSphere,
+}
+
+impl Bounds {
+  ///
+  /// Work out the bounds of a list of vertices. Empty lists get zeroed out bounds.
+  ///
+  pub fn from_vertices(vertices: &[Vertex]) -> Self {
+    Bounds::from_points(vertices.iter().map(|vertex| vertex.position))
+  }
+
+  ///
+  /// Work out the bounds of some points. No points gets zeroed out bounds.
+  ///
+  pub fn from_points(points: impl Iterator<Item = Vec3> + Clone) -> Self {
+    let bounding_box = BoundingBox::from_points(points.clone()).unwrap_or_default();
+    Bounds {
+      bounding_box,
+      bounding_sphere: BoundingSphere::from_points(&bounding_box, points),
+    }
+  }
+
+  ///
+  /// The bounds that hold both bounds.
+  ///
+  pub fn union(&self, other: &Bounds) -> Self {
+    Bounds {
+      bounding_box: self.bounding_box.union(&other.bounding_box),
+      bounding_sphere: self.bounding_sphere.union(&other.bounding_sphere),
+    }
+  }
+}
+
+impl Primitive {
+  ///
+  /// Get the cached bounds of this primitive.
+  ///
+  /// Call `update_bounds` after moving the vertices around.
+  ///
+  pub fn bounds(&self) -> &Bounds {
+    &self.bounds
+  }
+
+  ///
+  /// Work the cached bounds out again from the vertices.
+  ///
+  pub fn update_bounds(&mut self) {
+    self.bounds = Bounds::from_vertices(&self.vertices);
+  }
+}
+
+impl Model {
+  ///
+  /// Get the bounds of the whole model, built up out of the cached primitive bounds.
+  ///
+  /// None if there are no primitives.
+  ///
+  pub fn bounds(&self) -> Option<Bounds> {
+    let first = self.primitives.first()?;
+    let bounding_box = self
+      .primitives
+      .iter()
+      .fold(first.bounds.bounding_box, |bounding_box, primitive| {
+        bounding_box.union(&primitive.bounds.bounding_box)
+      });
+    let spheres: Vec<BoundingSphere> = self
+      .primitives
+      .iter()
+      .map(|primitive| primitive.bounds.bounding_sphere)
+      .collect();
+    Some(Bounds {
+      bounding_box,
+      bounding_sphere: BoundingSphere::from_spheres(&bounding_box, &spheres),
+    })
+  }
+
+  ///
+  /// Work the cached bounds of every primitive out again from the vertices.
+  ///
+  pub fn update_bounds(&mut self) {
+    for primitive in &mut self.primitives {
+      primitive.update_bounds();
+    }
+  }
+}
+
+impl MinetestGLTF {
+  ///
+  /// Get every timestamp (in seconds) that has a keyframe, sorted.
+  ///
+  pub(crate) fn keyframe_times(&self) -> Vec<f32> {
+    let mut times: Vec<f32> = vec![];
+    if let Some(bone_animations) = &self.bone_animations {
+      for channel in bone_animations.values() {
+        times.extend(&channel.translation_timestamps);
+        times.extend(&channel.rotation_timestamps);
+        times.extend(&channel.scale_timestamps);
+      }
+    }
+    times.sort_by(f32::total_cmp);
+    times.dedup();
+    times
+  }
+
+  ///
+  /// Get the position of every vertex of every primitive, skinned with a pose.
+  ///
+  /// Vertices that aren't skinned stay where they are.
+  ///
+  pub(crate) fn skinned_positions(&self, skeleton: &Skeleton, pose: &Pose) -> Vec<Vec3> {
+    let model = match &self.model {
+      Some(model) => model,
+      None => return vec![],
+    };
+    let skinning_matrices = skeleton.skinning_matrices(pose);
+
+    let mut positions = vec![];
+    for primitive in &model.primitives {
+      for (i, vertex) in primitive.vertices.iter().enumerate() {
+        let (joints, weights) = match (primitive.joints.get(i), primitive.weights.get(i)) {
+          (Some(joints), Some(weights)) if primitive.has_joints => (joints, weights),
+          _ => {
+            positions.push(vertex.position);
+            continue;
+          }
+        };
+
+        let mut skinned = Vec3::ZERO;
+        let mut total_weight = 0.0;
+        for (joint, weight) in joints.iter().zip(weights) {
+          if let Some(matrix) = skinning_matrices.get(*joint as usize) {
+            skinned += matrix.transform_point3(vertex.position) * *weight;
+            total_weight += weight;
+          }
+        }
+        positions.push(if total_weight > 0.0 {
+          skinned / total_weight
+        } else {
+          vertex.position
+        });
+      }
+    }
+    positions
+  }
+
+  ///
+  /// Get the bounds that hold the model in every keyframe of the bone animations.
+  ///
+  /// Falls back to the rest bounds of the model if it isn't skinned or animated.
+  ///
+  /// The bounds of each keyframe are folded together one at a time, so the sphere is the one
+  /// that holds every keyframe sphere, not one around the center of the box.
+  ///
+  /// None if there is no model.
+  ///
+  pub fn animated_bounds(&self) -> Option<Bounds> {
+    let rest = self.model.as_ref()?.bounds()?;
+    let frames = match self.animated_frames() {
+      Some(frames) => frames,
+      None => return Some(rest),
+    };
+    Some(
+      frames
+        .map(|positions| Bounds::from_points(positions.iter().copied()))
+        .reduce(|bounds, frame| bounds.union(&frame))
+        .unwrap_or(rest),
+    )
+  }
+
+  ///
+  /// Get the skinned position of every vertex, one keyframe of the bone animations at a time.
+  ///
+  /// Each keyframe is skinned as it's reached, so only one keyframe is held at once.
+  ///
+  /// None if the model isn't skinned or animated.
+  ///
+  pub(crate) fn animated_frames(&self) -> Option<impl Iterator<Item = Vec<Vec3>> + '_> {
+    let (skeleton, bone_animations) = match (&self.skeleton, &self.bone_animations) {
+      (Some(skeleton), Some(bone_animations)) => (skeleton, bone_animations),
+      _ => return None,
+    };
+
+    Some(self.keyframe_times().into_iter().map(move |time| {
+      let pose = Pose::sample_with_rest(skeleton, bone_animations, time);
+      self.skinned_positions(skeleton, &pose)
+    }))
+  }
+}
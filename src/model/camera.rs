@@ -0,0 +1,225 @@
+use ahash::AHashMap;
+use glam::{Mat4, Quat, Vec2, Vec3};
+use gltf::{animation::Property, camera::Projection as GltfProjection, Gltf};
+
+use crate::animation::{sample_track, BoneAnimationChannel, BoneTransform};
+
+/// Contains camera properties.
+#[derive(Clone, Debug)]
+pub struct Camera {
+  #[cfg(feature = "names")]
+  /// Camera name. Requires the `names` feature.
+  pub name: Option<String>,
+
+  #[cfg(feature = "extras")]
+  /// Camera extra data. Requires the `extras` feature.
+  pub extras: gltf::json::extras::Extras,
+
+  /// The node (bone) id the camera is attached to.
+  pub node: i32,
+
+  /// The world transform of the node at rest. (Also called the camera to world matrix.)
+  pub transform: Mat4,
+
+  /// Projection type and specific parameters
+  pub projection: Projection,
+
+  /// The distance to the far clipping plane.
+  ///
+  /// For perspective projection, this may be infinite.
+  pub zfar: f32,
+
+  /// The distance to the near clipping plane.
+  pub znear: f32,
+
+  /// The nodes from the root of the scene down to the camera node.
+  pub path: Vec<CameraPathNode>,
+}
+
+/// A node on the way from the root of the scene down to a camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraPathNode {
+  /// The node (bone) id.
+  pub id: i32,
+  /// The rest transform.
+  pub rest: BoneTransform,
+  /// If the translation, rotation and scale are animated. The rest of the tracks keep their
+  /// rest value, since the finalized animations fill them in with identity.
+  pub animated: [bool; 3],
+}
+
+/// Camera projections
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+  /// Perspective projection
+  Perspective {
+    /// Y-axis FOV, in radians
+    yfov: f32,
+    /// Aspect ratio, if specified
+    aspect_ratio: Option<f32>,
+  },
+  /// Orthographic projection
+  Orthographic {
+    /// Projection scale
+    scale: Vec2,
+  },
+}
+
+impl Default for Projection {
+  fn default() -> Self {
+    Self::Perspective {
+      yfov: 0.399,
+      aspect_ratio: None,
+    }
+  }
+}
+
+impl Camera {
+  /// Position of the camera.
+  pub fn position(&self) -> Vec3 {
+    self.transform.w_axis.truncate()
+  }
+
+  /// Right vector of the camera.
+  pub fn right(&self) -> Vec3 {
+    self.transform.x_axis.truncate().normalize()
+  }
+
+  /// Up vector of the camera.
+  pub fn up(&self) -> Vec3 {
+    self.transform.y_axis.truncate().normalize()
+  }
+
+  /// Forward vector of the camera (backside direction).
+  ///
+  /// glTF cameras look down -Z, so this points out of the back of the camera. After a
+  /// `CoordinateConversion` cameras look down +Z instead, like irrlicht cameras.
+  pub fn forward(&self) -> Vec3 {
+    self.transform.z_axis.truncate().normalize()
+  }
+
+  ///
+  /// Sample the world transform of the camera at a point in time (in seconds).
+  ///
+  /// Every node on the way down to the camera can be animated. Tracks that aren't animated keep
+  /// their rest value. This is what drives the player camera in a cutscene.
+  ///
+  /// Only the first animation of the file is loaded, so that's the one that gets sampled.
+  ///
+  pub fn sample(&self, bone_animations: &AHashMap<i32, BoneAnimationChannel>, time: f32) -> Mat4 {
+    self.path.iter().fold(Mat4::IDENTITY, |parent, node| {
+      let rest = node.rest;
+      let local = match bone_animations.get(&node.id) {
+        Some(channel) => BoneTransform {
+          translation: match node.animated[0] {
+            true => sample_track(
+              &channel.translation_timestamps,
+              &channel.translations,
+              time,
+              Vec3::lerp,
+            )
+            .unwrap_or(rest.translation),
+            false => rest.translation,
+          },
+          rotation: match node.animated[1] {
+            true => sample_track(
+              &channel.rotation_timestamps,
+              &channel.rotations,
+              time,
+              Quat::slerp,
+            )
+            .unwrap_or(rest.rotation),
+            false => rest.rotation,
+          },
+          scale: match node.animated[2] {
+            true => sample_track(&channel.scale_timestamps, &channel.scales, time, Vec3::lerp)
+              .unwrap_or(rest.scale),
+            false => rest.scale,
+          },
+        },
+        None => rest,
+      };
+      parent * local.to_matrix()
+    })
+  }
+
+  ///
+  /// Mark which tracks of the path are animated by the first animation, like the bone animations.
+  ///
+  pub(crate) fn mark_animated(&mut self, gltf_data: &Gltf) {
+    let animation = match gltf_data.animations().next() {
+      Some(animation) => animation,
+      None => return,
+    };
+    for channel in animation.channels() {
+      let target = channel.target();
+      let track = match target.property() {
+        Property::Translation => 0,
+        Property::Rotation => 1,
+        Property::Scale => 2,
+        Property::MorphTargetWeights => continue,
+      };
+      let id = target.node().index() as i32;
+      for node in self.path.iter_mut().filter(|node| node.id == id) {
+        node.animated[track] = true;
+      }
+    }
+  }
+
+  ///
+  /// Load up a camera.
+  ///
+  pub(crate) fn load(gltf_cam: gltf::Camera, transform: &Mat4, path: Vec<CameraPathNode>) -> Self {
+    let mut cam = Self {
+      node: path.last().map(|node| node.id).unwrap_or(-1),
+      transform: *transform,
+      path,
+      ..Default::default()
+    };
+
+    #[cfg(feature = "names")]
+    {
+      cam.name = gltf_cam.name().map(String::from);
+    }
+    #[cfg(feature = "extras")]
+    {
+      cam.extras = gltf_cam.extras().clone();
+    }
+
+    match gltf_cam.projection() {
+      GltfProjection::Orthographic(ortho) => {
+        cam.projection = Projection::Orthographic {
+          scale: Vec2::new(ortho.xmag(), ortho.ymag()),
+        };
+        cam.zfar = ortho.zfar();
+        cam.znear = ortho.znear();
+      }
+      GltfProjection::Perspective(pers) => {
+        cam.projection = Projection::Perspective {
+          yfov: pers.yfov(),
+          aspect_ratio: pers.aspect_ratio(),
+        };
+        cam.zfar = pers.zfar().unwrap_or(f32::INFINITY);
+        cam.znear = pers.znear();
+      }
+    };
+    cam
+  }
+}
+
+impl Default for Camera {
+  fn default() -> Self {
+    Camera {
+      #[cfg(feature = "names")]
+      name: None,
+      #[cfg(feature = "extras")]
+      extras: Default::default(),
+      node: -1,
+      transform: Mat4::IDENTITY,
+      projection: Projection::default(),
+      zfar: f32::INFINITY,
+      znear: 0.,
+      path: vec![],
+    }
+  }
+}
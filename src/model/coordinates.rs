@@ -0,0 +1,207 @@
+use glam::{Mat4, Quat, Vec3, Vec4};
+
+use crate::{
+  animation::BoneAnimationChannel,
+  camera::Camera,
+  light::{Light, LightKind},
+  minetest_gltf::MinetestGLTF,
+  primitive::Mode,
+  skeleton::Skeleton,
+  Primitive,
+};
+
+/// The size of a node in minetest. Entity models are conventionally scaled by this.
+pub const BS: f32 = 10.0;
+
+///
+/// Converts glTF (right-handed, Y-up, meters) into minetest/irrlicht (left-handed, Y-up) space.
+///
+/// This mirrors the Z axis and scales everything uniformly.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CoordinateConversion {
+  /// Uniform scale applied to every position and translation.
+  pub scale: f32,
+}
+
+impl Default for CoordinateConversion {
+  fn default() -> Self {
+    CoordinateConversion { scale: 1.0 }
+  }
+}
+
+impl CoordinateConversion {
+  ///
+  /// The conversion for a minetest entity model. Scaled by BS.
+  ///
+  pub fn minetest() -> Self {
+    CoordinateConversion { scale: BS }
+  }
+
+  ///
+  /// Create a conversion with a custom scale factor.
+  ///
+  pub fn with_scale(scale: f32) -> Self {
+    CoordinateConversion { scale }
+  }
+
+  ///
+  /// The conversion as a matrix.
+  ///
+  pub fn matrix(&self) -> Mat4 {
+    Mat4::from_scale(Vec3::new(self.scale, self.scale, -self.scale))
+  }
+
+  ///
+  /// Convert a position or translation.
+  ///
+  pub fn position(&self, position: Vec3) -> Vec3 {
+    Vec3::new(position.x, position.y, -position.z) * self.scale
+  }
+
+  ///
+  /// Convert a direction, like a normal. The length is kept.
+  ///
+  pub fn direction(&self, direction: Vec3) -> Vec3 {
+    Vec3::new(direction.x, direction.y, -direction.z)
+  }
+
+  ///
+  /// Convert a tangent. Mirroring flips the handedness of the tangent basis, so w is flipped too.
+  ///
+  pub fn tangent(&self, tangent: Vec4) -> Vec4 {
+    Vec4::new(tangent.x, tangent.y, -tangent.z, -tangent.w)
+  }
+
+  ///
+  /// Convert a rotation.
+  ///
+  pub fn rotation(&self, rotation: Quat) -> Quat {
+    Quat::from_xyzw(-rotation.x, -rotation.y, rotation.z, rotation.w)
+  }
+
+  ///
+  /// Convert a transformation matrix. (Like an inverse bind matrix.)
+  ///
+  pub fn transform(&self, transform: &Mat4) -> Mat4 {
+    let conversion = self.matrix();
+    conversion * *transform * conversion.inverse()
+  }
+
+  ///
+  /// Convert a primitive's vertices and reverse its winding.
+  ///
+  pub fn convert_primitive(&self, primitive: &mut Primitive) {
+    for vertex in &mut primitive.vertices {
+      vertex.position = self.position(vertex.position);
+      vertex.normal = self.direction(vertex.normal);
+      vertex.tangent = self.tangent(vertex.tangent);
+    }
+    primitive.update_bounds();
+
+    match primitive.mode {
+      Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan => {}
+      // Winding doesn't matter for these.
+      _ => return,
+    }
+
+    let indices = primitive
+      .indices
+      .get_or_insert_with(|| (0..primitive.vertices.len() as u32).collect());
+
+    match primitive.mode {
+      Mode::Triangles => {
+        for triangle in indices.chunks_exact_mut(3) {
+          triangle.swap(1, 2);
+        }
+      }
+      Mode::TriangleFan if indices.len() > 1 => {
+        indices[1..].reverse();
+      }
+      Mode::TriangleStrip => {
+        // ? A degenerate triangle at the start flips the winding of every triangle after it.
+        if let Some(first) = indices.first().copied() {
+          indices.insert(0, first);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  ///
+  /// Convert the rest pose and inverse bind matrices of a skeleton.
+  ///
+  pub fn convert_skeleton(&self, skeleton: &mut Skeleton) {
+    for node in &mut skeleton.nodes {
+      node.translation = self.position(node.translation);
+      node.rotation = self.rotation(node.rotation);
+    }
+    for inverse_bind in &mut skeleton.inverse_bind_matrices {
+      *inverse_bind = self.transform(inverse_bind);
+    }
+  }
+
+  ///
+  /// Convert the translations and rotations of an animation channel.
+  ///
+  pub fn convert_channel(&self, channel: &mut BoneAnimationChannel) {
+    for translation in &mut channel.translations {
+      *translation = self.position(*translation);
+    }
+    for rotation in &mut channel.rotations {
+      *rotation = self.rotation(*rotation);
+    }
+  }
+
+  ///
+  /// Convert the placement and range of a light.
+  ///
+  /// Point and spot intensities are scaled by the square of the scale, so the light still
+  /// reaches as far.
+  ///
+  pub fn convert_light(&self, light: &mut Light) {
+    light.transform = self.transform(&light.transform);
+    light.position = self.position(light.position);
+    light.direction = self.direction(light.direction);
+    light.range = light.range.map(|range| range * self.scale);
+    if light.kind != LightKind::Directional {
+      light.intensity *= self.scale * self.scale;
+    }
+  }
+
+  ///
+  /// Convert the placement of a camera, and the rest transforms of the nodes above it.
+  ///
+  pub fn convert_camera(&self, camera: &mut Camera) {
+    camera.transform = self.transform(&camera.transform);
+    for node in &mut camera.path {
+      node.rest.translation = self.position(node.rest.translation);
+      node.rest.rotation = self.rotation(node.rest.rotation);
+    }
+  }
+
+  ///
+  /// Convert everything in a loaded model.
+  ///
+  pub fn convert(&self, minetest_gltf: &mut MinetestGLTF) {
+    if let Some(model) = &mut minetest_gltf.model {
+      for primitive in &mut model.primitives {
+        self.convert_primitive(primitive);
+      }
+      for light in &mut model.lights {
+        self.convert_light(light);
+      }
+      for camera in &mut model.cameras {
+        self.convert_camera(camera);
+      }
+    }
+    if let Some(skeleton) = &mut minetest_gltf.skeleton {
+      self.convert_skeleton(skeleton);
+    }
+    if let Some(bone_animations) = &mut minetest_gltf.bone_animations {
+      for channel in bone_animations.values_mut() {
+        self.convert_channel(channel);
+      }
+    }
+  }
+}
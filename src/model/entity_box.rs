@@ -0,0 +1,244 @@
+use glam::Vec3;
+
+use crate::{
+  coordinates::{CoordinateConversion, BS},
+  minetest_gltf::MinetestGLTF,
+};
+
+///
+/// Which vertex positions an entity box is built from.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BoxPose {
+  /// The model as it is loaded.
+  #[default]
+  Rest,
+  /// Every keyframe of the bone animations. Falls back to the rest pose if there are none.
+  Animated,
+}
+
+///
+/// How to build a minetest `collisionbox` or `selectionbox`.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EntityBoxOptions {
+  /// Which vertex positions to use.
+  pub pose: BoxPose,
+  /// The conversion into minetest space, applied before scaling down by BS.
+  ///
+  /// Set this to None if the model was already converted while loading.
+  pub conversion: Option<CoordinateConversion>,
+  /// The `visual_size` of the entity.
+  pub visual_size: Vec3,
+  /// If set, the box only holds this fraction (0.0 - 1.0) of the vertices on each axis.
+  /// The rest are trimmed off evenly from both ends.
+  ///
+  /// Useful to keep a stray hand or hat from blowing up a collision box.
+  pub percentile: Option<f32>,
+}
+
+impl Default for EntityBoxOptions {
+  fn default() -> Self {
+    EntityBoxOptions {
+      pose: BoxPose::Rest,
+      conversion: Some(CoordinateConversion::default()),
+      visual_size: Vec3::ONE,
+      percentile: None,
+    }
+  }
+}
+
+///
+/// A box in minetest units (nodes), relative to the entity position.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EntityBox {
+  /// The smallest corner.
+  pub min: Vec3,
+  /// The largest corner.
+  pub max: Vec3,
+}
+
+impl EntityBox {
+  ///
+  /// Get the box in the order minetest wants it. `{x1, y1, z1, x2, y2, z2}`
+  ///
+  pub fn to_array(&self) -> [f32; 6] {
+    [
+      self.min.x, self.min.y, self.min.z, self.max.x, self.max.y, self.max.z,
+    ]
+  }
+
+  ///
+  /// Get the box as a lua table, ready to be pasted into an entity definition.
+  ///
+  /// Numbers are rounded to 3 decimal places.
+  ///
+  pub fn to_lua(&self) -> String {
+    let numbers: Vec<String> = self
+      .to_array()
+      .iter()
+      // Adding 0.0 turns -0 into 0.
+      .map(|number| ((number * 1000.0).round() / 1000.0 + 0.0).to_string())
+      .collect();
+    format!("{{{}}}", numbers.join(", "))
+  }
+}
+
+impl MinetestGLTF {
+  ///
+  /// Build a minetest `collisionbox` or `selectionbox` out of the model.
+  ///
+  /// Animated boxes skin one keyframe at a time. Trimmed boxes go over the vertices a few more
+  /// times to find the trimmed values, instead of holding every vertex of every keyframe.
+  ///
+  /// None if there is no model or it has no vertices.
+  ///
+  pub fn entity_box(&self, options: &EntityBoxOptions) -> Option<EntityBox> {
+    let model = self.model.as_ref()?;
+
+    let animated = options.pose == BoxPose::Animated && self.animated_frames().is_some();
+    let scale = options.visual_size / BS;
+    let place = |position: Vec3| match &options.conversion {
+      Some(conversion) => conversion.position(position) * scale,
+      None => position * scale,
+    };
+    let visit = |visitor: &mut dyn FnMut(Vec3)| match self.animated_frames().filter(|_| animated) {
+      Some(frames) => {
+        for positions in frames {
+          for position in positions {
+            visitor(place(position));
+          }
+        }
+      }
+      None => {
+        for primitive in &model.primitives {
+          for vertex in &primitive.vertices {
+            visitor(place(vertex.position));
+          }
+        }
+      }
+    };
+
+    let mut count: usize = 0;
+    let mut min = Vec3::INFINITY;
+    let mut max = Vec3::NEG_INFINITY;
+    visit(&mut |position| {
+      count += 1;
+      min = min.min(position);
+      max = max.max(position);
+    });
+    if count == 0 {
+      return None;
+    }
+
+    let percentile = options.percentile.unwrap_or(1.0).clamp(0.0, 1.0);
+    let last = count - 1;
+    let trim = (((1.0 - percentile) * 0.5 * last as f32).round() as usize).min(last / 2);
+    if trim == 0 {
+      return Some(EntityBox { min, max });
+    }
+
+    // Radix select the trimmed values of each axis, 8 bits a pass.
+    let mut selections = [[RankSelect::new(trim), RankSelect::new(last - trim)]; 3];
+    for shift in [24, 16, 8, 0] {
+      let mut histograms = [[[0_usize; 256]; 2]; 3];
+      visit(&mut |position| {
+        for axis in 0..3 {
+          let key = sort_key(position[axis]);
+          for (selection, histogram) in selections[axis].iter().zip(&mut histograms[axis]) {
+            if selection.matches(key) {
+              histogram[((key >> shift) & 0xFF) as usize] += 1;
+            }
+          }
+        }
+      });
+      for (axis_selections, axis_histograms) in selections.iter_mut().zip(&histograms) {
+        for (selection, histogram) in axis_selections.iter_mut().zip(axis_histograms) {
+          selection.narrow(histogram, shift);
+        }
+      }
+    }
+    for (axis, [low, high]) in selections.iter().enumerate() {
+      min[axis] = low.value();
+      max[axis] = high.value();
+    }
+
+    Some(EntityBox { min, max })
+  }
+}
+
+///
+/// Finds the value with some rank (place in sorted order) out of values that get visited over and
+/// over, one byte of the value at a time.
+///
+#[derive(Clone, Copy)]
+struct RankSelect {
+  /// The bytes of the sort key that are found so far.
+  prefix: u32,
+  /// Which bits of the sort key are found so far.
+  mask: u32,
+  /// The rank of the value among the values that match the prefix.
+  rank: usize,
+}
+
+impl RankSelect {
+  fn new(rank: usize) -> Self {
+    RankSelect {
+      prefix: 0,
+      mask: 0,
+      rank,
+    }
+  }
+
+  ///
+  /// Get if a sort key can still be the value.
+  ///
+  fn matches(&self, key: u32) -> bool {
+    key & self.mask == self.prefix
+  }
+
+  ///
+  /// Find the next byte, out of how many matching values had each byte.
+  ///
+  fn narrow(&mut self, histogram: &[usize; 256], shift: u32) {
+    for (byte, count) in histogram.iter().enumerate() {
+      if self.rank < *count {
+        self.prefix |= (byte as u32) << shift;
+        self.mask |= 0xFF << shift;
+        return;
+      }
+      self.rank -= count;
+    }
+  }
+
+  ///
+  /// The value, once every byte is found.
+  ///
+  fn value(&self) -> f32 {
+    from_sort_key(self.prefix)
+  }
+}
+
+///
+/// Turn a float into a key that sorts the same way as `f32::total_cmp`.
+///
+fn sort_key(value: f32) -> u32 {
+  let bits = value.to_bits();
+  if bits & 0x8000_0000 != 0 {
+    !bits
+  } else {
+    bits | 0x8000_0000
+  }
+}
+
+///
+/// Turn a key from `sort_key` back into the float.
+///
+fn from_sort_key(key: u32) -> f32 {
+  f32::from_bits(if key & 0x8000_0000 != 0 {
+    key & 0x7FFF_FFFF
+  } else {
+    !key
+  })
+}
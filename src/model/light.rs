@@ -0,0 +1,141 @@
+use glam::{Mat4, Vec3};
+use gltf::khr_lights_punctual::{Kind, Light as GltfLight};
+
+/// The brightest light level a minetest node can give off.
+pub const MINETEST_LIGHT_MAX: u8 = 14;
+
+/// The illuminance in lux (lm/m2) where a point or spot light is taken to stop lighting anything.
+pub const MIN_ILLUMINANCE: f32 = 1.0;
+
+/// What kind of light it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+  /// Directional lights are light sources that act as though they are
+  /// infinitely far away and emit light in the `direction`. Because it is at
+  /// an infinite distance, the light is not attenuated. Its intensity is
+  /// defined in lumens per metre squared, or lux (lm/m2).
+  Directional,
+
+  /// Point lights emit light in all directions from their `position` in space;
+  /// The brightness of the light attenuates in a physically correct manner as
+  /// distance increases from the light's position (i.e.  brightness goes like
+  /// the inverse square of the distance). Point light intensity is defined in
+  /// candela, which is lumens per square radian (lm/sr).
+  Point,
+
+  /// Spot lights emit light in a cone in `direction`. The angle and falloff
+  /// of the cone is defined using two numbers, the `inner_cone_angle` and
+  /// `outer_cone_angle`. As with point lights, the brightness also attenuates
+  /// in a physically correct manner as distance increases from the light's
+  /// position (i.e. brightness goes like the inverse square of the distance).
+  /// Spot light intensity refers to the brightness inside the
+  /// `inner_cone_angle` (and at the location of the light) and is defined in
+  /// candela, which is lumens per square radian (lm/sr). Engines that don't
+  /// support two angles for spotlights should use outer_cone_angle as the
+  /// spotlight angle (leaving `inner_cone_angle` to implicitly be `0`).
+  Spot {
+    /// Inner cone angle of the spot light, in radians.
+    inner_cone_angle: f32,
+    /// Outer cone angle of the spot light, in radians.
+    outer_cone_angle: f32,
+  },
+}
+
+/// Represents a `KHR_lights_punctual` light attached to a node.
+#[derive(Clone, Debug)]
+pub struct Light {
+  #[cfg(feature = "names")]
+  /// Light name. Requires the `names` feature.
+  pub name: Option<String>,
+  #[cfg(feature = "extras")]
+  /// Light extra data. Requires the `extras` feature
+  pub extras: gltf::json::extras::Extras,
+  /// The node (bone) id the light is attached to.
+  pub node: i32,
+  /// The world transform of the node.
+  pub transform: Mat4,
+  /// Position of the light. Doesn't matter for directional lights.
+  pub position: Vec3,
+  /// Direction the light shines in, normalized. Doesn't matter for point lights.
+  pub direction: Vec3,
+  /// Linear RGB color of the light.
+  pub color: Vec3,
+  /// Intensity of the light. See `LightKind` for the units.
+  pub intensity: f32,
+  /// The distance where the light stops. None for infinite. Directional lights never have one.
+  pub range: Option<f32>,
+  /// What kind of light it is, with the cone angles of spot lights.
+  pub kind: LightKind,
+}
+
+impl Light {
+  ///
+  /// Load up a light.
+  ///
+  pub(crate) fn load(gltf_light: GltfLight, node: i32, transform: &Mat4) -> Self {
+    Light {
+      #[cfg(feature = "names")]
+      name: gltf_light.name().map(String::from),
+      #[cfg(feature = "extras")]
+      extras: gltf_light.extras().clone(),
+      node,
+      transform: *transform,
+      position: transform.transform_point3(Vec3::ZERO),
+      // ? Lights shine down their local -Z.
+      direction: transform.transform_vector3(Vec3::NEG_Z).normalize_or_zero(),
+      color: Vec3::from(gltf_light.color()),
+      intensity: gltf_light.intensity(),
+      range: gltf_light.range(),
+      kind: match gltf_light.kind() {
+        Kind::Directional => LightKind::Directional,
+        Kind::Point => LightKind::Point,
+        Kind::Spot {
+          inner_cone_angle,
+          outer_cone_angle,
+        } => LightKind::Spot {
+          inner_cone_angle,
+          outer_cone_angle,
+        },
+      },
+    }
+  }
+
+  ///
+  /// How far the light reaches, in model units.
+  ///
+  /// Point and spot lights reach until their illuminance drops under `MIN_ILLUMINANCE`, or to
+  /// their range if that's closer. The brightest color channel counts. Directional lights reach
+  /// everywhere.
+  ///
+  pub fn reach(&self) -> f32 {
+    let intensity = self.intensity * self.color.max_element();
+    if intensity <= 0.0 {
+      return 0.0;
+    }
+    match self.kind {
+      LightKind::Directional => f32::INFINITY,
+      LightKind::Point | LightKind::Spot { .. } => {
+        let reach = (intensity / MIN_ILLUMINANCE).sqrt();
+        match self.range {
+          Some(range) => reach.min(range),
+          None => reach,
+        }
+      }
+    }
+  }
+
+  ///
+  /// The minetest light level (0 - 14) of a node light source that reaches about as far.
+  ///
+  /// Minetest light drops one level per node, so the level is the reach in nodes.
+  /// `node_size` is the size of a node in model units: 1.0 for glTF meters, or `BS` after
+  /// a `CoordinateConversion::minetest()`.
+  ///
+  pub fn minetest_light_level(&self, node_size: f32) -> u8 {
+    let nodes = self.reach() / node_size;
+    if nodes.is_nan() || nodes <= 0.0 {
+      return 0;
+    }
+    nodes.ceil().min(MINETEST_LIGHT_MAX as f32) as u8
+  }
+}
@@ -1,13 +1,33 @@
+/// Interleaved vertex buffers with a configurable layout.
+mod layout;
 /// Primitive type module. Tells how to draw. Points, lines, lineloop, linestrip, triangles, trianglestrip, and trianglefan.
 mod mode;
+/// Vertex welding, index generation and vertex cache reordering.
+mod optimize;
+/// Compact GPU formats for the vertex attributes.
+mod quantize;
+/// Quadric error metric simplification, for levels of detail.
+mod simplify;
+/// Splitting big primitives up so they fit 16 bit indices.
+mod split;
+/// KHR_texture_transform UV transforms, and baking them.
+mod texture_transform;
 /// Raw gpu vertex definition module.
 mod vertex;
 
 use glam::{Mat4, Vec2, Vec3, Vec4};
+pub use layout::*;
 pub use mode::*;
+pub use optimize::*;
+pub use quantize::*;
+pub use split::*;
+pub use texture_transform::*;
 pub use vertex::*;
 
-use crate::minetest_gltf::MinetestGLTF;
+use crate::{
+  bounds::{BoundingBox, BoundingSphere, Bounds},
+  minetest_gltf::MinetestGLTF,
+};
 
 /// Geometry to be rendered with the given material.
 ///
@@ -74,12 +94,31 @@ pub struct Primitive {
   pub indices: Option<Vec<u32>>,
   pub weights: Vec<[f32; 4]>,
   pub joints: Vec<[u16; 4]>,
+  /// Vertex colors (`COLOR_0`) as linear RGBA. Empty if there aren't any.
+  pub colors: Vec<[f32; 4]>,
+  /// The texture coordinate sets after the first one (`TEXCOORD_1` and up), with a value
+  /// per vertex each. The first set is in the vertices.
+  pub extra_tex_coords: Vec<Vec<Vec2>>,
   pub mode: Mode,
   pub has_normals: bool,
   pub has_tangents: bool,
   pub has_tex_coords: bool,
   pub has_weights: bool,
   pub has_joints: bool,
+  pub has_colors: bool,
+  /// The material this primitive uses. This is the glTF material index, the B3D brush id
+  /// or the order of the OBJ `usemtl` name.
+  pub material: Option<usize>,
+  /// Which texture of a minetest `textures = {...}` table this primitive gets. 0 based.
+  ///
+  /// Slots are handed out per material, in the order minetest creates the mesh buffers. They
+  /// aren't stored in the file, the glTF writer nests the meshes so they come out the same when
+  /// the file is loaded again. Orders that can't be nested that way are written sorted by slot.
+  pub texture_slot: usize,
+  /// The `KHR_texture_transform`s of the texture references of the material.
+  pub texture_transforms: TextureTransforms,
+
+  pub(crate) bounds: Bounds,
 }
 
 impl Primitive {
@@ -179,6 +218,240 @@ impl Primitive {
     Ok(triangles)
   }
 
+  /// Indices of each triangle, with strips and fans unrolled.
+  ///
+  /// **Note**: This function will return an error if the mode isn't `Triangles`, `TriangleFan`
+  /// or `TriangleStrip`.
+  pub(crate) fn triangle_indices(&self) -> Result<Vec<[u32; 3]>, BadMode> {
+    let indices = match self.indices() {
+      Some(indices) => indices.clone(),
+      None => (0..self.vertices.len() as u32).collect(),
+    };
+
+    let mut triangles = vec![];
+    match self.mode {
+      Mode::Triangles => {
+        for triangle in indices.chunks_exact(3) {
+          triangles.push([triangle[0], triangle[1], triangle[2]]);
+        }
+      }
+      Mode::TriangleStrip => {
+        for i in 0..indices.len().saturating_sub(2) {
+          // Every other triangle is flipped to keep the winding the same.
+          if i % 2 == 0 {
+            triangles.push([indices[i], indices[i + 1], indices[i + 2]]);
+          } else {
+            triangles.push([indices[i + 1], indices[i], indices[i + 2]]);
+          }
+        }
+      }
+      Mode::TriangleFan => {
+        for i in 1..indices.len().saturating_sub(1) {
+          triangles.push([indices[0], indices[i], indices[i + 1]]);
+        }
+      }
+      _ => return Err(BadMode { mode: self.mode() }),
+    }
+    Ok(triangles)
+  }
+
+  /// Indices of each line, with strips and loops unrolled.
+  ///
+  /// **Note**: This function will return an error if the mode isn't `Lines`, `LineLoop`
+  /// or `LineStrip`.
+  pub(crate) fn line_indices(&self) -> Result<Vec<[u32; 2]>, BadMode> {
+    let indices = match self.indices() {
+      Some(indices) => indices.clone(),
+      None => (0..self.vertices.len() as u32).collect(),
+    };
+
+    let mut lines = vec![];
+    match self.mode {
+      Mode::Lines => {
+        for line in indices.chunks_exact(2) {
+          lines.push([line[0], line[1]]);
+        }
+      }
+      Mode::LineStrip | Mode::LineLoop => {
+        for line in indices.windows(2) {
+          lines.push([line[0], line[1]]);
+        }
+        if self.mode == Mode::LineLoop && indices.len() > 2 {
+          lines.push([indices[indices.len() - 1], indices[0]]);
+        }
+      }
+      _ => return Err(BadMode { mode: self.mode() }),
+    }
+    Ok(lines)
+  }
+
+  /// A copy of this primitive as an indexed triangle list, with strips and fans unrolled.
+  ///
+  /// The vertices, joints and weights are kept as they are, and strips keep their winding.
+  /// Degenerate triangles that only join strips together are dropped.
+  ///
+  /// **Note**: This function will return an error if the mode isn't `Triangles`, `TriangleFan`
+  /// or `TriangleStrip`.
+  pub fn to_triangle_list(&self) -> Result<Primitive, BadMode> {
+    let strip = self.mode == Mode::TriangleStrip;
+    let indices = self
+      .triangle_indices()?
+      .into_iter()
+      .filter(|[a, b, c]| !strip || (a != b && b != c && a != c))
+      .flatten()
+      .collect();
+
+    let mut list = self.clone();
+    list.mode = Mode::Triangles;
+    list.indices = Some(indices);
+    Ok(list)
+  }
+
+  /// A copy of this primitive as an indexed line list, with strips and loops unrolled.
+  ///
+  /// The vertices, joints and weights are kept as they are.
+  ///
+  /// **Note**: This function will return an error if the mode isn't `Lines`, `LineLoop`
+  /// or `LineStrip`.
+  pub fn to_line_list(&self) -> Result<Primitive, BadMode> {
+    let indices = self.line_indices()?.into_iter().flatten().collect();
+
+    let mut list = self.clone();
+    list.mode = Mode::Lines;
+    list.indices = Some(indices);
+    Ok(list)
+  }
+
+  /// Merge primitives into one triangle, line or point list. The first primitive picks which.
+  ///
+  /// The name, extras, index, material and texture slot of the first primitive are kept. If the
+  /// base color texture transforms differ, they get baked first, see `bake_texture_transform`.
+  /// Other texture transforms are kept where all the primitives agree on them.
+  ///
+  /// **Note**: This function will return an error if the primitives mix triangles, lines
+  /// and points.
+  pub(crate) fn merge(primitives: &[&Primitive]) -> Result<Primitive, BadMode> {
+    let first = match primitives.first() {
+      Some(first) => *first,
+      None => return Ok(Primitive::default()),
+    };
+    let mode = match first.mode {
+      Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan => Mode::Triangles,
+      Mode::Lines | Mode::LineStrip | Mode::LineLoop => Mode::Lines,
+      Mode::Points => Mode::Points,
+    };
+
+    let baked: Vec<Primitive>;
+    let primitives: Vec<&Primitive> = if primitives.iter().all(|primitive| {
+      primitive.texture_transforms.base_color == first.texture_transforms.base_color
+    }) {
+      primitives.to_vec()
+    } else {
+      baked = primitives
+        .iter()
+        .map(|primitive| {
+          let mut primitive = (*primitive).clone();
+          primitive.bake_texture_transform();
+          primitive
+        })
+        .collect();
+      baked.iter().collect()
+    };
+    let first = primitives[0];
+
+    let mut merged = Primitive {
+      #[cfg(feature = "names")]
+      mesh_name: first.mesh_name.clone(),
+      #[cfg(feature = "extras")]
+      mesh_extras: first.mesh_extras.clone(),
+      #[cfg(feature = "extras")]
+      primitive_extras: first.primitive_extras.clone(),
+      primitive_index: first.primitive_index,
+      mode: mode.clone(),
+      has_normals: primitives.iter().any(|primitive| primitive.has_normals),
+      has_tangents: primitives.iter().any(|primitive| primitive.has_tangents),
+      has_tex_coords: primitives.iter().any(|primitive| primitive.has_tex_coords),
+      has_weights: primitives.iter().any(|primitive| primitive.has_weights),
+      has_joints: primitives.iter().any(|primitive| primitive.has_joints),
+      has_colors: primitives.iter().any(|primitive| primitive.has_colors),
+      material: first.material,
+      texture_slot: first.texture_slot,
+      texture_transforms: TextureTransforms::common(
+        primitives
+          .iter()
+          .map(|primitive| &primitive.texture_transforms),
+      ),
+      ..Default::default()
+    };
+
+    let extra_sets = primitives
+      .iter()
+      .map(|primitive| primitive.extra_tex_coords.len())
+      .max()
+      .unwrap_or(0);
+    merged.extra_tex_coords = vec![vec![]; extra_sets];
+
+    let mut merged_indices = vec![];
+    for primitive in primitives {
+      let indices: Vec<u32> = match mode {
+        Mode::Triangles => primitive
+          .triangle_indices()?
+          .into_iter()
+          .flatten()
+          .collect(),
+        Mode::Lines => primitive.line_indices()?.into_iter().flatten().collect(),
+        _ => {
+          primitive.points()?;
+          match primitive.indices() {
+            Some(indices) => indices.clone(),
+            None => (0..primitive.vertices.len() as u32).collect(),
+          }
+        }
+      };
+
+      let offset = merged.vertices.len() as u32;
+      merged_indices.extend(indices.into_iter().map(|index| index + offset));
+      merged.vertices.extend_from_slice(&primitive.vertices);
+
+      // Primitives without skinning data get zeroed out joints and weights.
+      let vertex_count = primitive.vertices.len();
+      if merged.has_joints {
+        if primitive.joints.len() == vertex_count {
+          merged.joints.extend_from_slice(&primitive.joints);
+        } else {
+          merged.joints.extend(vec![[0; 4]; vertex_count]);
+        }
+      }
+      if merged.has_weights {
+        if primitive.weights.len() == vertex_count {
+          merged.weights.extend_from_slice(&primitive.weights);
+        } else {
+          merged.weights.extend(vec![[0.0; 4]; vertex_count]);
+        }
+      }
+      // Primitives without colors are white, and missing texture coordinates are zero.
+      if merged.has_colors {
+        if primitive.colors.len() == vertex_count {
+          merged.colors.extend_from_slice(&primitive.colors);
+        } else {
+          merged.colors.extend(vec![[1.0; 4]; vertex_count]);
+        }
+      }
+      for (set, merged_set) in merged.extra_tex_coords.iter_mut().enumerate() {
+        match primitive.extra_tex_coords.get(set) {
+          Some(tex_coords) if tex_coords.len() == vertex_count => {
+            merged_set.extend_from_slice(tex_coords)
+          }
+          _ => merged_set.extend(vec![Vec2::ZERO; vertex_count]),
+        }
+      }
+    }
+    merged.indices = Some(merged_indices);
+    merged.update_bounds();
+
+    Ok(merged)
+  }
+
   /// List of lines ready to be rendered.
   ///
   /// **Note**: This function will return an error if the mode isn't `Lines`, `LineLoop`
@@ -250,6 +523,76 @@ impl Primitive {
     self.has_tex_coords
   }
 
+  /// Indicate if the vertices have colors.
+  ///
+  /// **Note**: If this function return `false` the colors list is empty.
+  pub fn has_colors(&self) -> bool {
+    self.has_colors
+  }
+
+  /// A primitive with everything but the vertex data and indices of this one.
+  pub(crate) fn without_data(&self, mode: Mode) -> Primitive {
+    Primitive {
+      #[cfg(feature = "names")]
+      mesh_name: self.mesh_name.clone(),
+      #[cfg(feature = "extras")]
+      mesh_extras: self.mesh_extras.clone(),
+      #[cfg(feature = "extras")]
+      primitive_extras: self.primitive_extras.clone(),
+      primitive_index: self.primitive_index,
+      mode,
+      has_normals: self.has_normals,
+      has_tangents: self.has_tangents,
+      has_tex_coords: self.has_tex_coords,
+      has_weights: self.has_weights,
+      has_joints: self.has_joints,
+      has_colors: self.has_colors,
+      material: self.material,
+      texture_slot: self.texture_slot,
+      texture_transforms: self.texture_transforms,
+      ..Default::default()
+    }
+  }
+
+  /// Rebuild every per vertex list so new vertex `i` is old vertex `order[i]`.
+  ///
+  /// Lists that don't have a value for every vertex are left as they are.
+  pub(crate) fn reorder_vertices(&mut self, order: &[usize]) {
+    let source = Primitive {
+      vertices: std::mem::take(&mut self.vertices),
+      joints: std::mem::take(&mut self.joints),
+      weights: std::mem::take(&mut self.weights),
+      colors: std::mem::take(&mut self.colors),
+      extra_tex_coords: std::mem::take(&mut self.extra_tex_coords),
+      ..Default::default()
+    };
+    self.gather_vertices(&source, order);
+  }
+
+  /// Fill every per vertex list with the vertices of `source` at `order`.
+  ///
+  /// Lists of `source` that don't have a value for every vertex are copied over as they are.
+  pub(crate) fn gather_vertices(&mut self, source: &Primitive, order: &[usize]) {
+    fn gather<T: Clone>(list: &[T], count: usize, order: &[usize]) -> Vec<T> {
+      if list.len() == count {
+        order.iter().map(|i| list[*i].clone()).collect()
+      } else {
+        list.to_vec()
+      }
+    }
+
+    let count = source.vertices.len();
+    self.vertices = gather(&source.vertices, count, order);
+    self.joints = gather(&source.joints, count, order);
+    self.weights = gather(&source.weights, count, order);
+    self.colors = gather(&source.colors, count, order);
+    self.extra_tex_coords = source
+      .extra_tex_coords
+      .iter()
+      .map(|set| gather(set, count, order))
+      .collect();
+  }
+
   fn apply_transform_position(pos: [f32; 3], transform: &Mat4) -> Vec3 {
     let pos = Vec4::new(pos[0], pos[1], pos[2], 1.);
     let res = *transform * pos;
@@ -286,40 +629,45 @@ impl Primitive {
       .read_indices()
       .map(|indices| indices.into_u32().collect());
 
+    // ? The attributes are read by hand, since KHR_mesh_quantization allows integer components.
     // Init vertices with the position
-    let mut vertices: Vec<_> = reader
-      .read_positions()
+    let mut vertices: Vec<_> = Self::read_floats(&primitive, buffers, gltf::Semantic::Positions)
       .unwrap_or_else(|| panic!("The model primitive doesn't contain positions"))
-      .map(|pos| Vertex {
+      .into_iter()
+      .map(|pos: [f32; 3]| Vertex {
         position: Self::apply_transform_position(pos, transform),
         ..Default::default()
       })
       .collect();
 
     // Fill normals
-    let has_normals = if let Some(normals) = reader.read_normals() {
-      for (i, normal) in normals.enumerate() {
-        vertices[i].normal = Self::apply_transform_vector(normal, transform).normalize();
-      }
-      true
-    } else {
-      false
-    };
+    let has_normals =
+      if let Some(normals) = Self::read_floats(&primitive, buffers, gltf::Semantic::Normals) {
+        for (i, normal) in normals.into_iter().enumerate() {
+          vertices[i].normal = Self::apply_transform_vector(normal, transform).normalize();
+        }
+        true
+      } else {
+        false
+      };
 
     // Fill tangents.
-    let has_tangents = if let Some(tangents) = reader.read_tangents() {
-      for (i, tangent) in tangents.enumerate() {
-        let tangent = Self::apply_transform_tangent(tangent, transform);
-        vertices[i].tangent = tangent.truncate().normalize().extend(tangent.w);
-      }
-      true
-    } else {
-      false
-    };
+    let has_tangents =
+      if let Some(tangents) = Self::read_floats(&primitive, buffers, gltf::Semantic::Tangents) {
+        for (i, tangent) in tangents.into_iter().enumerate() {
+          let tangent = Self::apply_transform_tangent(tangent, transform);
+          vertices[i].tangent = tangent.truncate().normalize().extend(tangent.w);
+        }
+        true
+      } else {
+        false
+      };
 
     // Texture coordinates.
-    let has_tex_coords = if let Some(tex_coords) = reader.read_tex_coords(0) {
-      for (i, tex_coords) in tex_coords.into_f32().enumerate() {
+    let has_tex_coords = if let Some(tex_coords) =
+      Self::read_floats(&primitive, buffers, gltf::Semantic::TexCoords(0))
+    {
+      for (i, tex_coords) in tex_coords.into_iter().enumerate() {
         vertices[i].tex_coords = Vec2::from(tex_coords);
       }
       true
@@ -327,6 +675,23 @@ impl Primitive {
       false
     };
 
+    // The other texture coordinate sets.
+    let mut extra_tex_coords = vec![];
+    while let Some(tex_coords) = Self::read_floats(
+      &primitive,
+      buffers,
+      gltf::Semantic::TexCoords(extra_tex_coords.len() as u32 + 1),
+    ) {
+      extra_tex_coords.push(tex_coords.into_iter().map(Vec2::from).collect());
+    }
+
+    // Vertex colors.
+    let colors: Vec<[f32; 4]> = match reader.read_colors(0) {
+      Some(colors) => colors.into_rgba_f32().collect(),
+      None => vec![],
+    };
+    let has_colors = !colors.is_empty();
+
     let debugging_enabled = false;
 
     // Weights.
@@ -356,6 +721,21 @@ impl Primitive {
       false
     };
 
+    // * The POSITION accessor min and max are required by the spec, so use them when they're there.
+    let bounding_box = match Self::accessor_bounding_box(&primitive) {
+      Some(bounding_box) => bounding_box.transformed(transform),
+      None => {
+        BoundingBox::from_points(vertices.iter().map(|vertex| vertex.position)).unwrap_or_default()
+      }
+    };
+    let bounds = Bounds {
+      bounding_box,
+      bounding_sphere: BoundingSphere::from_points(
+        &bounding_box,
+        vertices.iter().map(|vertex| vertex.position),
+      ),
+    };
+
     Primitive {
       #[cfg(feature = "names")]
       mesh_name: mesh.name().map(String::from),
@@ -369,11 +749,108 @@ impl Primitive {
       mode: primitive.mode().into(),
       weights,
       joints,
+      colors,
+      extra_tex_coords,
       has_normals,
       has_tangents,
       has_tex_coords,
       has_weights,
       has_joints,
+      has_colors,
+      material: primitive.material().index(),
+      // Handed out once the whole scene is loaded.
+      texture_slot: 0,
+      texture_transforms: TextureTransforms::load(&primitive.material()),
+      bounds,
+    }
+  }
+
+  ///
+  /// Read a float attribute with any component type.
+  ///
+  /// Integers are dequantized like KHR_mesh_quantization says. Normalized ones map onto
+  /// -1.0 - 1.0 (or 0.0 - 1.0 when unsigned), the rest are taken as they are.
+  ///
+  fn read_floats<const N: usize>(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    semantic: gltf::Semantic,
+  ) -> Option<Vec<[f32; N]>>
+  where
+    [f32; N]: gltf::accessor::Item,
+    [i8; N]: gltf::accessor::Item,
+    [u8; N]: gltf::accessor::Item,
+    [i16; N]: gltf::accessor::Item,
+    [u16; N]: gltf::accessor::Item,
+  {
+    use gltf::accessor::{DataType, Item, Iter};
+
+    let accessor = primitive.get(&semantic)?;
+    if accessor.dimensions().multiplicity() != N {
+      return None;
+    }
+
+    fn read<'a, T: Item + Copy, const N: usize>(
+      accessor: gltf::Accessor<'a>,
+      buffers: &'a [gltf::buffer::Data],
+      convert: impl Fn(T) -> f32,
+    ) -> Option<Vec<[f32; N]>>
+    where
+      [T; N]: Item,
+    {
+      let iter = Iter::<[T; N]>::new(accessor, |buffer| {
+        buffers.get(buffer.index()).map(|data| &data[..])
+      })?;
+      Some(iter.map(|value| value.map(&convert)).collect())
+    }
+
+    let normalized = accessor.normalized();
+    match accessor.data_type() {
+      DataType::F32 => read(accessor, buffers, |value: f32| value),
+      DataType::I8 if normalized => read(accessor, buffers, |value: i8| {
+        (value as f32 / i8::MAX as f32).max(-1.0)
+      }),
+      DataType::U8 if normalized => {
+        read(accessor, buffers, |value: u8| value as f32 / u8::MAX as f32)
+      }
+      DataType::I16 if normalized => read(accessor, buffers, |value: i16| {
+        (value as f32 / i16::MAX as f32).max(-1.0)
+      }),
+      DataType::U16 if normalized => read(accessor, buffers, |value: u16| {
+        value as f32 / u16::MAX as f32
+      }),
+      DataType::I8 => read(accessor, buffers, |value: i8| value as f32),
+      DataType::U8 => read(accessor, buffers, |value: u8| value as f32),
+      DataType::I16 => read(accessor, buffers, |value: i16| value as f32),
+      DataType::U16 => read(accessor, buffers, |value: u16| value as f32),
+      // Not allowed for vertex attributes.
+      DataType::U32 => None,
     }
   }
+
+  ///
+  /// Get the bounding box out of the min and max of the POSITION accessor, in mesh space.
+  ///
+  /// Normalized positions would need to be scaled, so those come from the vertices instead.
+  ///
+  fn accessor_bounding_box(primitive: &gltf::Primitive) -> Option<BoundingBox> {
+    let accessor = primitive.get(&gltf::Semantic::Positions)?;
+    if accessor.normalized() {
+      return None;
+    }
+
+    let to_vec3 = |value: gltf::json::Value| -> Option<Vec3> {
+      let array = value.as_array()?;
+      Some(Vec3::new(
+        array.first()?.as_f64()? as f32,
+        array.get(1)?.as_f64()? as f32,
+        array.get(2)?.as_f64()? as f32,
+      ))
+    };
+
+    Some(BoundingBox::new(
+      to_vec3(accessor.min()?)?,
+      to_vec3(accessor.max()?)?,
+    ))
+  }
 }
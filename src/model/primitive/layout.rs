@@ -0,0 +1,314 @@
+use std::fmt;
+
+use super::Primitive;
+
+/// A vertex attribute that can go into a vertex buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Attribute {
+  /// 3 components.
+  Position,
+  /// 3 components.
+  Normal,
+  /// 4 components. W is the handedness of the bitangent.
+  Tangent,
+  /// 2 components. Set 0 is the one in the vertices, the rest come from `extra_tex_coords`.
+  TexCoords(usize),
+  /// 4 components, linear RGBA.
+  Color,
+  /// 4 components. Only integer formats.
+  Joints,
+  /// 4 components.
+  Weights,
+}
+
+impl Attribute {
+  ///
+  /// How many components the attribute has.
+  ///
+  pub fn components(&self) -> usize {
+    match self {
+      Attribute::Position | Attribute::Normal => 3,
+      Attribute::TexCoords(_) => 2,
+      Attribute::Tangent | Attribute::Color | Attribute::Joints | Attribute::Weights => 4,
+    }
+  }
+}
+
+/// How an attribute is stored in the vertex buffer. Everything is little endian.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+  /// 2 `f32`s.
+  Float32x2,
+  /// 3 `f32`s.
+  Float32x3,
+  /// 4 `f32`s.
+  Float32x4,
+  /// 4 `u8`s. Joints only.
+  Uint8x4,
+  /// 4 `u16`s. Joints only.
+  Uint16x4,
+  /// 4 `u8`s, 0.0 - 1.0 mapped to 0 - 255.
+  Unorm8x4,
+  /// 2 `u16`s, 0.0 - 1.0 mapped to 0 - 65535.
+  Unorm16x2,
+  /// 4 `u16`s, 0.0 - 1.0 mapped to 0 - 65535.
+  Unorm16x4,
+}
+
+impl Format {
+  ///
+  /// How many components the format has.
+  ///
+  pub fn components(&self) -> usize {
+    match self {
+      Format::Float32x2 | Format::Unorm16x2 => 2,
+      Format::Float32x3 => 3,
+      Format::Float32x4
+      | Format::Uint8x4
+      | Format::Uint16x4
+      | Format::Unorm8x4
+      | Format::Unorm16x4 => 4,
+    }
+  }
+
+  ///
+  /// How many bytes one value takes up.
+  ///
+  pub fn size(&self) -> usize {
+    match self {
+      Format::Float32x2 => 8,
+      Format::Float32x3 => 12,
+      Format::Float32x4 => 16,
+      Format::Uint8x4 | Format::Unorm8x4 | Format::Unorm16x2 => 4,
+      Format::Uint16x4 | Format::Unorm16x4 => 8,
+    }
+  }
+
+  fn is_integer(&self) -> bool {
+    matches!(self, Format::Uint8x4 | Format::Uint16x4)
+  }
+
+  ///
+  /// Write one value. `value` has at least as many components as the format.
+  ///
+  fn write(&self, value: &[f32], output: &mut Vec<u8>) {
+    let unorm = |value: f32, max: f32| (value.clamp(0.0, 1.0) * max).round();
+    for component in &value[..self.components()] {
+      match self {
+        Format::Float32x2 | Format::Float32x3 | Format::Float32x4 => {
+          output.extend(component.to_le_bytes())
+        }
+        Format::Uint8x4 => output.push(*component as u8),
+        Format::Uint16x4 => output.extend((*component as u16).to_le_bytes()),
+        Format::Unorm8x4 => output.push(unorm(*component, 255.0) as u8),
+        Format::Unorm16x2 | Format::Unorm16x4 => {
+          output.extend((unorm(*component, 65535.0) as u16).to_le_bytes())
+        }
+      }
+    }
+  }
+}
+
+/// Represents a runtime error. Triggered when a vertex layout can't hold a primitive.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LayoutError {
+  /// The format doesn't fit the attribute. Wrong component count, or integers for
+  /// something other than joints.
+  BadFormat {
+    /// The attribute.
+    attribute: Attribute,
+    /// The format it was given.
+    format: Format,
+  },
+  /// A joint index is too big for the format.
+  JointOutOfRange {
+    /// The joint index.
+    joint: u16,
+    /// The format it was given.
+    format: Format,
+  },
+}
+
+impl fmt::Display for LayoutError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LayoutError::BadFormat { attribute, format } => {
+        write!(f, "Format {:?} can't hold {:?}", format, attribute)
+      }
+      LayoutError::JointOutOfRange { joint, format } => {
+        write!(f, "Joint {} doesn't fit in {:?}", joint, format)
+      }
+    }
+  }
+}
+
+impl std::error::Error for LayoutError {}
+
+/// Where an attribute ended up in a vertex.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexElement {
+  /// The attribute.
+  pub attribute: Attribute,
+  /// How it's stored.
+  pub format: Format,
+  /// Bytes from the start of the vertex.
+  pub offset: usize,
+}
+
+///
+/// Describes an interleaved vertex buffer. Attributes are packed tightly, in the order they
+/// were added.
+///
+/// # Example
+///
+/// ```
+/// # use minetest_gltf::primitive::{Attribute, Format, VertexLayout};
+/// let layout = VertexLayout::new()
+///   .with(Attribute::Position, Format::Float32x3)
+///   .with(Attribute::TexCoords(0), Format::Float32x2)
+///   .with(Attribute::Joints, Format::Uint8x4)
+///   .with(Attribute::Weights, Format::Unorm8x4);
+/// assert_eq!(layout.stride(), 28);
+/// assert_eq!(layout.elements()[2].offset, 20);
+/// ```
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VertexLayout {
+  elements: Vec<VertexElement>,
+  stride: usize,
+}
+
+impl VertexLayout {
+  ///
+  /// An empty layout.
+  ///
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  ///
+  /// Add an attribute after the ones that are already in.
+  ///
+  pub fn with(mut self, attribute: Attribute, format: Format) -> Self {
+    self.elements.push(VertexElement {
+      attribute,
+      format,
+      offset: self.stride,
+    });
+    self.stride += format.size();
+    self
+  }
+
+  ///
+  /// Get every attribute with its format and offset.
+  ///
+  pub fn elements(&self) -> &[VertexElement] {
+    &self.elements
+  }
+
+  ///
+  /// Get the size of one vertex in bytes.
+  ///
+  pub fn stride(&self) -> usize {
+    self.stride
+  }
+
+  ///
+  /// Check that every format fits its attribute.
+  ///
+  pub fn validate(&self) -> Result<(), LayoutError> {
+    for element in &self.elements {
+      let joints = element.attribute == Attribute::Joints;
+      if element.attribute.components() != element.format.components()
+        || joints != element.format.is_integer()
+      {
+        return Err(LayoutError::BadFormat {
+          attribute: element.attribute,
+          format: element.format,
+        });
+      }
+    }
+    Ok(())
+  }
+
+  ///
+  /// Pack the vertices of a primitive into an interleaved buffer.
+  ///
+  /// Attributes the primitive doesn't have get filled in, so every primitive can share a
+  /// layout. Colors are white, and everything else is zero.
+  ///
+  pub fn build(&self, primitive: &Primitive) -> Result<VertexBuffer, LayoutError> {
+    self.validate()?;
+
+    let count = primitive.vertices.len();
+    let mut data = Vec::with_capacity(count * self.stride);
+    for (i, vertex) in primitive.vertices.iter().enumerate() {
+      for element in &self.elements {
+        let value: [f32; 4] = match element.attribute {
+          Attribute::Position => vertex.position.extend(0.0).to_array(),
+          Attribute::Normal => vertex.normal.extend(0.0).to_array(),
+          Attribute::Tangent => vertex.tangent.to_array(),
+          Attribute::TexCoords(0) => [vertex.tex_coords.x, vertex.tex_coords.y, 0.0, 0.0],
+          Attribute::TexCoords(set) => match primitive.extra_tex_coords.get(set - 1) {
+            Some(tex_coords) if tex_coords.len() == count => {
+              [tex_coords[i].x, tex_coords[i].y, 0.0, 0.0]
+            }
+            _ => [0.0; 4],
+          },
+          Attribute::Color => match primitive.colors.get(i) {
+            Some(color) if primitive.colors.len() == count => *color,
+            _ => [1.0; 4],
+          },
+          Attribute::Joints => match primitive.joints.get(i) {
+            Some(joints) if primitive.joints.len() == count => {
+              if element.format == Format::Uint8x4 {
+                if let Some(joint) = joints.iter().find(|joint| **joint > u8::MAX as u16) {
+                  return Err(LayoutError::JointOutOfRange {
+                    joint: *joint,
+                    format: element.format,
+                  });
+                }
+              }
+              joints.map(f32::from)
+            }
+            _ => [0.0; 4],
+          },
+          Attribute::Weights => match primitive.weights.get(i) {
+            Some(weights) if primitive.weights.len() == count => *weights,
+            _ => [0.0; 4],
+          },
+        };
+        element.format.write(&value, &mut data);
+      }
+    }
+
+    Ok(VertexBuffer {
+      data,
+      elements: self.elements.clone(),
+      stride: self.stride,
+      vertex_count: count,
+    })
+  }
+}
+
+/// An interleaved vertex buffer, ready to upload.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VertexBuffer {
+  /// The packed vertices.
+  pub data: Vec<u8>,
+  /// Every attribute with its format and offset.
+  pub elements: Vec<VertexElement>,
+  /// The size of one vertex in bytes.
+  pub stride: usize,
+  /// How many vertices there are.
+  pub vertex_count: usize,
+}
+
+impl Primitive {
+  ///
+  /// Pack the vertices into an interleaved buffer. See `VertexLayout::build`.
+  ///
+  pub fn vertex_buffer(&self, layout: &VertexLayout) -> Result<VertexBuffer, LayoutError> {
+    layout.build(self)
+  }
+}
@@ -50,3 +50,5 @@ impl fmt::Display for BadMode {
     write!(f, "Invalid mode \"{:?}\"", self.mode,)
   }
 }
+
+impl std::error::Error for BadMode {}
@@ -0,0 +1,304 @@
+use ahash::AHashMap;
+
+use super::{Mode, Primitive};
+
+/// How many vertices the post-transform cache is assumed to hold.
+const CACHE_SIZE: usize = 32;
+
+///
+/// Which steps of the optimization pipeline to run.
+///
+/// Everything is turned on by default.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OptimizeOptions {
+  /// Weld vertices that are exactly the same, joints and weights included.
+  pub weld: bool,
+  /// Reorder triangles so the post-transform vertex cache gets hit more often.
+  pub vertex_cache: bool,
+  /// Reorder vertices in the order they're first used, so fetching them is more linear.
+  pub vertex_fetch: bool,
+}
+
+impl Default for OptimizeOptions {
+  fn default() -> Self {
+    OptimizeOptions {
+      weld: true,
+      vertex_cache: true,
+      vertex_fetch: true,
+    }
+  }
+}
+
+impl Primitive {
+  ///
+  /// Optimize the primitive in place.
+  ///
+  /// Indices are always generated. Strips and fans are unrolled into triangle lists, since the
+  /// triangles can't be reordered otherwise. Line strips and loops are left alone.
+  ///
+  pub fn optimize(&mut self, options: &OptimizeOptions) {
+    match self.mode {
+      Mode::TriangleStrip | Mode::TriangleFan => {
+        if let Ok(list) = self.to_triangle_list() {
+          *self = list;
+        }
+      }
+      _ => {
+        if self.indices.is_none() {
+          self.indices = Some((0..self.vertices.len() as u32).collect());
+        }
+      }
+    }
+
+    if options.weld {
+      self.weld_vertices();
+    }
+    if options.vertex_cache && self.mode == Mode::Triangles {
+      self.optimize_vertex_cache();
+    }
+    // ? Strips and loops depend on the vertex order, so only lists get their vertices reordered.
+    if options.vertex_fetch && !matches!(self.mode, Mode::LineStrip | Mode::LineLoop) {
+      self.optimize_vertex_fetch();
+    }
+
+    self.update_bounds();
+  }
+
+  ///
+  /// Get the average cache miss ratio of the triangles. How many vertices get transformed per
+  /// triangle, with a FIFO cache of `cache_size` vertices.
+  ///
+  /// 3.0 is the worst, 0.5 is about the best a regular grid can do.
+  ///
+  pub fn average_cache_miss_ratio(&self, cache_size: usize) -> f32 {
+    let triangles = match self.triangle_indices() {
+      Ok(triangles) if !triangles.is_empty() => triangles,
+      _ => return 0.0,
+    };
+
+    let mut cache: std::collections::VecDeque<u32> = std::collections::VecDeque::new();
+    let mut misses = 0;
+    for index in triangles.iter().flatten() {
+      if !cache.contains(index) {
+        misses += 1;
+        cache.push_back(*index);
+        if cache.len() > cache_size {
+          cache.pop_front();
+        }
+      }
+    }
+    misses as f32 / triangles.len() as f32
+  }
+
+  ///
+  /// Merge vertices that are exactly the same.
+  ///
+  fn weld_vertices(&mut self) {
+    let count = self.vertices.len();
+    let skinned = self.joints.len() == count;
+    let weighted = self.weights.len() == count;
+    let colored = self.colors.len() == count;
+
+    let mut lookup: AHashMap<Vec<u32>, u32> = AHashMap::new();
+    let mut remap = Vec::with_capacity(count);
+    let mut order = vec![];
+
+    for (i, vertex) in self.vertices.iter().enumerate() {
+      let mut key: Vec<u32> = [
+        vertex.position.to_array().as_slice(),
+        vertex.normal.to_array().as_slice(),
+        vertex.tangent.to_array().as_slice(),
+        vertex.tex_coords.to_array().as_slice(),
+      ]
+      .concat()
+      .into_iter()
+      .map(f32::to_bits)
+      .collect();
+      if skinned {
+        key.extend(self.joints[i].iter().map(|joint| *joint as u32));
+      }
+      if weighted {
+        key.extend(self.weights[i].iter().map(|weight| weight.to_bits()));
+      }
+      if colored {
+        key.extend(self.colors[i].iter().map(|channel| channel.to_bits()));
+      }
+      for set in &self.extra_tex_coords {
+        if let Some(tex_coords) = set.get(i) {
+          key.extend(tex_coords.to_array().map(f32::to_bits));
+        }
+      }
+
+      let index = *lookup.entry(key).or_insert_with(|| {
+        order.push(i);
+        (order.len() - 1) as u32
+      });
+      remap.push(index);
+    }
+
+    if let Some(indices) = &mut self.indices {
+      for index in indices.iter_mut() {
+        *index = remap[*index as usize];
+      }
+    }
+    self.reorder_vertices(&order);
+  }
+
+  ///
+  /// Reorder the triangles with Tom Forsyth's linear-speed vertex cache optimization.
+  ///
+  fn optimize_vertex_cache(&mut self) {
+    let indices = match &self.indices {
+      Some(indices) => indices,
+      None => return,
+    };
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+      return;
+    }
+
+    // The triangles that use each vertex and haven't been drawn yet.
+    let mut vertex_triangles: Vec<Vec<usize>> = vec![vec![]; self.vertices.len()];
+    for triangle in 0..triangle_count {
+      for corner in 0..3 {
+        vertex_triangles[indices[triangle * 3 + corner] as usize].push(triangle);
+      }
+    }
+
+    let mut cache_positions: Vec<Option<usize>> = vec![None; self.vertices.len()];
+    let mut vertex_scores: Vec<f32> = vertex_triangles
+      .iter()
+      .map(|triangles| vertex_score(None, triangles.len()))
+      .collect();
+    let mut triangle_scores: Vec<f32> = (0..triangle_count)
+      .map(|triangle| {
+        (0..3)
+          .map(|corner| vertex_scores[indices[triangle * 3 + corner] as usize])
+          .sum()
+      })
+      .collect();
+    let mut drawn = vec![false; triangle_count];
+
+    let mut cache: Vec<u32> = vec![];
+    let mut output = Vec::with_capacity(indices.len());
+    let mut best = best_triangle(&triangle_scores, &drawn, 0..triangle_count);
+    // Where to continue the search when the cache runs dry.
+    let mut next_undrawn = 0;
+
+    while let Some(triangle) = best {
+      drawn[triangle] = true;
+      let corners = [
+        indices[triangle * 3],
+        indices[triangle * 3 + 1],
+        indices[triangle * 3 + 2],
+      ];
+      output.extend_from_slice(&corners);
+
+      for corner in corners {
+        vertex_triangles[corner as usize].retain(|other| *other != triangle);
+      }
+
+      // Move the corners to the front of the cache.
+      let mut new_cache = corners.to_vec();
+      new_cache.extend(cache.iter().filter(|vertex| !corners.contains(vertex)));
+      for (position, vertex) in new_cache.iter().enumerate() {
+        cache_positions[*vertex as usize] = if position < CACHE_SIZE {
+          Some(position)
+        } else {
+          None
+        };
+      }
+
+      // Rescore everything that was touched, including what fell out of the cache.
+      let mut touched_triangles = vec![];
+      for vertex in &new_cache {
+        let vertex = *vertex as usize;
+        vertex_scores[vertex] =
+          vertex_score(cache_positions[vertex], vertex_triangles[vertex].len());
+        touched_triangles.extend(&vertex_triangles[vertex]);
+      }
+      for other in &touched_triangles {
+        triangle_scores[*other] = (0..3)
+          .map(|corner| vertex_scores[indices[other * 3 + corner] as usize])
+          .sum();
+      }
+
+      new_cache.truncate(CACHE_SIZE);
+      cache = new_cache;
+
+      best = best_triangle(&triangle_scores, &drawn, touched_triangles.into_iter());
+      if best.is_none() {
+        while next_undrawn < triangle_count && drawn[next_undrawn] {
+          next_undrawn += 1;
+        }
+        best = best_triangle(&triangle_scores, &drawn, next_undrawn..triangle_count);
+      }
+    }
+
+    self.indices = Some(output);
+  }
+
+  ///
+  /// Reorder the vertices in the order the indices first use them.
+  ///
+  /// Vertices that aren't used at all are dropped.
+  ///
+  pub(super) fn optimize_vertex_fetch(&mut self) {
+    let indices = match &mut self.indices {
+      Some(indices) => indices,
+      None => return,
+    };
+
+    let mut remap: Vec<Option<u32>> = vec![None; self.vertices.len()];
+    let mut order = Vec::with_capacity(self.vertices.len());
+    for index in indices.iter_mut() {
+      let old = *index as usize;
+      *index = *remap[old].get_or_insert_with(|| {
+        order.push(old);
+        (order.len() - 1) as u32
+      });
+    }
+
+    self.reorder_vertices(&order);
+  }
+}
+
+///
+/// Score a vertex by where it is in the cache and how many triangles still need it.
+///
+fn vertex_score(cache_position: Option<usize>, remaining_triangles: usize) -> f32 {
+  if remaining_triangles == 0 {
+    return -1.0;
+  }
+
+  let cache_score = match cache_position {
+    // The last triangle's vertices get a fixed score, so the next triangle isn't just a repeat.
+    Some(position) if position < 3 => 0.75,
+    Some(position) => (1.0 - (position - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(1.5),
+    None => 0.0,
+  };
+
+  // Vertices with only a few triangles left get boosted, so they get finished off.
+  cache_score + 2.0 * (remaining_triangles as f32).powf(-0.5)
+}
+
+///
+/// Find the best scoring triangle that hasn't been drawn yet.
+///
+fn best_triangle(
+  triangle_scores: &[f32],
+  drawn: &[bool],
+  candidates: impl Iterator<Item = usize>,
+) -> Option<usize> {
+  let mut best: Option<usize> = None;
+  for triangle in candidates {
+    if drawn[triangle] {
+      continue;
+    }
+    if best.is_none_or(|best| triangle_scores[triangle] > triangle_scores[best]) {
+      best = Some(triangle);
+    }
+  }
+  best
+}
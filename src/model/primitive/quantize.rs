@@ -0,0 +1,321 @@
+use glam::{Mat4, Vec2, Vec3};
+
+use super::Primitive;
+use crate::bounds::BoundingBox;
+
+/// The biggest value of a 16 bit snorm.
+const SNORM16_MAX: f32 = i16::MAX as f32;
+
+///
+/// Turns quantized positions back into model space.
+///
+/// The scale is uniform, so the quantized mesh can be drawn with `matrix` as part of the model
+/// matrix without skewing the normals.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Dequantization {
+  /// Added after scaling. The center of the bounding box.
+  pub offset: Vec3,
+  /// What a normalized -1.0 - 1.0 position gets multiplied by.
+  pub scale: f32,
+}
+
+impl Dequantization {
+  ///
+  /// The transform for normalized positions, like the ones a GPU reads out of a snorm16 attribute.
+  ///
+  pub fn matrix(&self) -> Mat4 {
+    Mat4::from_translation(self.offset) * Mat4::from_scale(Vec3::splat(self.scale))
+  }
+
+  ///
+  /// Turn a quantized position back into a position.
+  ///
+  pub fn position(&self, quantized: [i16; 3]) -> Vec3 {
+    self.offset + Vec3::from_array(quantized.map(snorm16_to_f32)) * self.scale
+  }
+}
+
+///
+/// The biggest error the quantization made for each attribute. Zero for attributes the primitive
+/// doesn't have.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QuantizationReport {
+  /// How far any position moved, in model units.
+  pub position: f32,
+  /// The biggest angle any normal turned, in radians.
+  pub normal: f32,
+  /// The biggest angle any tangent turned, in radians.
+  pub tangent: f32,
+  /// The biggest change to any texture coordinate, in any set.
+  pub tex_coords: f32,
+  /// The biggest change to any weight.
+  pub weights: f32,
+}
+
+///
+/// The vertex attributes of a primitive in compact GPU formats.
+///
+/// Indices, joints and everything else stay on the primitive. Lists are empty for attributes
+/// the primitive doesn't have.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QuantizedPrimitive {
+  /// Normalized snorm16 positions. See `dequantization`.
+  pub positions: Vec<[i16; 3]>,
+  /// Octahedral encoded snorm16 normals. See `octahedral_decode`.
+  pub normals: Vec<[i16; 2]>,
+  /// Octahedral encoded snorm16 tangent directions, then the handedness as -1 or 1 snorm16.
+  pub tangents: Vec<[i16; 3]>,
+  /// Half float texture coordinates. See `f16_to_f32`.
+  pub tex_coords: Vec<[u16; 2]>,
+  /// Half float texture coordinates of the other sets.
+  pub extra_tex_coords: Vec<Vec<[u16; 2]>>,
+  /// Unorm8 weights. Every vertex that had weights adding up to 1.0 adds up to exactly 255.
+  pub weights: Vec<[u8; 4]>,
+  /// How to get the positions back.
+  pub dequantization: Dequantization,
+  /// How much was lost.
+  pub report: QuantizationReport,
+}
+
+impl Primitive {
+  ///
+  /// Quantize the vertex attributes into compact GPU formats, for memory-constrained clients.
+  ///
+  /// Positions become normalized i16 around the center of the bounding box of the vertices, normals and tangents
+  /// become octahedral snorm16, texture coordinates become half floats and weights become unorm8.
+  /// The primitive itself isn't touched.
+  ///
+  pub fn quantize(&self) -> QuantizedPrimitive {
+    let mut report = QuantizationReport::default();
+
+    // ? Worked out from the vertices, the cached bounds might be out of date.
+    let bounding_box = BoundingBox::from_points(self.vertices.iter().map(|vertex| vertex.position))
+      .unwrap_or_default();
+    let dequantization = Dequantization {
+      offset: bounding_box.center(),
+      scale: match bounding_box.size().max_element() * 0.5 {
+        scale if scale > 0.0 => scale,
+        _ => 1.0,
+      },
+    };
+    let positions = self
+      .vertices
+      .iter()
+      .map(|vertex| {
+        let normalized = (vertex.position - dequantization.offset) / dequantization.scale;
+        let quantized = normalized.to_array().map(f32_to_snorm16);
+        report.position = report
+          .position
+          .max(dequantization.position(quantized).distance(vertex.position));
+        quantized
+      })
+      .collect();
+
+    let mut normals = vec![];
+    if self.has_normals {
+      normals = self
+        .vertices
+        .iter()
+        .map(|vertex| {
+          let encoded = octahedral_encode(vertex.normal);
+          report.normal = report
+            .normal
+            .max(angle_error(vertex.normal, octahedral_decode(encoded)));
+          encoded
+        })
+        .collect();
+    }
+
+    let mut tangents = vec![];
+    if self.has_tangents {
+      tangents = self
+        .vertices
+        .iter()
+        .map(|vertex| {
+          let direction = vertex.tangent.truncate();
+          let [x, y] = octahedral_encode(direction);
+          report.tangent = report
+            .tangent
+            .max(angle_error(direction, octahedral_decode([x, y])));
+          let handedness = if vertex.tangent.w < 0.0 { -1.0 } else { 1.0 };
+          [x, y, f32_to_snorm16(handedness)]
+        })
+        .collect();
+    }
+
+    let mut quantize_tex_coords = |tex_coords: Vec2| {
+      let quantized = tex_coords.to_array().map(f32_to_f16);
+      let error = (Vec2::from_array(quantized.map(f16_to_f32)) - tex_coords)
+        .abs()
+        .max_element();
+      report.tex_coords = report.tex_coords.max(error);
+      quantized
+    };
+    let mut tex_coords = vec![];
+    if self.has_tex_coords {
+      tex_coords = self
+        .vertices
+        .iter()
+        .map(|vertex| quantize_tex_coords(vertex.tex_coords))
+        .collect();
+    }
+    let extra_tex_coords = self
+      .extra_tex_coords
+      .iter()
+      .map(|set| set.iter().map(|uv| quantize_tex_coords(*uv)).collect())
+      .collect();
+
+    let weights = self
+      .weights
+      .iter()
+      .map(|weights| {
+        let quantized = quantize_weights(weights);
+        for (weight, quantized) in weights.iter().zip(quantized) {
+          report.weights = report
+            .weights
+            .max((quantized as f32 / 255.0 - weight).abs());
+        }
+        quantized
+      })
+      .collect();
+
+    QuantizedPrimitive {
+      positions,
+      normals,
+      tangents,
+      tex_coords,
+      extra_tex_coords,
+      weights,
+      dequantization,
+      report,
+    }
+  }
+}
+
+///
+/// Weights as unorm8. The rounding is fixed up on the biggest weight so weights that added up
+/// to 1.0 still do.
+///
+fn quantize_weights(weights: &[f32; 4]) -> [u8; 4] {
+  let mut quantized = weights.map(|weight| (weight.clamp(0.0, 1.0) * 255.0).round() as i32);
+  let sum: f32 = weights.iter().sum();
+  if (sum - 1.0).abs() < 0.01 {
+    let mut biggest = 0;
+    for i in 1..4 {
+      if quantized[i] > quantized[biggest] {
+        biggest = i;
+      }
+    }
+    quantized[biggest] += 255 - quantized.iter().sum::<i32>();
+  }
+  quantized.map(|weight| weight.clamp(0, 255) as u8)
+}
+
+fn angle_error(original: Vec3, decoded: Vec3) -> f32 {
+  match original.try_normalize() {
+    Some(original) => original.dot(decoded).clamp(-1.0, 1.0).acos(),
+    // ? Zero vectors have no direction to lose.
+    None => 0.0,
+  }
+}
+
+fn f32_to_snorm16(value: f32) -> i16 {
+  (value.clamp(-1.0, 1.0) * SNORM16_MAX).round() as i16
+}
+
+fn snorm16_to_f32(value: i16) -> f32 {
+  (value as f32 / SNORM16_MAX).max(-1.0)
+}
+
+///
+/// Encode a direction as two snorm16 values by folding the octahedron it's projected on.
+///
+/// Zero vectors come out as straight up Z.
+///
+pub fn octahedral_encode(direction: Vec3) -> [i16; 2] {
+  let length = direction.x.abs() + direction.y.abs() + direction.z.abs();
+  if length <= 0.0 {
+    return [0, 0];
+  }
+  let direction = direction / length;
+  let folded = if direction.z >= 0.0 {
+    Vec2::new(direction.x, direction.y)
+  } else {
+    (Vec2::ONE - Vec2::new(direction.y.abs(), direction.x.abs()))
+      * Vec2::new(direction.x.signum(), direction.y.signum())
+  };
+  folded.to_array().map(f32_to_snorm16)
+}
+
+///
+/// Decode a direction out of `octahedral_encode`. The result is normalized.
+///
+pub fn octahedral_decode(encoded: [i16; 2]) -> Vec3 {
+  let [x, y] = encoded.map(snorm16_to_f32);
+  let mut direction = Vec3::new(x, y, 1.0 - x.abs() - y.abs());
+  let fold = (-direction.z).max(0.0);
+  direction.x -= fold * direction.x.signum();
+  direction.y -= fold * direction.y.signum();
+  direction.normalize()
+}
+
+///
+/// Convert a float to the bits of a half float, rounding to the nearest even.
+///
+/// Too big values become infinity.
+///
+pub fn f32_to_f16(value: f32) -> u16 {
+  let bits = value.to_bits();
+  let sign = ((bits >> 16) & 0x8000) as u16;
+  let exponent = ((bits >> 23) & 0xff) as i32;
+  let mantissa = bits & 0x7f_ffff;
+
+  // Infinity and NaN.
+  if exponent == 0xff {
+    return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+  }
+
+  let round = |value: u32, shift: u32| {
+    let truncated = value >> shift;
+    let remainder = value & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    if remainder > halfway || (remainder == halfway && truncated & 1 == 1) {
+      truncated + 1
+    } else {
+      truncated
+    }
+  };
+
+  let exponent = exponent - 127 + 15;
+  if exponent >= 0x1f {
+    return sign | 0x7c00;
+  }
+  if exponent <= 0 {
+    // Too small for a normal half float.
+    if exponent < -10 {
+      return sign;
+    }
+    return sign | round(mantissa | 0x80_0000, (14 - exponent) as u32) as u16;
+  }
+  // ? Rounding up can carry into the exponent, which is still the right answer.
+  sign | round(((exponent as u32) << 23) | mantissa, 13) as u16
+}
+
+///
+/// Convert the bits of a half float to a float.
+///
+pub fn f16_to_f32(bits: u16) -> f32 {
+  let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+  let exponent = ((bits >> 10) & 0x1f) as i32;
+  let mantissa = (bits & 0x3ff) as f32;
+  sign
+    * match exponent {
+      0 => mantissa * 2f32.powi(-24),
+      0x1f if mantissa == 0.0 => f32::INFINITY,
+      0x1f => f32::NAN,
+      _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
@@ -0,0 +1,401 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use ahash::{AHashMap, AHashSet};
+use glam::{DVec3, Vec3};
+
+use super::{BadMode, Mode, Primitive};
+use crate::Model;
+
+///
+/// A quadric error metric. The sum of squared distances to a set of planes.
+///
+/// Stored as the upper triangle of a symmetric 4x4 matrix.
+///
+#[derive(Clone, Copy, Default)]
+struct Quadric {
+  a: [f64; 10],
+}
+
+impl Quadric {
+  ///
+  /// The quadric of the plane a triangle is on, weighted by the triangle area.
+  ///
+  fn from_triangle(p0: Vec3, p1: Vec3, p2: Vec3) -> Self {
+    let (p0, p1, p2) = (p0.as_dvec3(), p1.as_dvec3(), p2.as_dvec3());
+    let cross = (p1 - p0).cross(p2 - p0);
+    let length = cross.length();
+    if length <= f64::EPSILON {
+      return Quadric::default();
+    }
+    let normal = cross / length;
+    let d = -normal.dot(p0);
+    let area = length * 0.5;
+    let (a, b, c) = (normal.x, normal.y, normal.z);
+    Quadric {
+      a: [
+        a * a * area,
+        a * b * area,
+        a * c * area,
+        a * d * area,
+        b * b * area,
+        b * c * area,
+        b * d * area,
+        c * c * area,
+        c * d * area,
+        d * d * area,
+      ],
+    }
+  }
+
+  fn add(&self, other: &Quadric) -> Self {
+    let mut a = self.a;
+    for (value, other) in a.iter_mut().zip(other.a) {
+      *value += other;
+    }
+    Quadric { a }
+  }
+
+  ///
+  /// The error of moving to a point.
+  ///
+  fn error(&self, point: DVec3) -> f64 {
+    let [aa, ab, ac, ad, bb, bc, bd, cc, cd, dd] = self.a;
+    let (x, y, z) = (point.x, point.y, point.z);
+    (aa * x * x + 2.0 * ab * x * y + 2.0 * ac * x * z + 2.0 * ad * x)
+      + (bb * y * y + 2.0 * bc * y * z + 2.0 * bd * y)
+      + (cc * z * z + 2.0 * cd * z)
+      + dd
+  }
+}
+
+///
+/// Moving the `from` vertex onto the `to` vertex.
+///
+/// Ordered so the cheapest collapse comes out of the heap first.
+///
+struct Collapse {
+  cost: f64,
+  from: u32,
+  to: u32,
+  from_version: u32,
+  to_version: u32,
+}
+
+impl PartialEq for Collapse {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Collapse {
+  fn cmp(&self, other: &Self) -> Ordering {
+    other.cost.total_cmp(&self.cost)
+  }
+}
+
+///
+/// The working state of a simplification.
+///
+struct Simplifier<'a> {
+  primitive: &'a Primitive,
+  triangles: Vec<[u32; 3]>,
+  alive: Vec<bool>,
+  // The alive triangles around each vertex.
+  vertex_triangles: Vec<Vec<usize>>,
+  quadrics: Vec<Quadric>,
+  // Locked vertices never move. Seams and borders are locked.
+  locked: Vec<bool>,
+  // The joint with the biggest weight. Only vertices that follow the same joint are merged.
+  dominant_joints: Option<Vec<u16>>,
+  versions: Vec<u32>,
+  heap: BinaryHeap<Collapse>,
+  triangle_count: usize,
+}
+
+impl<'a> Simplifier<'a> {
+  fn new(primitive: &'a Primitive, triangles: Vec<[u32; 3]>) -> Self {
+    let vertex_count = primitive.vertices.len();
+    let position = |index: u32| primitive.vertices[index as usize].position;
+
+    // Vertices that share a position, but not the rest of the attributes, are on a seam.
+    let mut position_groups: AHashMap<[u32; 3], u32> = AHashMap::new();
+    let mut group_sizes: Vec<u32> = vec![];
+    let groups: Vec<u32> = primitive
+      .vertices
+      .iter()
+      .map(|vertex| {
+        let key = vertex.position.to_array().map(f32::to_bits);
+        let group = *position_groups.entry(key).or_insert_with(|| {
+          group_sizes.push(0);
+          (group_sizes.len() - 1) as u32
+        });
+        group_sizes[group as usize] += 1;
+        group
+      })
+      .collect();
+
+    // Edges with only one triangle (by position, so seams don't count) are on a border.
+    let mut edge_uses: AHashMap<(u32, u32), u32> = AHashMap::new();
+    for triangle in &triangles {
+      for corner in 0..3 {
+        let a = groups[triangle[corner] as usize];
+        let b = groups[triangle[(corner + 1) % 3] as usize];
+        *edge_uses.entry((a.min(b), a.max(b))).or_default() += 1;
+      }
+    }
+    let mut border_groups: AHashSet<u32> = AHashSet::new();
+    for ((a, b), uses) in edge_uses {
+      if uses == 1 {
+        border_groups.insert(a);
+        border_groups.insert(b);
+      }
+    }
+
+    let locked = groups
+      .iter()
+      .map(|group| group_sizes[*group as usize] > 1 || border_groups.contains(group))
+      .collect();
+
+    let mut vertex_triangles = vec![vec![]; vertex_count];
+    let mut quadrics = vec![Quadric::default(); vertex_count];
+    for (i, triangle) in triangles.iter().enumerate() {
+      let quadric = Quadric::from_triangle(
+        position(triangle[0]),
+        position(triangle[1]),
+        position(triangle[2]),
+      );
+      for corner in triangle {
+        vertex_triangles[*corner as usize].push(i);
+        quadrics[*corner as usize] = quadrics[*corner as usize].add(&quadric);
+      }
+    }
+
+    let skinned = primitive.has_joints
+      && primitive.joints.len() == vertex_count
+      && primitive.weights.len() == vertex_count;
+    let dominant_joints = skinned.then(|| {
+      primitive
+        .joints
+        .iter()
+        .zip(&primitive.weights)
+        .map(|(joints, weights)| {
+          let mut best = 0;
+          for i in 1..4 {
+            if weights[i] > weights[best] {
+              best = i;
+            }
+          }
+          joints[best]
+        })
+        .collect()
+    });
+
+    let triangle_count = triangles.len();
+    Simplifier {
+      primitive,
+      alive: vec![true; triangle_count],
+      triangles,
+      vertex_triangles,
+      quadrics,
+      locked,
+      dominant_joints,
+      versions: vec![0; vertex_count],
+      heap: BinaryHeap::new(),
+      triangle_count,
+    }
+  }
+
+  fn position(&self, index: u32) -> Vec3 {
+    self.primitive.vertices[index as usize].position
+  }
+
+  ///
+  /// Queue up every collapse out of a vertex and into a vertex.
+  ///
+  fn push_collapses(&mut self, vertex: u32) {
+    let mut neighbors = vec![];
+    for triangle in &self.vertex_triangles[vertex as usize] {
+      for corner in self.triangles[*triangle] {
+        if corner != vertex && !neighbors.contains(&corner) {
+          neighbors.push(corner);
+        }
+      }
+    }
+    for neighbor in neighbors {
+      self.push_collapse(vertex, neighbor);
+      self.push_collapse(neighbor, vertex);
+    }
+  }
+
+  fn push_collapse(&mut self, from: u32, to: u32) {
+    if self.locked[from as usize] {
+      return;
+    }
+    if let Some(dominant_joints) = &self.dominant_joints {
+      if dominant_joints[from as usize] != dominant_joints[to as usize] {
+        return;
+      }
+    }
+    let quadric = self.quadrics[from as usize].add(&self.quadrics[to as usize]);
+    self.heap.push(Collapse {
+      cost: quadric.error(self.position(to).as_dvec3()),
+      from,
+      to,
+      from_version: self.versions[from as usize],
+      to_version: self.versions[to as usize],
+    });
+  }
+
+  ///
+  /// Get if moving `from` onto `to` would flip or squash any triangle that stays around.
+  ///
+  fn flips(&self, from: u32, to: u32) -> bool {
+    let target = self.position(to);
+    for triangle in &self.vertex_triangles[from as usize] {
+      let corners = self.triangles[*triangle];
+      if corners.contains(&to) {
+        continue;
+      }
+      let before = corners.map(|corner| self.position(corner));
+      let after = corners.map(|corner| {
+        if corner == from {
+          target
+        } else {
+          self.position(corner)
+        }
+      });
+      let normal_before = (before[1] - before[0]).cross(before[2] - before[0]);
+      let normal_after = (after[1] - after[0]).cross(after[2] - after[0]);
+      if normal_after.length_squared() <= f32::EPSILON * normal_before.length_squared()
+        || normal_before.dot(normal_after) <= 0.0
+      {
+        return true;
+      }
+    }
+    false
+  }
+
+  fn collapse(&mut self, from: u32, to: u32) {
+    let triangles = std::mem::take(&mut self.vertex_triangles[from as usize]);
+    for triangle in triangles {
+      if self.triangles[triangle].contains(&to) {
+        // This one gets squashed flat. Take it out of everything.
+        self.alive[triangle] = false;
+        self.triangle_count -= 1;
+        for corner in self.triangles[triangle] {
+          if corner != from {
+            self.vertex_triangles[corner as usize].retain(|other| *other != triangle);
+          }
+        }
+      } else {
+        for corner in &mut self.triangles[triangle] {
+          if *corner == from {
+            *corner = to;
+          }
+        }
+        self.vertex_triangles[to as usize].push(triangle);
+      }
+    }
+
+    self.quadrics[to as usize] = self.quadrics[to as usize].add(&self.quadrics[from as usize]);
+    self.versions[from as usize] += 1;
+    self.versions[to as usize] += 1;
+    self.push_collapses(to);
+  }
+
+  fn run(mut self, target_triangles: usize) -> Vec<u32> {
+    for vertex in 0..self.primitive.vertices.len() as u32 {
+      if !self.locked[vertex as usize] {
+        self.push_collapses(vertex);
+      }
+    }
+
+    while self.triangle_count > target_triangles {
+      let next = match self.heap.pop() {
+        Some(next) => next,
+        None => break,
+      };
+      let stale = next.from_version != self.versions[next.from as usize]
+        || next.to_version != self.versions[next.to as usize]
+        || self.vertex_triangles[next.from as usize].is_empty();
+      if stale || self.flips(next.from, next.to) {
+        continue;
+      }
+      self.collapse(next.from, next.to);
+    }
+
+    self
+      .triangles
+      .iter()
+      .zip(&self.alive)
+      .filter(|(_, alive)| **alive)
+      .flat_map(|(triangle, _)| *triangle)
+      .collect()
+  }
+}
+
+impl Primitive {
+  ///
+  /// Simplify the primitive down to about `ratio` (0.0 - 1.0) of its triangles with quadric
+  /// error metric edge collapses.
+  ///
+  /// Vertices are only ever moved onto other vertices, so every vertex that's left keeps its
+  /// texture coordinates, joints and weights. It still animates with the same bone animations.
+  /// Vertices on UV seams and open borders are never moved, and vertices only merge with vertices
+  /// that follow the same joint the most.
+  ///
+  /// The ratio is a target. Simplification stops early when nothing else can be collapsed.
+  /// Unwelded vertices all look like seams, so run `optimize` first on primitives without indices.
+  ///
+  /// **Note**: This function will return an error if the mode isn't `Triangles`, `TriangleFan`
+  /// or `TriangleStrip`.
+  ///
+  pub fn simplify(&self, ratio: f32) -> Result<Primitive, BadMode> {
+    let triangles = self.triangle_indices()?;
+    let target_triangles = (triangles.len() as f32 * ratio.clamp(0.0, 1.0)).ceil() as usize;
+
+    let mut simplified = self.clone();
+    simplified.mode = Mode::Triangles;
+    simplified.indices = Some(Simplifier::new(self, triangles).run(target_triangles));
+    simplified.optimize_vertex_fetch();
+    simplified.update_bounds();
+    Ok(simplified)
+  }
+
+  ///
+  /// Make a level of detail for every ratio. See `simplify`.
+  ///
+  pub fn generate_lods(&self, ratios: &[f32]) -> Result<Vec<Primitive>, BadMode> {
+    ratios.iter().map(|ratio| self.simplify(*ratio)).collect()
+  }
+}
+
+impl Model {
+  ///
+  /// Make a level of detail of the whole model for every ratio. See `Primitive::simplify`.
+  ///
+  /// Lines and points are left alone.
+  ///
+  pub fn generate_lods(&self, ratios: &[f32]) -> Vec<Model> {
+    ratios
+      .iter()
+      .map(|ratio| {
+        let mut lod = self.clone();
+        for primitive in &mut lod.primitives {
+          if let Ok(simplified) = primitive.simplify(*ratio) {
+            *primitive = simplified;
+          }
+        }
+        lod
+      })
+      .collect()
+  }
+}
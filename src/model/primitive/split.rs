@@ -0,0 +1,131 @@
+use ahash::AHashMap;
+
+use super::{Mode, Primitive};
+use crate::Model;
+
+/// The most vertices a primitive can have and still be drawn with 16 bit indices.
+pub const MAX_U16_VERTICES: usize = u16::MAX as usize;
+
+impl Primitive {
+  ///
+  /// Split the primitive into primitives of at most `max_vertices` unique vertices each.
+  ///
+  /// Primitives that are small enough already come back as they are. Otherwise strips, fans and
+  /// loops are unrolled into lists first, and whole triangles (or lines, or points) are handed
+  /// out to the chunks in order, so nothing gets cut in half. Joints, weights, colors, the
+  /// material and the texture slot are kept.
+  ///
+  /// Use `MAX_U16_VERTICES` for minetest/irrlicht mesh buffers, then `indices_u16` on each chunk.
+  ///
+  pub fn split(&self, max_vertices: usize) -> Vec<Primitive> {
+    if self.vertices.len() <= max_vertices {
+      return vec![self.clone()];
+    }
+    // Every chunk needs room for at least one whole triangle.
+    let max_vertices = max_vertices.max(3);
+
+    let (mode, indices) = match self.mode {
+      Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan => (
+        Mode::Triangles,
+        self
+          .to_triangle_list()
+          .ok()
+          .and_then(|list| list.indices)
+          .unwrap_or_default(),
+      ),
+      Mode::Lines | Mode::LineStrip | Mode::LineLoop => (
+        Mode::Lines,
+        self
+          .to_line_list()
+          .ok()
+          .and_then(|list| list.indices)
+          .unwrap_or_default(),
+      ),
+      Mode::Points => (
+        Mode::Points,
+        match &self.indices {
+          Some(indices) => indices.clone(),
+          None => (0..self.vertices.len() as u32).collect(),
+        },
+      ),
+    };
+    let group_size = match mode {
+      Mode::Triangles => 3,
+      Mode::Lines => 2,
+      _ => 1,
+    };
+
+    let finish = |order: &[usize], indices: Vec<u32>| {
+      let mut chunk = self.without_data(mode.clone());
+      chunk.gather_vertices(self, order);
+      chunk.indices = Some(indices);
+      chunk.update_bounds();
+      chunk
+    };
+
+    let mut chunks = vec![];
+    let mut order: Vec<usize> = vec![];
+    let mut chunk_indices: Vec<u32> = vec![];
+    let mut remap: AHashMap<u32, u32> = AHashMap::new();
+
+    for group in indices.chunks_exact(group_size) {
+      let new_vertices = group
+        .iter()
+        .enumerate()
+        .filter(|(i, index)| !remap.contains_key(*index) && !group[..*i].contains(*index))
+        .count();
+      if order.len() + new_vertices > max_vertices {
+        chunks.push(finish(&order, std::mem::take(&mut chunk_indices)));
+        order.clear();
+        remap.clear();
+      }
+
+      for index in group {
+        let local = *remap.entry(*index).or_insert_with(|| {
+          order.push(*index as usize);
+          (order.len() - 1) as u32
+        });
+        chunk_indices.push(local);
+      }
+    }
+
+    if !chunk_indices.is_empty() {
+      chunks.push(finish(&order, chunk_indices));
+    }
+    chunks
+  }
+
+  ///
+  /// Get the indices as 16 bit indices. Generated if the primitive doesn't have any.
+  ///
+  /// None if the primitive has more than `MAX_U16_VERTICES` vertices. See `split`.
+  ///
+  pub fn indices_u16(&self) -> Option<Vec<u16>> {
+    if self.vertices.len() > MAX_U16_VERTICES {
+      return None;
+    }
+    match &self.indices {
+      Some(indices) => indices
+        .iter()
+        .map(|index| u16::try_from(*index).ok())
+        .collect(),
+      None => Some((0..self.vertices.len() as u16).collect()),
+    }
+  }
+}
+
+impl Model {
+  ///
+  /// Split every primitive that is too big for 16 bit indices. See `Primitive::split`.
+  ///
+  /// The chunks take the place of the primitive they came from, so the texture slots stay in
+  /// the same order.
+  ///
+  pub fn split_for_u16_indices(&mut self) {
+    self.primitives = self
+      .primitives
+      .iter()
+      .flat_map(|primitive| primitive.split(MAX_U16_VERTICES))
+      .collect();
+  }
+}
@@ -0,0 +1,225 @@
+use glam::{Mat3, Vec2};
+
+use super::Primitive;
+use crate::Model;
+
+/// The name of the extension.
+const EXTENSION_NAME: &str = "KHR_texture_transform";
+
+///
+/// A `KHR_texture_transform` on a texture reference. Texture atlases use it to pick their piece
+/// of the texture.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureTransform {
+  /// Where the UV origin moves to, as a factor of the texture size.
+  pub offset: Vec2,
+  /// Counter-clockwise rotation of the UVs around the origin, in radians.
+  pub rotation: f32,
+  /// What the UVs get multiplied by.
+  pub scale: Vec2,
+  /// The texture coordinate set the texture reads. The extension can override the one of the
+  /// texture reference.
+  pub tex_coord: usize,
+}
+
+impl Default for TextureTransform {
+  fn default() -> Self {
+    Self {
+      offset: Vec2::ZERO,
+      rotation: 0.0,
+      scale: Vec2::ONE,
+      tex_coord: 0,
+    }
+  }
+}
+
+impl TextureTransform {
+  ///
+  /// The UV transform, for engines with texture matrices. Scale, then rotation, then offset.
+  ///
+  pub fn matrix(&self) -> Mat3 {
+    // ? V points down, so counter-clockwise to the extension is clockwise to glam.
+    Mat3::from_scale_angle_translation(self.scale, -self.rotation, self.offset)
+  }
+
+  ///
+  /// Transform one texture coordinate.
+  ///
+  pub fn apply(&self, tex_coords: Vec2) -> Vec2 {
+    self.matrix().transform_point2(tex_coords)
+  }
+
+  ///
+  /// Read the extension off a texture reference that reads set `tex_coord`. References without
+  /// it get the identity transform.
+  ///
+  fn load(extension: Option<&gltf::json::Value>, tex_coord: u32) -> Self {
+    let extension = match extension {
+      Some(extension) => extension,
+      None => {
+        return Self {
+          tex_coord: tex_coord as usize,
+          ..Default::default()
+        }
+      }
+    };
+    let vec2 = |name: &str, default: Vec2| match extension.get(name).and_then(|v| v.as_array()) {
+      Some(values) if values.len() == 2 => Vec2::new(
+        values[0].as_f64().unwrap_or(default.x as f64) as f32,
+        values[1].as_f64().unwrap_or(default.y as f64) as f32,
+      ),
+      _ => default,
+    };
+    Self {
+      offset: vec2("offset", Vec2::ZERO),
+      rotation: extension
+        .get("rotation")
+        .and_then(|rotation| rotation.as_f64())
+        .unwrap_or(0.0) as f32,
+      scale: vec2("scale", Vec2::ONE),
+      tex_coord: extension
+        .get("texCoord")
+        .and_then(|tex_coord| tex_coord.as_u64())
+        .unwrap_or(tex_coord as u64) as usize,
+    }
+  }
+
+  ///
+  /// If the transform doesn't move any UVs.
+  ///
+  pub fn is_identity(&self) -> bool {
+    self.offset == Vec2::ZERO && self.rotation == 0.0 && self.scale == Vec2::ONE
+  }
+}
+
+///
+/// The texture transforms of every texture reference of a material. None for references that
+/// aren't there. References without a transform get the identity transform, which still says
+/// the set they read.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextureTransforms {
+  /// The base color texture. The one minetest draws.
+  pub base_color: Option<TextureTransform>,
+  /// The metallic roughness texture.
+  pub metallic_roughness: Option<TextureTransform>,
+  /// The normal map.
+  pub normal: Option<TextureTransform>,
+  /// The occlusion texture.
+  pub occlusion: Option<TextureTransform>,
+  /// The emissive texture.
+  pub emissive: Option<TextureTransform>,
+}
+
+impl TextureTransforms {
+  ///
+  /// Read the transforms of every texture reference of a material.
+  ///
+  pub(crate) fn load(material: &gltf::Material) -> Self {
+    let info = |info: Option<gltf::texture::Info>| {
+      info
+        .map(|info| TextureTransform::load(info.extension_value(EXTENSION_NAME), info.tex_coord()))
+    };
+    let pbr = material.pbr_metallic_roughness();
+    Self {
+      base_color: info(pbr.base_color_texture()),
+      metallic_roughness: info(pbr.metallic_roughness_texture()),
+      normal: material.normal_texture().map(|normal| {
+        TextureTransform::load(normal.extension_value(EXTENSION_NAME), normal.tex_coord())
+      }),
+      occlusion: material.occlusion_texture().map(|occlusion| {
+        TextureTransform::load(
+          occlusion.extension_value(EXTENSION_NAME),
+          occlusion.tex_coord(),
+        )
+      }),
+      emissive: info(material.emissive_texture()),
+    }
+  }
+
+  ///
+  /// The transforms that are the same in all of them.
+  ///
+  pub(crate) fn common<'a>(mut all: impl Iterator<Item = &'a TextureTransforms>) -> Self {
+    let first = all.next().copied().unwrap_or_default();
+    all.fold(first, |common, transforms| {
+      let same = |a: Option<TextureTransform>, b: Option<TextureTransform>| a.filter(|_| a == b);
+      Self {
+        base_color: same(common.base_color, transforms.base_color),
+        metallic_roughness: same(common.metallic_roughness, transforms.metallic_roughness),
+        normal: same(common.normal, transforms.normal),
+        occlusion: same(common.occlusion, transforms.occlusion),
+        emissive: same(common.emissive, transforms.emissive),
+      }
+    })
+  }
+}
+
+impl Primitive {
+  ///
+  /// Bake the base color texture transform into the first texture coordinate set.
+  ///
+  /// For engines without texture matrices, like minetest, that always draw the first set. The
+  /// set the transform reads is transformed into the first set, and the transform is dropped.
+  /// If other texture references read the first set, it gets moved to a new set at the end of
+  /// `extra_tex_coords` for them, and their `tex_coord` points there.
+  ///
+  /// Nothing happens if there is no transform, or the set it reads doesn't exist.
+  ///
+  pub fn bake_texture_transform(&mut self) {
+    let transform = match self.texture_transforms.base_color {
+      Some(transform) if !(transform.is_identity() && transform.tex_coord == 0) => transform,
+      _ => return,
+    };
+
+    let count = self.vertices.len();
+    let source: Vec<Vec2> = match transform.tex_coord {
+      0 if self.has_tex_coords => self.vertices.iter().map(|v| v.tex_coords).collect(),
+      0 => return,
+      set => match self.extra_tex_coords.get(set - 1) {
+        Some(tex_coords) if tex_coords.len() == count => tex_coords.clone(),
+        _ => return,
+      },
+    };
+
+    let transforms = &mut self.texture_transforms;
+    let readers_of_first_set: Vec<&mut TextureTransform> = [
+      &mut transforms.metallic_roughness,
+      &mut transforms.normal,
+      &mut transforms.occlusion,
+      &mut transforms.emissive,
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|other| other.tex_coord == 0)
+    .collect();
+    if self.has_tex_coords && !readers_of_first_set.is_empty() {
+      let moved_set = self.extra_tex_coords.len() + 1;
+      for other in readers_of_first_set {
+        other.tex_coord = moved_set;
+      }
+      self
+        .extra_tex_coords
+        .push(self.vertices.iter().map(|v| v.tex_coords).collect());
+    }
+
+    for (vertex, tex_coords) in self.vertices.iter_mut().zip(source) {
+      vertex.tex_coords = transform.apply(tex_coords);
+    }
+    self.has_tex_coords = true;
+    self.texture_transforms.base_color = None;
+  }
+}
+
+impl Model {
+  ///
+  /// Bake the base color texture transform of every primitive. See
+  /// `Primitive::bake_texture_transform`.
+  ///
+  pub fn bake_texture_transforms(&mut self) {
+    for primitive in &mut self.primitives {
+      primitive.bake_texture_transform();
+    }
+  }
+}
@@ -0,0 +1,187 @@
+use glam::{Mat4, Quat, Vec3};
+use gltf::{buffer::Data, Gltf};
+
+use crate::animation::Pose;
+
+///
+/// A single node (bone) in the document's scene graph.
+///
+/// Every node in the document is kept, not just the skin joints, so
+/// the hierarchy can be walked from any node.
+///
+#[derive(Clone, Debug)]
+pub struct SkeletonNode {
+  #[cfg(feature = "names")]
+  /// Node name. Requires the `names` feature.
+  pub name: Option<String>,
+  /// The node id. This is the same id `bone_animations` is keyed by.
+  pub id: i32,
+  /// The parent node id. `None` if this is a root node.
+  pub parent: Option<i32>,
+  /// The children node ids.
+  pub children: Vec<i32>,
+  /// Rest translation.
+  pub translation: Vec3,
+  /// Rest rotation.
+  pub rotation: Quat,
+  /// Rest scale.
+  pub scale: Vec3,
+}
+
+///
+/// The node hierarchy and the first skin of a model.
+///
+#[derive(Clone, Debug, Default)]
+pub struct Skeleton {
+  /// All nodes in the document. Indexed by node id.
+  pub nodes: Vec<SkeletonNode>,
+  /// The node ids of the skin joints. `JOINTS_0` indexes into this.
+  pub joints: Vec<i32>,
+  /// The inverse bind matrix of each joint. Same order as `joints`.
+  pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+impl Skeleton {
+  ///
+  /// Load up the skeleton.
+  ///
+  /// ? We are mimicking minetest C++ and only getting the first skin.
+  ///
+  pub(crate) fn load(gltf_data: &Gltf, buffers: &[Data]) -> Option<Self> {
+    let skin = gltf_data.skins().next()?;
+
+    let mut nodes: Vec<SkeletonNode> = gltf_data
+      .nodes()
+      .map(|node| {
+        let (translation, rotation, scale) = node.transform().decomposed();
+        SkeletonNode {
+          #[cfg(feature = "names")]
+          name: node.name().map(String::from),
+          id: node.index() as i32,
+          parent: None,
+          children: node.children().map(|child| child.index() as i32).collect(),
+          translation: Vec3::from_array(translation),
+          rotation: Quat::from_array(rotation),
+          scale: Vec3::from_array(scale),
+        }
+      })
+      .collect();
+
+    // Now link the parents up.
+    for parent in 0..nodes.len() {
+      for child in nodes[parent].children.clone() {
+        nodes[child as usize].parent = Some(parent as i32);
+      }
+    }
+
+    let joints: Vec<i32> = skin.joints().map(|joint| joint.index() as i32).collect();
+
+    // * If there are no inverse bind matrices, the spec says they're all identity.
+    let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+    let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+      Some(matrices) => matrices.map(|m| Mat4::from_cols_array_2d(&m)).collect(),
+      None => vec![Mat4::IDENTITY; joints.len()],
+    };
+
+    Some(Skeleton {
+      nodes,
+      joints,
+      inverse_bind_matrices,
+    })
+  }
+
+  ///
+  /// Get a node by its id.
+  ///
+  pub fn node(&self, id: i32) -> Option<&SkeletonNode> {
+    if id < 0 {
+      return None;
+    }
+    self.nodes.get(id as usize)
+  }
+
+  ///
+  /// Get the ids of a node and every node underneath it.
+  ///
+  /// The root of the sub-tree comes first.
+  ///
+  pub fn subtree(&self, root: i32) -> Vec<i32> {
+    let mut found = vec![];
+    let mut stack = vec![root];
+    while let Some(id) = stack.pop() {
+      if let Some(node) = self.node(id) {
+        found.push(id);
+        stack.extend(node.children.iter().rev());
+      }
+    }
+    found
+  }
+
+  #[cfg(feature = "names")]
+  ///
+  /// Find the id of the first node with this name. Requires the `names` feature.
+  ///
+  pub fn find_node(&self, name: &str) -> Option<i32> {
+    self
+      .nodes
+      .iter()
+      .find(|node| node.name.as_deref() == Some(name))
+      .map(|node| node.id)
+  }
+
+  ///
+  /// Get the global transform of every node from a pose. Indexed by node id.
+  ///
+  /// Nodes that are not in the pose use identity.
+  ///
+  pub fn global_transforms(&self, pose: &Pose) -> Vec<Mat4> {
+    let mut globals: Vec<Option<Mat4>> = vec![None; self.nodes.len()];
+
+    for node in &self.nodes {
+      // Walk up to the root, then come back down.
+      let mut chain = vec![node.id];
+      let mut parent = node.parent;
+      while let Some(id) = parent {
+        if globals[id as usize].is_some() {
+          break;
+        }
+        chain.push(id);
+        parent = self.nodes[id as usize].parent;
+      }
+
+      for id in chain.into_iter().rev() {
+        let local = pose.get(id).to_matrix();
+        let global = match self.nodes[id as usize].parent {
+          Some(parent) => globals[parent as usize].unwrap_or(Mat4::IDENTITY) * local,
+          None => local,
+        };
+        globals[id as usize] = Some(global);
+      }
+    }
+
+    globals
+      .into_iter()
+      .map(|global| global.unwrap_or(Mat4::IDENTITY))
+      .collect()
+  }
+
+  ///
+  /// Get the skinning matrix of every joint from a pose. Same order as `joints`.
+  ///
+  /// Apply bone overrides to the pose before calling this.
+  ///
+  /// Note: The primitive vertices already have the mesh node transform baked in.
+  ///
+  pub fn skinning_matrices(&self, pose: &Pose) -> Vec<Mat4> {
+    let globals = self.global_transforms(pose);
+    self
+      .joints
+      .iter()
+      .zip(&self.inverse_bind_matrices)
+      .map(|(joint, inverse_bind)| match globals.get(*joint as usize) {
+        Some(global) => *global * *inverse_bind,
+        None => *inverse_bind,
+      })
+      .collect()
+  }
+}
@@ -0,0 +1,206 @@
+//! Wavefront (`.obj`) support.
+//!
+//! Every `usemtl` group becomes its own primitive, like the mesh buffers of minetest.
+//!
+//! **Note**: Texture coordinates are flipped on V so they match glTF (origin in the top left).
+
+use std::error::Error;
+
+use ahash::AHashMap;
+use glam::{Vec2, Vec3};
+
+use crate::{
+  minetest_gltf::MinetestGLTF,
+  primitive::{Mode, Vertex},
+  Model, Primitive,
+};
+
+///
+/// A primitive that is still being built up.
+///
+#[derive(Default)]
+struct ObjGroup {
+  #[cfg_attr(not(feature = "names"), allow(dead_code))]
+  name: Option<String>,
+  material: Option<usize>,
+  vertices: Vec<Vertex>,
+  indices: Vec<u32>,
+  // (position, tex coords, normal) -> index
+  lookup: AHashMap<(usize, Option<usize>, Option<usize>), u32>,
+  has_normals: bool,
+  has_tex_coords: bool,
+}
+
+///
+/// Turn an OBJ index (1 based, or negative from the end) into a 0 based index.
+///
+fn resolve_index(raw: &str, count: usize, line_number: usize) -> Result<usize, String> {
+  let index: i64 = match raw.parse() {
+    Ok(index) => index,
+    Err(_) => return Err(format!("OBJ: Bad index [{}] on line {}.", raw, line_number)),
+  };
+  let resolved = if index < 0 {
+    count as i64 + index
+  } else {
+    index - 1
+  };
+  if resolved < 0 || resolved as usize >= count {
+    return Err(format!(
+      "OBJ: Index [{}] out of range on line {}.",
+      raw, line_number
+    ));
+  }
+  Ok(resolved as usize)
+}
+
+fn parse_floats<const N: usize>(parts: &[&str], line_number: usize) -> Result<[f32; N], String> {
+  let mut values = [0.0; N];
+  for (i, value) in values.iter_mut().enumerate() {
+    *value = match parts.get(i).map(|part| part.parse::<f32>()) {
+      Some(Ok(parsed)) => parsed,
+      // Texture coordinates are allowed to leave out components.
+      None if i > 0 => 0.0,
+      _ => return Err(format!("OBJ: Bad number on line {}.", line_number)),
+    };
+  }
+  Ok(values)
+}
+
+///
+/// Read OBJ text into the same output as a glTF load.
+///
+/// Polygons are fanned out into triangles.
+///
+pub fn read_obj(text: &str, path: &str) -> Result<MinetestGLTF, Box<dyn Error + Send + Sync>> {
+  let mut positions: Vec<Vec3> = vec![];
+  let mut tex_coords: Vec<Vec2> = vec![];
+  let mut normals: Vec<Vec3> = vec![];
+
+  let mut groups: Vec<ObjGroup> = vec![ObjGroup::default()];
+  let mut object_name: Option<String> = None;
+  // Material name -> material index. In the order they first show up.
+  let mut materials: AHashMap<String, usize> = AHashMap::new();
+
+  for (line_number, line) in text.lines().enumerate() {
+    let line_number = line_number + 1;
+    let line = match line.find('#') {
+      Some(comment) => &line[..comment],
+      None => line,
+    };
+    let mut parts = line.split_whitespace();
+    let keyword = match parts.next() {
+      Some(keyword) => keyword,
+      None => continue,
+    };
+    let parts: Vec<&str> = parts.collect();
+
+    match keyword {
+      "v" => positions.push(Vec3::from_array(parse_floats::<3>(&parts, line_number)?)),
+      "vt" => {
+        let [u, v] = parse_floats::<2>(&parts, line_number)?;
+        tex_coords.push(Vec2::new(u, 1.0 - v));
+      }
+      "vn" => {
+        normals.push(Vec3::from_array(parse_floats::<3>(&parts, line_number)?).normalize_or_zero())
+      }
+      "o" | "g" => object_name = parts.first().map(|name| name.to_string()),
+      "usemtl" => {
+        // * A new material is a new primitive. Reuse the current one if it's still empty.
+        let needs_new = groups.last().is_none_or(|group| !group.indices.is_empty());
+        if needs_new {
+          groups.push(ObjGroup::default());
+        }
+        let material_count = materials.len();
+        let material = parts
+          .first()
+          .map(|name| *materials.entry(name.to_string()).or_insert(material_count));
+        if let Some(group) = groups.last_mut() {
+          group.name = object_name.clone();
+          group.material = material;
+        }
+      }
+      "f" => {
+        let group = match groups.last_mut() {
+          Some(group) => group,
+          None => continue,
+        };
+        if group.name.is_none() {
+          group.name = object_name.clone();
+        }
+
+        let mut polygon = vec![];
+        for corner in &parts {
+          let mut indices = corner.split('/');
+          let position = match indices.next() {
+            Some(raw) => resolve_index(raw, positions.len(), line_number)?,
+            None => {
+              return Err(format!("OBJ: Face without a position on line {}.", line_number).into())
+            }
+          };
+          let tex_coord = match indices.next() {
+            Some(raw) if !raw.is_empty() => {
+              Some(resolve_index(raw, tex_coords.len(), line_number)?)
+            }
+            _ => None,
+          };
+          let normal = match indices.next() {
+            Some(raw) if !raw.is_empty() => Some(resolve_index(raw, normals.len(), line_number)?),
+            _ => None,
+          };
+
+          let key = (position, tex_coord, normal);
+          let index = match group.lookup.get(&key) {
+            Some(index) => *index,
+            None => {
+              group.vertices.push(Vertex {
+                position: positions[position],
+                normal: normal.map_or(Vec3::ZERO, |normal| normals[normal]),
+                tex_coords: tex_coord.map_or(Vec2::ZERO, |tex_coord| tex_coords[tex_coord]),
+                ..Default::default()
+              });
+              let index = (group.vertices.len() - 1) as u32;
+              group.lookup.insert(key, index);
+              index
+            }
+          };
+          group.has_normals |= normal.is_some();
+          group.has_tex_coords |= tex_coord.is_some();
+          polygon.push(index);
+        }
+
+        for i in 1..polygon.len().saturating_sub(1) {
+          group
+            .indices
+            .extend_from_slice(&[polygon[0], polygon[i], polygon[i + 1]]);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  let primitives: Vec<Primitive> = groups
+    .into_iter()
+    .filter(|group| !group.indices.is_empty())
+    .enumerate()
+    .map(|(primitive_index, group)| Primitive {
+      #[cfg(feature = "names")]
+      mesh_name: group.name,
+      primitive_index,
+      vertices: group.vertices,
+      indices: Some(group.indices),
+      mode: Mode::Triangles,
+      has_normals: group.has_normals,
+      has_tex_coords: group.has_tex_coords,
+      material: group.material,
+      ..Default::default()
+    })
+    .collect();
+
+  if primitives.is_empty() {
+    return Err(format!("OBJ: Model has no primitives! {}", path).into());
+  }
+
+  let mut minetest_gltf = MinetestGLTF::new(vec![], path);
+  minetest_gltf.model = Some(Model::from_primitives(primitives));
+  Ok(minetest_gltf)
+}